@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::error::AppError;
+use crate::models::*;
+use crate::utils::write_file_atomic;
+
+/// Prefix that tells API tokens apart from interactive JWTs
+pub const API_TOKEN_PREFIX: &str = "pat_";
+
+/// Role carried by the claims of requests authenticated with an API token
+pub const API_TOKEN_ROLE: &str = "api";
+
+/// Scopes an API token can be granted
+pub const API_TOKEN_SCOPES: &[&str] = &["content:read", "content:write", "content:delete", "github:refresh"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApiTokenRecord {
+    #[serde(flatten)]
+    token: ApiToken,
+    token_hash: String,
+}
+
+/// Long-lived, scoped tokens for CI and scripts, persisted with hashed secrets
+pub struct ApiTokenStore {
+    path: Option<PathBuf>,
+    tokens: Mutex<Vec<ApiTokenRecord>>,
+}
+
+fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+impl ApiTokenStore {
+    /// Load API tokens from `path`, starting empty if it does not exist yet
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let tokens = if Path::new(path).exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(ApiTokenStore {
+            path: Some(PathBuf::from(path)),
+            tokens: Mutex::new(tokens),
+        })
+    }
+
+    /// An API token store that is never written to disk
+    pub fn in_memory() -> Self {
+        ApiTokenStore {
+            path: None,
+            tokens: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn persist(&self, tokens: &[ApiTokenRecord]) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_file_atomic(&path.to_string_lossy(), serde_json::to_string_pretty(tokens)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Create a token, returning its secret (shown only once) and its metadata
+    pub fn create(
+        &self,
+        name: &str,
+        scopes: &[String],
+        created_by: &str,
+        expires_in_days: Option<i64>,
+    ) -> Result<(String, ApiToken), Box<dyn std::error::Error>> {
+        let name = name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Err(AppError::BadRequest("Token name must be between 1 and 100 characters".to_string()).into());
+        }
+        if scopes.is_empty() {
+            return Err(AppError::BadRequest("At least one scope is required".to_string()).into());
+        }
+        if let Some(scope) = scopes.iter().find(|scope| !API_TOKEN_SCOPES.contains(&scope.as_str())) {
+            return Err(AppError::BadRequest(format!("Unknown scope '{}', expected one of {}", scope, API_TOKEN_SCOPES.join(", "))).into());
+        }
+        if expires_in_days.is_some_and(|days| days <= 0) {
+            return Err(AppError::BadRequest("expires_in_days must be positive".to_string()).into());
+        }
+
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens.iter().any(|record| record.token.name == name) {
+            return Err(AppError::Conflict(format!("A token named '{}' already exists", name)).into());
+        }
+
+        let secret = format!(
+            "{}{}{}",
+            API_TOKEN_PREFIX,
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let now = Utc::now();
+        let mut scopes = scopes.to_vec();
+        scopes.sort();
+        scopes.dedup();
+
+        let token = ApiToken {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            scopes,
+            created_by: created_by.to_string(),
+            created_at: now,
+            expires_at: expires_in_days.map(|days| now + chrono::Duration::days(days)),
+        };
+        tokens.push(ApiTokenRecord {
+            token: token.clone(),
+            token_hash: hash_api_token(&secret),
+        });
+        self.persist(&tokens)?;
+
+        Ok((secret, token))
+    }
+
+    /// All tokens, oldest first
+    pub fn list(&self) -> Vec<ApiToken> {
+        self.tokens.lock().unwrap().iter().map(|record| record.token.clone()).collect()
+    }
+
+    /// Revoke a token by id
+    pub fn revoke(&self, id: &str) -> Result<ApiToken, Box<dyn std::error::Error>> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let index = tokens
+            .iter()
+            .position(|record| record.token.id == id)
+            .ok_or_else(|| AppError::NotFound(format!("API token '{}' not found", id)))?;
+        let record = tokens.remove(index);
+        self.persist(&tokens)?;
+        Ok(record.token)
+    }
+
+    /// Verify a presented token, returning claims carrying its scopes
+    pub fn verify(&self, secret: &str) -> Result<Claims, Box<dyn std::error::Error>> {
+        let token_hash = hash_api_token(secret);
+        let tokens = self.tokens.lock().unwrap();
+        let token = &tokens
+            .iter()
+            .find(|record| record.token_hash == token_hash)
+            .ok_or("Unknown or revoked API token")?
+            .token;
+
+        if token.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err("API token expired".into());
+        }
+
+        Ok(Claims {
+            sub: format!("token:{}", token.name),
+            exp: token.expires_at.map(|expires_at| expires_at.timestamp()).unwrap_or(i64::MAX),
+            iat: token.created_at.timestamp(),
+            role: API_TOKEN_ROLE.to_string(),
+            jti: token.id.clone(),
+            iat_micros: token.created_at.timestamp_micros(),
+            scopes: token.scopes.clone(),
+            csrf: String::new(),
+        })
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use crate::metrics::METRICS;
+use crate::models::*;
+use crate::permissions::authenticated_claims;
+use crate::utils::client_ip;
+use crate::AppConfig;
+
+/// Append-only log of admin actions and login attempts, one JSON event per line
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    /// Serializes appends; also holds the events of an in-memory log
+    events: Mutex<Vec<AuditEvent>>,
+}
+
+impl AuditLog {
+    /// Audit log appending to the file at `path`
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(AuditLog {
+            path: Some(path),
+            events: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// An audit log that is never written to disk
+    pub fn in_memory() -> Self {
+        AuditLog {
+            path: None,
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Append an event, syncing it to disk
+    ///
+    /// Blocks on the file system; async callers run it on the blocking pool.
+    pub fn append(&self, event: AuditEvent) -> Result<(), std::io::Error> {
+        let mut events = self.events.lock().unwrap();
+
+        match &self.path {
+            Some(path) => {
+                let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", serde_json::to_string(&event)?)?;
+                file.sync_data()?;
+            }
+            None => events.push(event),
+        }
+        Ok(())
+    }
+
+    /// Events matching the query, newest first
+    ///
+    /// The log is scanned from its end and the scan stops once `limit` events
+    /// match or events get older than `since`, so queries for recent events stay
+    /// cheap however long the log grows.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEvent>, std::io::Error> {
+        let limit = query.limit.unwrap_or(100).min(MAX_QUERY_LIMIT);
+        let mut matched = Vec::new();
+        let mut visit = |event: AuditEvent| {
+            if query.since.is_some_and(|since| event.timestamp < since) {
+                return false;
+            }
+            if query.matches(&event) {
+                matched.push(event);
+            }
+            matched.len() < limit
+        };
+
+        let events = self.events.lock().unwrap();
+        match &self.path {
+            Some(path) if path.exists() => visit_lines_from_end(path, |line| match serde_json::from_str(line) {
+                Ok(event) => visit(event),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Skipping unreadable audit event");
+                    true
+                }
+            })?,
+            Some(_) => {}
+            None => {
+                for event in events.iter().rev() {
+                    if !visit(event.clone()) {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(matched)
+    }
+}
+
+/// Most events a single audit query returns
+const MAX_QUERY_LIMIT: usize = 1000;
+
+impl AuditQuery {
+    /// Whether an event passes every filter except `since` and `limit`
+    fn matches(&self, event: &AuditEvent) -> bool {
+        self.user.as_ref().is_none_or(|user| &event.user == user)
+            && self.action.as_ref().is_none_or(|action| &event.action == action)
+            && self.result.as_ref().is_none_or(|result| &event.result == result)
+            && self.path.as_ref().is_none_or(|path| {
+                event.path.as_deref().is_some_and(|p| p.starts_with(path.as_str()))
+                    || event.target_path.as_deref().is_some_and(|p| p.starts_with(path.as_str()))
+            })
+    }
+}
+
+/// Call `visit` with every non-empty line of a file, last line first, until it returns false
+fn visit_lines_from_end(path: &Path, mut visit: impl FnMut(&str) -> bool) -> Result<(), std::io::Error> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    let mut file = fs::File::open(path)?;
+    let mut position = file.metadata()?.len();
+    // The start of the line that straddles the previous chunk boundary
+    let mut partial = Vec::new();
+    let mut visit_line = |line: &[u8]| {
+        let line = String::from_utf8_lossy(line);
+        line.trim().is_empty() || visit(&line)
+    };
+
+    while position > 0 {
+        let size = CHUNK_SIZE.min(position);
+        position -= size;
+        let mut chunk = vec![0; size as usize];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&partial);
+
+        let mut end = chunk.len();
+        while let Some(newline) = chunk[..end].iter().rposition(|&byte| byte == b'\n') {
+            if !visit_line(&chunk[newline + 1..end]) {
+                return Ok(());
+            }
+            end = newline;
+        }
+        chunk.truncate(end);
+        partial = chunk;
+    }
+
+    visit_line(&partial);
+    Ok(())
+}
+
+/// Classify a response status as an audit result
+fn audit_result(status: actix_web::http::StatusCode) -> AuditResult {
+    if status.is_success() {
+        AuditResult::Success
+    } else if matches!(status.as_u16(), 401 | 403 | 428 | 429) {
+        AuditResult::Denied
+    } else {
+        AuditResult::Failure
+    }
+}
+
+/// Who and what an audited request acts on
+#[derive(Debug, Default)]
+pub struct AuditSubject {
+    /// Defaults to the authenticated caller (`Claims.sub`); logins set the attempted username
+    pub user: Option<String>,
+    pub path: Option<String>,
+    pub target_path: Option<String>,
+}
+
+impl AuditSubject {
+    pub fn user(user: &str) -> Self {
+        AuditSubject { user: Some(user.to_string()), ..Default::default() }
+    }
+
+    pub fn path(path: &str) -> Self {
+        AuditSubject { path: Some(path.to_string()), ..Default::default() }
+    }
+
+    pub fn paths(path: &str, target_path: &str) -> Self {
+        AuditSubject {
+            path: Some(path.to_string()),
+            target_path: Some(target_path.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn target(target_path: Option<&str>) -> Self {
+        AuditSubject { target_path: target_path.map(str::to_string), ..Default::default() }
+    }
+}
+
+/// Run an admin handler, then count and audit its outcome whether it succeeded or not
+///
+/// Errors are turned into their response here, so the audit event carries the
+/// status the client actually got.
+pub async fn audited(
+    req: HttpRequest,
+    action: &str,
+    subject: AuditSubject,
+    handler: impl Future<Output = Result<HttpResponse>>,
+) -> Result<HttpResponse> {
+    let response = handler.await.unwrap_or_else(|e| e.error_response());
+    let result = audit_result(response.status());
+    METRICS.record_admin_operation(action, &result);
+    record_audit(&req, action, subject, result, &response).await;
+    Ok(response)
+}
+
+/// Append the outcome of a request, if an audit log is registered with the app
+///
+/// The synced append runs on the blocking pool so it never stalls the worker.
+async fn record_audit(
+    req: &HttpRequest,
+    action: &str,
+    subject: AuditSubject,
+    result: AuditResult,
+    response: &HttpResponse,
+) {
+    let audit_log = match req.app_data::<web::Data<AuditLog>>() {
+        Some(audit_log) => audit_log.clone(),
+        None => return,
+    };
+
+    let user = match subject.user {
+        Some(user) => user,
+        None => authenticated_claims(req)
+            .map(|claims| claims.sub)
+            .unwrap_or_else(|_| "anonymous".to_string()),
+    };
+    let trust_proxy = req
+        .app_data::<web::Data<AppConfig>>()
+        .is_some_and(|config| config.trust_proxy);
+
+    let event = AuditEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp: Utc::now(),
+        user,
+        ip: client_ip(req, trust_proxy),
+        action: action.to_string(),
+        path: subject.path,
+        target_path: subject.target_path,
+        result,
+        status: response.status().as_u16(),
+    };
+
+    match web::block(move || audit_log.append(event)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!(error = %e, "Failed to write audit event"),
+        Err(e) => tracing::error!(error = %e, "Audit write task failed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn event(index: usize) -> AuditEvent {
+        AuditEvent {
+            id: index.to_string(),
+            timestamp: chrono::DateTime::from_timestamp(1_700_000_000 + index as i64, 0).unwrap(),
+            user: "admin".to_string(),
+            ip: "127.0.0.1".to_string(),
+            action: if index.is_multiple_of(2) { "save" } else { "delete" }.to_string(),
+            path: Some(format!("blog/post-{}.md", index)),
+            target_path: None,
+            result: AuditResult::Success,
+            status: 200,
+        }
+    }
+
+    #[test]
+    fn queries_read_the_newest_events_from_the_end_of_the_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let log_path = format!("{}/audit.log", dir);
+        let log = AuditLog::open(&log_path).unwrap();
+        // Enough events to span several read chunks, plus a torn line
+        let mut lines: String = (0..2000).map(|index| serde_json::to_string(&event(index)).unwrap() + "\n").collect();
+        lines.insert_str(0, "{\"torn\n");
+        fs::write(&log_path, lines).unwrap();
+
+        let ids = |query: AuditQuery| log.query(&query).unwrap().into_iter().map(|event| event.id).collect::<Vec<_>>();
+        assert_eq!(ids(AuditQuery { limit: Some(3), ..Default::default() }), vec!["1999", "1998", "1997"]);
+        assert_eq!(
+            ids(AuditQuery { action: Some("delete".to_string()), limit: Some(2), ..Default::default() }),
+            vec!["1999", "1997"],
+        );
+        let since = event(1995).timestamp;
+        assert_eq!(ids(AuditQuery { since: Some(since), ..Default::default() }).len(), 5);
+        assert_eq!(ids(AuditQuery { limit: Some(5000), ..Default::default() }).len(), MAX_QUERY_LIMIT);
+        assert_eq!(ids(AuditQuery { path: Some("blog/post-0.md".to_string()), limit: Some(5000), ..Default::default() }), vec!["0"]);
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing::error;
+use crate::config::*;
+use crate::export::export_site;
+use crate::logging::init_logging;
+use crate::models::*;
+use crate::utils::*;
+
+/// Portfolio backend: the API server plus content tools that work without the admin UI
+#[derive(Debug, Parser)]
+#[command(name = "portfolio-backend", version)]
+pub struct Cli {
+    /// TOML config file layered under environment variables
+    #[arg(long, global = true, env = "CONFIG_FILE")]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default)
+    Serve,
+    /// Scaffold a markdown file with frontmatter
+    New {
+        kind: ContentKind,
+        /// Title of the new post or project; the file name is derived from it
+        #[arg(required = true, num_args = 1..)]
+        title: Vec<String>,
+    },
+    /// Check every content file parses and has valid metadata
+    Lint,
+    /// Export the public site to static files for a plain static host
+    Build {
+        /// Output directory
+        #[arg(long, default_value = "dist")]
+        out: PathBuf,
+        /// Built frontend to copy into the output [default: FRONTEND_PATH]
+        #[arg(long)]
+        frontend: Option<PathBuf>,
+        /// Public URL of the site, used in feeds and the sitemap [default: FRONTEND_URL]
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+    /// Print an Argon2id hash for a password read from stdin
+    HashPassword,
+    /// GitHub project cache
+    Github {
+        #[command(subcommand)]
+        command: GithubCommand,
+    },
+    /// Persisted caches
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ContentKind {
+    /// A blog post
+    Post,
+    /// A project
+    Project,
+}
+
+impl ContentKind {
+    /// Content category the kind is stored in
+    pub fn category(self) -> &'static str {
+        match self {
+            ContentKind::Post => "blog",
+            ContentKind::Project => "project",
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GithubCommand {
+    /// Re-fetch every configured repository, ignoring the cache TTL
+    Refresh,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Fetch repositories missing from the GitHub cache or expired
+    Warm,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective configuration with secrets redacted, then validate it
+    Check,
+}
+
+/// Load the configuration for a content or cache command, then start logging in
+/// its format
+///
+/// Only `serve` validates the whole configuration: these commands run offline
+/// and should not need production credentials or JWT keys to be set.
+pub fn load_config(config_file: Option<&str>) -> std::io::Result<AppConfig> {
+    let config = AppConfig::read(config_file).map_err(|e| {
+        eprintln!("Configuration error: {}", e);
+        Error::new(ErrorKind::InvalidInput, e)
+    })?;
+    init_logging(config.log_format);
+    Ok(config)
+}
+
+/// Load and validate the configuration for the server, then start logging in its format
+pub fn load_server_config(config_file: Option<&str>) -> std::io::Result<AppConfig> {
+    let config = AppConfig::load(config_file).map_err(|e| {
+        eprintln!("Configuration error: {}", e);
+        Error::new(ErrorKind::InvalidInput, e)
+    })?;
+    init_logging(config.log_format);
+    Ok(config)
+}
+
+/// Path of the persisted GitHub cache
+pub fn github_cache_path(config: &AppConfig) -> String {
+    format!("{}/{}", config.data_path, GITHUB_CACHE_FILE)
+}
+
+fn load_persisted_github_cache(config: &AppConfig) -> std::io::Result<Mutex<HashMap<String, CachedGithubProject>>> {
+    let path = github_cache_path(config);
+    let cache = load_github_cache(&path).map_err(|e| {
+        error!(path = %path, error = %e, "Failed to load GitHub cache");
+        Error::new(ErrorKind::InvalidData, e.to_string())
+    })?;
+    Ok(Mutex::new(cache))
+}
+
+fn save_persisted_github_cache(config: &AppConfig, cache: &Mutex<HashMap<String, CachedGithubProject>>) -> std::io::Result<()> {
+    let path = github_cache_path(config);
+    save_github_cache(&path, cache).map_err(|e| {
+        error!(path = %path, error = %e, "Failed to save GitHub cache");
+        Error::other(e.to_string())
+    })
+}
+
+/// Print an Argon2id PHC hash for a password read from stdin
+///
+/// There is deliberately no argument for the password, so it never ends up in
+/// shell history or the process list.
+pub fn hash_password_command() -> std::io::Result<()> {
+    // Typed passwords are read without echo; piped ones come from plain stdin
+    let line = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password("Password: ")?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line
+    };
+    let password = line.trim_end_matches(['\r', '\n']);
+
+    if password.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Password cannot be empty"));
+    }
+
+    let hash = hash_password(password)
+        .map_err(|e| Error::other(e.to_string()))?;
+    println!("{}", hash);
+    Ok(())
+}
+
+/// Print the effective configuration with secrets redacted, then validate it
+pub fn config_check_command(config_file: Option<&str>) -> std::io::Result<()> {
+    let config = AppConfig::read(config_file).map_err(|e| {
+        eprintln!("Configuration error: {}", e);
+        Error::new(ErrorKind::InvalidInput, e)
+    })?;
+
+    print!("{}", config.to_redacted_toml().map_err(Error::other)?);
+    config.validate().map_err(|e| {
+        eprintln!("Configuration error: {}", e);
+        Error::new(ErrorKind::InvalidInput, e)
+    })?;
+    eprintln!("Configuration OK");
+    Ok(())
+}
+
+/// Scaffold a new post or project dated today
+pub fn new_content_command(config: &AppConfig, kind: ContentKind, title: &[String]) -> std::io::Result<()> {
+    let category = kind.category();
+    validate_category(category, &config.allowed_categories)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+
+    let today = chrono::Local::now().date_naive();
+    let path = scaffold_content(&config.content_path, category, &title.join(" "), today)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+    println!("Created {}", path);
+    Ok(())
+}
+
+/// Report content problems, failing if there are any
+pub fn lint_command(config: &AppConfig) -> std::io::Result<()> {
+    let issues = lint_content(config);
+    for issue in &issues {
+        println!("{}: {}", issue.path, issue.message);
+    }
+
+    if issues.is_empty() {
+        eprintln!("No problems found");
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, format!("{} problem(s) found", issues.len())))
+    }
+}
+
+/// Export the public site to static files under `out`
+pub async fn build_command(
+    config: &AppConfig,
+    out: &std::path::Path,
+    frontend: Option<PathBuf>,
+    base_url: Option<String>,
+) -> std::io::Result<()> {
+    let frontend = frontend.unwrap_or_else(|| PathBuf::from(&config.frontend_path));
+    let base_url = base_url.unwrap_or_else(|| config.frontend_url.clone());
+    let github_cache = load_persisted_github_cache(config)?;
+    let written = export_site(config, &frontend, out, &base_url, &github_cache)
+        .await
+        .map_err(|e| Error::other(e.to_string()))?;
+    // Keep projects fetched for the export for the next build and the server
+    save_persisted_github_cache(config, &github_cache)?;
+    println!("Wrote {} file(s) to {}", written, out.display());
+    Ok(())
+}
+
+/// Re-fetch every configured GitHub repository into the persisted cache
+pub async fn github_refresh_command(config: &AppConfig) -> std::io::Result<()> {
+    let github_cache = load_persisted_github_cache(config)?;
+    let projects = load_github_projects(config, &github_cache, true)
+        .await
+        .map_err(|e| Error::other(e.to_string()))?;
+    save_persisted_github_cache(config, &github_cache)?;
+    println!("Refreshed {} GitHub project(s)", projects.len());
+    Ok(())
+}
+
+/// Fetch GitHub repositories missing from the persisted cache or expired
+pub async fn cache_warm_command(config: &AppConfig) -> std::io::Result<()> {
+    let github_cache = load_persisted_github_cache(config)?;
+    let cached = warm_github_cache(config, &github_cache)
+        .await
+        .map_err(|e| Error::other(e.to_string()))?;
+    save_persisted_github_cache(config, &github_cache)?;
+    println!("GitHub cache holds {} repositories", cached);
+    Ok(())
+}
@@ -0,0 +1,698 @@
+use std::env;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::logging::LogFormat;
+use crate::history::HISTORY_DIR;
+
+/// Development-only JWT signing secret, refused in production mode
+pub const DEFAULT_JWT_SECRET: &str = "your_super_secret_jwt_key_change_this_in_production";
+
+/// Development-only key for encrypting stored TOTP secrets, refused in production mode
+pub const DEFAULT_TWO_FACTOR_KEY: &str = "development_two_factor_key_change_this_in_production";
+
+/// Content categories served when none are configured
+pub const DEFAULT_CATEGORIES: &[&str] = &["project", "blog", "page"];
+
+/// Placeholder printed instead of secrets by `config check`
+pub const REDACTED: &str = "[redacted]";
+
+/// A JWT signing key identified by the `kid` header of the tokens it signs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtKey {
+    pub kid: String,
+    pub secret: String,
+}
+
+#[derive(Clone)]
+pub struct AppConfig {
+    pub host: String,
+    pub port: u16,
+    pub content_path: String,
+    pub frontend_path: String,
+    pub frontend_url: String,
+    /// Origins allowed to call the API cross-origin, with credentials
+    pub cors_allowed_origins: Vec<String>,
+    /// Seconds browsers may cache a preflight response
+    pub cors_max_age: usize,
+    pub trash_path: String,
+    pub trash_retention_days: i64,
+    /// Directory for server state such as revoked tokens, kept outside the content
+    pub data_path: String,
+    pub production: bool,
+    /// Take the client IP from the X-Real-IP header set by the reverse proxy
+    pub trust_proxy: bool,
+    /// Log lines as text or JSON
+    pub log_format: LogFormat,
+    /// Also deliver access tokens as HttpOnly cookies, guarded by CSRF tokens
+    pub cookie_sessions: bool,
+    /// Seconds of Strict-Transport-Security; 0 sends no HSTS header
+    pub hsts_max_age: u64,
+    /// Send the Content-Security-Policy as report-only while rolling out changes
+    pub csp_report_only: bool,
+    /// Origins pages may connect to besides their own, e.g. a separate API host
+    pub csp_connect_sources: Vec<String>,
+    /// Bearer token required to scrape `/metrics`; unset leaves it open, which
+    /// only development allows
+    pub metrics_token: Option<String>,
+    /// JWT keys; the first one signs new tokens, all of them verify
+    pub jwt_keys: Vec<JwtKey>,
+    /// Key the TOTP secrets in `two_factor.json` are encrypted with
+    pub two_factor_key: String,
+    /// Lifetime of access tokens
+    pub access_token_minutes: i64,
+    /// Lifetime of refresh tokens, i.e. how long a login lasts without activity
+    pub refresh_token_days: i64,
+    /// Content categories that may be listed, read and edited
+    pub allowed_categories: Vec<String>,
+    /// Seconds rendered content items stay cached
+    pub content_cache_ttl_seconds: i64,
+    /// Seconds GitHub projects stay cached
+    pub github_cache_ttl_seconds: i64,
+    /// Base URL of the GitHub REST API
+    pub github_api_url: String,
+    /// Token sent to the GitHub API for higher rate limits
+    pub github_token: Option<String>,
+    /// YAML file listing users with their roles and password hashes
+    pub users_file: Option<String>,
+    /// Username of the single admin when no users file is configured
+    pub admin_username: String,
+    /// Argon2 PHC string of the admin password
+    pub admin_password_hash: Option<String>,
+    /// Plaintext admin password, a development-only fallback for a missing hash
+    pub admin_password: Option<String>,
+}
+
+/// The TOML config file; every key is optional and environment variables win
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub server: ServerSection,
+    pub paths: PathsSection,
+    pub cors: CorsSection,
+    pub security: SecuritySection,
+    pub auth: AuthSection,
+    pub content: ContentSection,
+    pub github: GithubSection,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub production: Option<bool>,
+    pub trust_proxy: Option<bool>,
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsSection {
+    pub content: Option<String>,
+    pub frontend: Option<String>,
+    pub trash: Option<String>,
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsSection {
+    pub frontend_url: Option<String>,
+    pub allowed_origins: Option<Vec<String>>,
+    pub max_age: Option<usize>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecuritySection {
+    pub hsts_max_age: Option<u64>,
+    pub csp_report_only: Option<bool>,
+    pub csp_connect_sources: Option<Vec<String>>,
+    pub metrics_token: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSection {
+    pub jwt_secret: Option<String>,
+    pub jwt_keys: Option<Vec<JwtKey>>,
+    pub two_factor_key: Option<String>,
+    pub access_token_minutes: Option<i64>,
+    pub refresh_token_days: Option<i64>,
+    pub cookie_sessions: Option<bool>,
+    pub users_file: Option<String>,
+    pub admin_username: Option<String>,
+    pub admin_password_hash: Option<String>,
+    pub admin_password: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContentSection {
+    pub allowed_categories: Option<Vec<String>>,
+    pub cache_ttl_seconds: Option<i64>,
+    pub trash_retention_days: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GithubSection {
+    pub api_url: Option<String>,
+    pub token: Option<String>,
+    pub cache_ttl_seconds: Option<i64>,
+}
+
+impl ConfigFile {
+    /// Read and parse the config file at `path`
+    pub fn read(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config file {}: {}", path, e))?;
+        Self::parse(&content).map_err(|e| format!("Invalid config file {}: {}", path, e))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+}
+
+/// Looks up an environment variable; tests pass their own instead of the process environment
+pub type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// The process environment
+pub fn process_env(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// A setting from the environment variable `name`, else the config file, else `default`
+fn setting<T: FromStr>(env: Env, name: &str, file_value: Option<T>, default: T) -> Result<T, String> {
+    match env(name) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid {} value '{}'", name, value)),
+        None => Ok(file_value.unwrap_or(default)),
+    }
+}
+
+/// A boolean setting; the environment variable is on when "true" or "1"
+fn flag(env: Env, name: &str, file_value: Option<bool>, default: bool) -> bool {
+    match env(name) {
+        Some(value) => value == "true" || value == "1",
+        None => file_value.unwrap_or(default),
+    }
+}
+
+/// A list setting; the environment variable is split on `separator`
+fn list(env: Env, name: &str, separator: char, file_value: Option<Vec<String>>, default: Vec<String>) -> Vec<String> {
+    match env(name) {
+        Some(value) => value
+            .split(separator)
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+        None => file_value.unwrap_or(default),
+    }
+}
+
+impl AppConfig {
+    /// Directory holding the revision history of the content
+    pub fn history_path(&self) -> String {
+        format!("{}/{}", self.data_path, HISTORY_DIR)
+    }
+
+    /// Configuration from environment variables only
+    pub fn from_env() -> Result<Self, String> {
+        Self::load(None)
+    }
+
+    /// Configuration from the optional TOML file at `config_file`, overridden by
+    /// environment variables, and validated
+    pub fn load(config_file: Option<&str>) -> Result<Self, String> {
+        let config = Self::read(config_file)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Configuration from the optional TOML file at `config_file`, overridden by
+    /// environment variables, without validating it
+    pub fn read(config_file: Option<&str>) -> Result<Self, String> {
+        let file = match config_file {
+            Some(path) => ConfigFile::read(path)?,
+            None => ConfigFile::default(),
+        };
+        Self::from_sources(&file, &process_env)
+    }
+
+    /// Layer the variables from `env` over the config file and built-in defaults
+    pub fn from_sources(file: &ConfigFile, env: Env) -> Result<Self, String> {
+        let production = match env("APP_ENV") {
+            Some(app_env) => app_env == "production",
+            None => file.server.production.unwrap_or(false),
+        };
+        let frontend_url = setting(env, "FRONTEND_URL", file.cors.frontend_url.clone(), "http://localhost:3000".to_string())?;
+        let cors_allowed_origins = list(
+            env,
+            "CORS_ALLOWED_ORIGINS",
+            ',',
+            file.cors.allowed_origins.clone(),
+            vec![frontend_url.clone()],
+        )
+        .into_iter()
+        .map(|origin| origin.trim_end_matches('/').to_string())
+        .collect();
+
+        Ok(AppConfig {
+            host: setting(env, "HOST", file.server.host.clone(), "127.0.0.1".to_string())?,
+            port: setting(env, "PORT", file.server.port, 4000)?,
+            content_path: setting(env, "CONTENT_PATH", file.paths.content.clone(), "../content".to_string())?,
+            frontend_path: setting(env, "FRONTEND_PATH", file.paths.frontend.clone(), "../frontend".to_string())?,
+            frontend_url,
+            cors_allowed_origins,
+            cors_max_age: setting(env, "CORS_MAX_AGE", file.cors.max_age, 3600)?,
+            trash_path: setting(env, "TRASH_PATH", file.paths.trash.clone(), "../trash".to_string())?,
+            trash_retention_days: setting(env, "TRASH_RETENTION_DAYS", file.content.trash_retention_days, 30)?,
+            data_path: setting(env, "DATA_PATH", file.paths.data.clone(), "../data".to_string())?,
+            production,
+            trust_proxy: flag(env, "TRUST_PROXY", file.server.trust_proxy, false),
+            log_format: setting(env, "LOG_FORMAT", file.server.log_format, LogFormat::Text)?,
+            cookie_sessions: flag(env, "SESSION_COOKIES", file.auth.cookie_sessions, false),
+            hsts_max_age: setting(env, "HSTS_MAX_AGE", file.security.hsts_max_age, if production { 31_536_000 } else { 0 })?,
+            csp_report_only: flag(env, "CSP_REPORT_ONLY", file.security.csp_report_only, false),
+            csp_connect_sources: list(env, "CSP_CONNECT_SRC", ' ', file.security.csp_connect_sources.clone(), Vec::new()),
+            metrics_token: env("METRICS_TOKEN").or_else(|| file.security.metrics_token.clone()).filter(|token| !token.is_empty()),
+            jwt_keys: Self::jwt_keys_from_sources(&file.auth, env)?,
+            two_factor_key: setting(env, "TWO_FACTOR_KEY", file.auth.two_factor_key.clone(), DEFAULT_TWO_FACTOR_KEY.to_string())?,
+            access_token_minutes: setting(env, "ACCESS_TOKEN_MINUTES", file.auth.access_token_minutes, crate::utils::ACCESS_TOKEN_EXPIRATION_MINUTES)?,
+            refresh_token_days: setting(env, "REFRESH_TOKEN_DAYS", file.auth.refresh_token_days, crate::sessions::REFRESH_TOKEN_EXPIRATION_DAYS)?,
+            allowed_categories: list(
+                env,
+                "ALLOWED_CATEGORIES",
+                ',',
+                file.content.allowed_categories.clone(),
+                DEFAULT_CATEGORIES.iter().map(|category| category.to_string()).collect(),
+            ),
+            content_cache_ttl_seconds: setting(env, "CONTENT_CACHE_TTL", file.content.cache_ttl_seconds, 3600)?,
+            github_cache_ttl_seconds: setting(env, "GITHUB_CACHE_TTL", file.github.cache_ttl_seconds, 24 * 3600)?,
+            github_api_url: setting(env, "GITHUB_API_URL", file.github.api_url.clone(), "https://api.github.com".to_string())?
+                .trim_end_matches('/')
+                .to_string(),
+            github_token: env("GITHUB_TOKEN").or_else(|| file.github.token.clone()).filter(|token| !token.is_empty()),
+            users_file: env("USERS_FILE").or_else(|| file.auth.users_file.clone()).filter(|path| !path.is_empty()),
+            admin_username: setting(env, "ADMIN_USERNAME", file.auth.admin_username.clone(), "admin".to_string())?,
+            admin_password_hash: env("ADMIN_PASSWORD_HASH").or_else(|| file.auth.admin_password_hash.clone()).filter(|hash| !hash.is_empty()),
+            admin_password: env("ADMIN_PASSWORD").or_else(|| file.auth.admin_password.clone()).filter(|password| !password.is_empty()),
+        })
+    }
+
+    /// JWT keys from `JWT_KEYS` ("kid:secret,kid:secret", active key first) or a
+    /// single `JWT_SECRET`, else from the config file, else the development default
+    fn jwt_keys_from_sources(auth: &AuthSection, env: Env) -> Result<Vec<JwtKey>, String> {
+        let jwt_keys = match (env("JWT_KEYS"), env("JWT_SECRET")) {
+            (Some(keys), _) => keys
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(|entry| {
+                    let (kid, secret) = entry
+                        .trim()
+                        .split_once(':')
+                        .ok_or("Invalid JWT_KEYS value, expected kid:secret pairs")?;
+                    Ok(JwtKey { kid: kid.to_string(), secret: secret.to_string() })
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            (None, Some(secret)) => vec![JwtKey { kid: "primary".to_string(), secret }],
+            (None, None) => match (&auth.jwt_keys, &auth.jwt_secret) {
+                (Some(keys), _) => keys.clone(),
+                (None, Some(secret)) => vec![JwtKey { kid: "primary".to_string(), secret: secret.clone() }],
+                (None, None) => vec![JwtKey { kid: "default".to_string(), secret: DEFAULT_JWT_SECRET.to_string() }],
+            },
+        };
+        Ok(jwt_keys)
+    }
+
+    /// Check the configuration is usable, reporting every problem at once
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        if self.port == 0 {
+            problems.push("port must be between 1 and 65535".to_string());
+        }
+        if !std::path::Path::new(&self.content_path).is_dir() {
+            problems.push(format!("content path '{}' is not a directory", self.content_path));
+        }
+        for origin in &self.cors_allowed_origins {
+            if origin == "*" {
+                problems.push("CORS allowed origins cannot contain '*' because credentials are allowed".to_string());
+            } else if !(origin.starts_with("http://") || origin.starts_with("https://"))
+                || origin.splitn(4, '/').nth(3).is_some()
+            {
+                problems.push(format!("CORS origin '{}' must be a scheme and host, e.g. https://example.com", origin));
+            }
+        }
+        if self.trash_retention_days < 1 {
+            problems.push("trash retention must be at least 1 day".to_string());
+        }
+        if !(1..=24 * 60).contains(&self.access_token_minutes) {
+            problems.push("access token lifetime must be between 1 and 1440 minutes".to_string());
+        }
+        if !(1..=365).contains(&self.refresh_token_days) {
+            problems.push("refresh token lifetime must be between 1 and 365 days".to_string());
+        }
+        if self.allowed_categories.is_empty() {
+            problems.push("at least one content category must be allowed".to_string());
+        }
+        for category in &self.allowed_categories {
+            let valid = !category.is_empty()
+                && category.len() <= 50
+                && category.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                problems.push(format!("category '{}' must contain only letters, numbers, hyphens and underscores", category));
+            }
+        }
+        if self.content_cache_ttl_seconds < 0 || self.github_cache_ttl_seconds < 0 {
+            problems.push("cache TTLs cannot be negative".to_string());
+        }
+        if !(self.github_api_url.starts_with("http://") || self.github_api_url.starts_with("https://")) {
+            problems.push(format!("GitHub API URL '{}' must start with http:// or https://", self.github_api_url));
+        }
+        if let Err(e) = Self::validate_jwt_keys(&self.jwt_keys, self.production) {
+            problems.push(e);
+        }
+        if self.two_factor_key.is_empty() {
+            problems.push("TWO_FACTOR_KEY cannot be empty".to_string());
+        } else if self.production && (self.two_factor_key == DEFAULT_TWO_FACTOR_KEY || self.two_factor_key.len() < 32) {
+            problems.push("Refusing to start in production without a TWO_FACTOR_KEY of at least 32 characters".to_string());
+        }
+        if self.production && self.metrics_token.is_none() {
+            problems.push("Refusing to start in production without METRICS_TOKEN; /metrics would be public".to_string());
+        }
+        if let Err(e) = self.validate_admin_credentials() {
+            problems.push(e);
+        }
+        for name in self.redacted_secrets() {
+            problems.push(format!("{} is the {} placeholder printed by `config check`, not a real secret", name, REDACTED));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid configuration:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+
+    /// Check JWT keys are usable, and strong enough when running in production
+    pub fn validate_jwt_keys(jwt_keys: &[JwtKey], production: bool) -> Result<(), String> {
+        if jwt_keys.is_empty() {
+            return Err("JWT_KEYS must contain at least one key".to_string());
+        }
+
+        let mut kids = std::collections::HashSet::new();
+        for key in jwt_keys {
+            if key.kid.trim().is_empty() {
+                return Err("JWT keys must have a non-empty kid".to_string());
+            }
+            if key.secret.is_empty() {
+                return Err(format!("JWT key '{}' has an empty secret", key.kid));
+            }
+            if !kids.insert(key.kid.as_str()) {
+                return Err(format!("JWT key id '{}' is used more than once", key.kid));
+            }
+        }
+
+        if production {
+            for key in jwt_keys {
+                if key.secret == DEFAULT_JWT_SECRET {
+                    return Err("Refusing to start in production with the default JWT secret; set JWT_SECRET or JWT_KEYS".to_string());
+                }
+                if key.secret.len() < 32 {
+                    return Err(format!("JWT key '{}' is too short for production (minimum 32 characters)", key.kid));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the users file loads and the admin password hash parses, and that
+    /// production never falls back to the plaintext `ADMIN_PASSWORD`
+    pub fn validate_admin_credentials(&self) -> Result<(), String> {
+        if let Some(path) = &self.users_file {
+            crate::utils::load_users_file(path).map_err(|e| format!("users file '{}' cannot be loaded: {}", path, e))?;
+        } else if let Some(hash) = &self.admin_password_hash {
+            argon2::PasswordHash::new(hash).map_err(|e| format!("ADMIN_PASSWORD_HASH is not a valid PHC string: {}", e))?;
+        } else if self.production {
+            return Err("Refusing to start in production without ADMIN_PASSWORD_HASH or USERS_FILE".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Names of the secrets set to the `REDACTED` placeholder, e.g. by copying
+    /// the output of `config check` back into a config file
+    fn redacted_secrets(&self) -> Vec<String> {
+        let mut secrets = vec![
+            ("TWO_FACTOR_KEY".to_string(), Some(&self.two_factor_key)),
+            ("METRICS_TOKEN".to_string(), self.metrics_token.as_ref()),
+            ("GITHUB_TOKEN".to_string(), self.github_token.as_ref()),
+            ("ADMIN_PASSWORD_HASH".to_string(), self.admin_password_hash.as_ref()),
+            ("ADMIN_PASSWORD".to_string(), self.admin_password.as_ref()),
+        ];
+        secrets.extend(self.jwt_keys.iter().map(|key| (format!("JWT key '{}'", key.kid), Some(&key.secret))));
+        secrets
+            .into_iter()
+            .filter(|(_, secret)| secret.is_some_and(|secret| secret == REDACTED))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// The effective configuration as a config file, with secrets redacted
+    ///
+    /// Secrets left at their development default or unset are omitted.
+    pub fn to_redacted_toml(&self) -> Result<String, String> {
+        let redact = |secret: Option<&String>| secret.map(|_| REDACTED.to_string());
+        let default_jwt_keys = self.jwt_keys.iter().all(|key| key.secret == DEFAULT_JWT_SECRET);
+        let file = ConfigFile {
+            server: ServerSection {
+                host: Some(self.host.clone()),
+                port: Some(self.port),
+                production: Some(self.production),
+                trust_proxy: Some(self.trust_proxy),
+                log_format: Some(self.log_format),
+            },
+            paths: PathsSection {
+                content: Some(self.content_path.clone()),
+                frontend: Some(self.frontend_path.clone()),
+                trash: Some(self.trash_path.clone()),
+                data: Some(self.data_path.clone()),
+            },
+            cors: CorsSection {
+                frontend_url: Some(self.frontend_url.clone()),
+                allowed_origins: Some(self.cors_allowed_origins.clone()),
+                max_age: Some(self.cors_max_age),
+            },
+            security: SecuritySection {
+                hsts_max_age: Some(self.hsts_max_age),
+                csp_report_only: Some(self.csp_report_only),
+                csp_connect_sources: Some(self.csp_connect_sources.clone()),
+                metrics_token: redact(self.metrics_token.as_ref()),
+            },
+            auth: AuthSection {
+                jwt_secret: None,
+                jwt_keys: (!default_jwt_keys).then(|| {
+                    self.jwt_keys
+                        .iter()
+                        .map(|key| JwtKey { kid: key.kid.clone(), secret: REDACTED.to_string() })
+                        .collect()
+                }),
+                two_factor_key: redact(Some(&self.two_factor_key).filter(|key| *key != DEFAULT_TWO_FACTOR_KEY)),
+                access_token_minutes: Some(self.access_token_minutes),
+                refresh_token_days: Some(self.refresh_token_days),
+                cookie_sessions: Some(self.cookie_sessions),
+                users_file: self.users_file.clone(),
+                admin_username: Some(self.admin_username.clone()),
+                admin_password_hash: redact(self.admin_password_hash.as_ref()),
+                admin_password: redact(self.admin_password.as_ref()),
+            },
+            content: ContentSection {
+                allowed_categories: Some(self.allowed_categories.clone()),
+                cache_ttl_seconds: Some(self.content_cache_ttl_seconds),
+                trash_retention_days: Some(self.trash_retention_days),
+            },
+            github: GithubSection {
+                api_url: Some(self.github_api_url.clone()),
+                token: redact(self.github_token.as_ref()),
+                cache_ttl_seconds: Some(self.github_cache_ttl_seconds),
+            },
+        };
+
+        toml::to_string_pretty(&file).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn production_refuses_the_plaintext_admin_password() {
+        let mut config = AppConfig::from_sources(&ConfigFile::default(), &no_env).unwrap();
+        config.users_file = None;
+        config.admin_password_hash = None;
+        assert!(config.validate_admin_credentials().is_ok());
+        config.production = true;
+        assert!(config.validate_admin_credentials().is_err());
+        config.admin_password_hash = Some(crate::utils::hash_password("secret").unwrap());
+        assert!(config.validate_admin_credentials().is_ok());
+    }
+
+    #[test]
+    fn admin_credentials_must_parse() {
+        let mut config = AppConfig::from_sources(&ConfigFile::default(), &no_env).unwrap();
+        config.users_file = None;
+        config.admin_password_hash = Some("$argon2id$v=19$...".to_string());
+        assert!(config.validate_admin_credentials().unwrap_err().contains("ADMIN_PASSWORD_HASH"));
+
+        config.users_file = Some("/nonexistent/users.yaml".to_string());
+        assert!(config.validate_admin_credentials().unwrap_err().contains("users file"));
+    }
+
+    #[test]
+    fn production_refuses_weak_jwt_keys() {
+        let default_key = JwtKey { kid: "default".to_string(), secret: DEFAULT_JWT_SECRET.to_string() };
+        assert!(AppConfig::validate_jwt_keys(std::slice::from_ref(&default_key), false).is_ok());
+        assert!(AppConfig::validate_jwt_keys(&[default_key], true).is_err());
+        let short_key = JwtKey { kid: "2025".to_string(), secret: "new-secret".to_string() };
+        assert!(AppConfig::validate_jwt_keys(&[short_key], true).is_err());
+        let strong_key = JwtKey { kid: "prod".to_string(), secret: "x".repeat(48) };
+        assert!(AppConfig::validate_jwt_keys(&[strong_key], true).is_ok());
+    }
+
+    #[test]
+    fn production_refuses_the_default_two_factor_key() {
+        let mut config = AppConfig::from_sources(&ConfigFile::default(), &no_env).unwrap();
+        config.production = true;
+        assert!(config.validate().unwrap_err().contains("TWO_FACTOR_KEY"));
+        config.two_factor_key = "x".repeat(48);
+        assert!(!config.validate().unwrap_err().contains("TWO_FACTOR_KEY"));
+    }
+
+    #[test]
+    fn production_requires_a_metrics_token() {
+        let mut config = AppConfig::from_sources(&ConfigFile::default(), &no_env).unwrap();
+        assert!(!config.validate().is_err_and(|e| e.contains("METRICS_TOKEN")));
+        config.production = true;
+        assert!(config.validate().unwrap_err().contains("METRICS_TOKEN"));
+        config.metrics_token = Some("scraper-token".to_string());
+        assert!(!config.validate().unwrap_err().contains("METRICS_TOKEN"));
+    }
+
+    #[test]
+    fn jwt_keys_need_unique_ids_and_secrets() {
+        let key = |kid: &str, secret: &str| JwtKey { kid: kid.to_string(), secret: secret.to_string() };
+        let secret = "x".repeat(48);
+
+        for production in [false, true] {
+            assert!(AppConfig::validate_jwt_keys(&[key("", &secret)], production).is_err());
+            assert!(AppConfig::validate_jwt_keys(&[key("2025", "")], production).is_err());
+            assert!(AppConfig::validate_jwt_keys(&[key("2025", &secret), key("2025", &secret)], production).is_err());
+            assert!(AppConfig::validate_jwt_keys(&[key("2026", &secret), key("2025", &secret)], production).is_ok());
+        }
+    }
+
+    const CONFIG_FILE: &str = r#"
+        [auth]
+        jwt_keys = [{ kid = "2025", secret = "file-secret" }]
+        access_token_minutes = 10
+
+        [content]
+        allowed_categories = ["project", "notes"]
+
+        [github]
+        token = "ghp_file_token"
+        cache_ttl_seconds = 600
+    "#;
+
+    #[test]
+    fn config_files_set_what_the_environment_does_not() {
+        let config = AppConfig::from_sources(&ConfigFile::parse(CONFIG_FILE).unwrap(), &no_env).unwrap();
+        assert_eq!(config.access_token_minutes, 10);
+        assert_eq!(config.allowed_categories, vec!["project", "notes"]);
+        assert_eq!(config.github_cache_ttl_seconds, 600);
+        assert!(crate::utils::validate_category("notes", &config.allowed_categories).is_ok());
+        assert!(crate::utils::validate_category("blog", &config.allowed_categories).is_err());
+    }
+
+    #[test]
+    fn environment_variables_override_config_files() {
+        let env = |name: &str| match name {
+            "ACCESS_TOKEN_MINUTES" => Some("5".to_string()),
+            "JWT_KEYS" => Some("2026:env-secret, 2025:file-secret".to_string()),
+            "APP_ENV" => Some("production".to_string()),
+            _ => None,
+        };
+        let config = AppConfig::from_sources(&ConfigFile::parse(CONFIG_FILE).unwrap(), &env).unwrap();
+        assert_eq!(config.access_token_minutes, 5);
+        assert_eq!(config.jwt_keys.iter().map(|key| key.kid.as_str()).collect::<Vec<_>>(), vec!["2026", "2025"]);
+        assert!(config.production);
+        assert_eq!(config.github_cache_ttl_seconds, 600);
+    }
+
+    #[test]
+    fn printed_config_never_shows_secrets() {
+        let config = AppConfig::from_sources(&ConfigFile::parse(CONFIG_FILE).unwrap(), &no_env).unwrap();
+        let printed = config.to_redacted_toml().unwrap();
+        assert!(printed.contains(REDACTED));
+        assert!(!printed.contains("ghp_file_token"));
+        assert!(!printed.contains("file-secret"));
+        assert!(ConfigFile::parse(&printed).is_ok());
+    }
+
+    #[test]
+    fn printed_config_omits_unset_secrets() {
+        let printed = AppConfig::from_sources(&ConfigFile::default(), &no_env).unwrap().to_redacted_toml().unwrap();
+        assert!(!printed.contains(REDACTED));
+    }
+
+    #[test]
+    fn redaction_placeholders_are_not_accepted_as_secrets() {
+        let printed = AppConfig::from_sources(&ConfigFile::parse(CONFIG_FILE).unwrap(), &no_env)
+            .unwrap()
+            .to_redacted_toml()
+            .unwrap();
+        let error = AppConfig::from_sources(&ConfigFile::parse(&printed).unwrap(), &no_env)
+            .unwrap()
+            .validate()
+            .unwrap_err();
+        assert!(error.contains("GITHUB_TOKEN"));
+        assert!(error.contains("JWT key '2025'"));
+    }
+
+    #[test]
+    fn admin_credentials_can_come_from_the_config_file() {
+        let file = ConfigFile::parse("[auth]\nadmin_username = \"owner\"\nusers_file = \"users.yaml\"").unwrap();
+        let config = AppConfig::from_sources(&file, &no_env).unwrap();
+        assert_eq!(config.admin_username, "owner");
+        assert_eq!(config.users_file.as_deref(), Some("users.yaml"));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(ConfigFile::parse("[server]\nprot = 4000").unwrap_err().contains("prot"));
+    }
+
+    #[test]
+    fn validation_reports_every_problem_at_once() {
+        let mut config = AppConfig::from_sources(&ConfigFile::default(), &no_env).unwrap();
+        config.access_token_minutes = 0;
+        config.allowed_categories = vec!["../etc".to_string()];
+        config.cors_allowed_origins = vec!["*".to_string()];
+        let error = config.validate().unwrap_err();
+        assert!(error.contains("access token lifetime"));
+        assert!(error.contains("category '../etc'"));
+        assert!(error.contains("'*'"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::io;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use crate::models::*;
+use crate::utils::ValidationError;
+
+/// Error of an API request, mapped to a status code and a machine-readable error code
+///
+/// Client errors answer with their message as-is, so it must be written for the
+/// client. Server errors (IO, YAML, GitHub and internal failures) are logged with
+/// their detail and answered with a generic message, so file system errors and
+/// paths never reach the client.
+#[derive(Debug)]
+pub enum AppError {
+    /// Invalid input; `context` says which, e.g. "Invalid slug parameter"
+    Validation { context: &'static str, error: ValidationError },
+    BadRequest(String),
+    /// Missing, invalid or expired credentials
+    Unauthorized(String),
+    /// Authenticated, but not allowed to do this
+    Forbidden(String),
+    /// A cookie session request without a matching CSRF token
+    Csrf,
+    NotFound(String),
+    Conflict(String),
+    /// Overwriting an existing file without an `If-Match` header
+    PreconditionRequired(String),
+    TooManyRequests { message: String, retry_after: i64 },
+    Io(io::Error),
+    Yaml(serde_yaml::Error),
+    /// The GitHub API failed or answered with an error status
+    GitHub(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn invalid(context: &'static str, error: ValidationError) -> Self {
+        AppError::Validation { context, error }
+    }
+
+    /// Classify an error from the `Box<dyn Error>` helpers
+    ///
+    /// IO, YAML, validation and GitHub errors keep their mapping; `AppError`s
+    /// raised by the helpers pass through, so client errors must be raised as
+    /// one. Anything else is unexpected and becomes `Internal`, whose detail is
+    /// only logged.
+    pub fn from_boxed(error: Box<dyn std::error::Error>) -> Self {
+        let error = match error.downcast::<AppError>() {
+            Ok(error) => return *error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<io::Error>() {
+            Ok(error) => return AppError::Io(*error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<serde_yaml::Error>() {
+            Ok(error) => return AppError::Yaml(*error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<ValidationError>() {
+            Ok(error) => return AppError::invalid("Invalid input", *error),
+            Err(error) => error,
+        };
+        match error.downcast::<reqwest::Error>() {
+            Ok(error) => AppError::GitHub(error.to_string()),
+            Err(error) => AppError::Internal(error.to_string()),
+        }
+    }
+
+    /// Machine-readable error code returned in `ApiResponse::code`
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation { .. } => "invalid_input",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Csrf => "csrf_failed",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::PreconditionRequired(_) => "precondition_required",
+            AppError::TooManyRequests { .. } => "rate_limited",
+            AppError::Io(e) => match e.kind() {
+                io::ErrorKind::NotFound => "not_found",
+                io::ErrorKind::AlreadyExists => "conflict",
+                _ => "storage_error",
+            },
+            AppError::Yaml(_) => "invalid_content",
+            AppError::GitHub(_) => "github_unavailable",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Message returned to the client; never includes server-side detail
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::Validation { context, error } => format!("{}: {}", context, error),
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::PreconditionRequired(message)
+            | AppError::TooManyRequests { message, .. } => message.clone(),
+            AppError::Csrf => "CSRF token missing or invalid".to_string(),
+            AppError::Io(e) => match e.kind() {
+                io::ErrorKind::NotFound => "Not found".to_string(),
+                io::ErrorKind::AlreadyExists => "Already exists".to_string(),
+                _ => "Storage error".to_string(),
+            },
+            AppError::Yaml(_) => "Content could not be parsed".to_string(),
+            AppError::GitHub(_) => "GitHub is unavailable".to_string(),
+            AppError::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Io(e) => write!(f, "IO error: {}", e),
+            AppError::Yaml(e) => write!(f, "YAML error: {}", e),
+            AppError::GitHub(detail) => write!(f, "GitHub API error: {}", detail),
+            AppError::Internal(detail) => f.write_str(detail),
+            _ => f.write_str(&self.public_message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<ValidationError> for AppError {
+    fn from(error: ValidationError) -> Self {
+        AppError::invalid("Invalid input", error)
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        AppError::Io(error)
+    }
+}
+
+impl From<serde_yaml::Error> for AppError {
+    fn from(error: serde_yaml::Error) -> Self {
+        AppError::Yaml(error)
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Validation { .. } | AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) | AppError::Csrf => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Io(e) => match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::AlreadyExists => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            AppError::Yaml(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::GitHub(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// The error as an `ApiResponse`, logging the detail of server errors
+    ///
+    /// Called while the handler's request span is entered, so the log line
+    /// carries the request id.
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!(code = self.code(), error = %self, "Request failed");
+        }
+
+        let mut response = HttpResponse::build(status).json(
+            ApiResponse::<()>::error_with_code(self.code(), &self.public_message())
+        );
+        if let AppError::TooManyRequests { retry_after, .. } = self {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unexpected_helper_errors_are_internal_and_keep_their_detail_private() {
+        let parse_error = serde_json::from_str::<serde_json::Value>("{ not json").unwrap_err();
+        let error = AppError::from_boxed(parse_error.into());
+        assert!(matches!(error, AppError::Internal(_)));
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.public_message(), "Internal server error");
+    }
+
+    #[test]
+    fn explicit_client_errors_pass_through() {
+        let error = AppError::from_boxed(AppError::BadRequest("Path is not a directory".to_string()).into());
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.public_message(), "Path is not a directory");
+    }
+}
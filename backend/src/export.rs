@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use crate::models::*;
+use crate::security_headers::{is_strict_path, SKIPPED_DIRS};
+use crate::shortcodes::escape_html;
+use crate::utils::*;
+use crate::AppConfig;
+
+/// Category the RSS and Atom feeds are built from
+pub const FEED_CATEGORY: &str = "blog";
+
+/// Maximum number of entries in each feed
+const FEED_ENTRIES: usize = 20;
+
+/// Write `data` as the JSON body the API would return, at `relative_path` under `out_dir`
+fn write_api_json<T: Serialize>(out_dir: &Path, relative_path: &str, data: T) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string(&ApiResponse::success(data))?;
+    write_export_file(out_dir, relative_path, json.as_bytes())
+}
+
+fn write_export_file(out_dir: &Path, relative_path: &str, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = out_dir.join(relative_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_file_atomic(&path.to_string_lossy(), contents)?;
+    Ok(())
+}
+
+/// Render the public site to static files under `out_dir`
+///
+/// Copies the built frontend from `frontend_dir` without the admin and login
+/// pages, then writes every public API response as JSON with the same body as
+/// the server (`api/content/<category>.json`, `api/content/<category>/<slug>.json`,
+/// `api/content/tags.json`, `api/github/projects.json`), RSS and Atom feeds of
+/// `FEED_CATEGORY` and a sitemap. Absolute links use `site_url`. Returns the
+/// number of files written.
+pub async fn export_site(
+    app_config: &AppConfig,
+    frontend_dir: &Path,
+    out_dir: &Path,
+    site_url: &str,
+    github_cache: &Mutex<HashMap<String, CachedGithubProject>>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let site_url = site_url.trim_end_matches('/');
+    let mut pages = Vec::new();
+    let mut written = 0;
+
+    fs::create_dir_all(out_dir)?;
+    if frontend_dir.is_dir() {
+        written += copy_frontend(frontend_dir, frontend_dir, out_dir, &mut pages)?;
+    }
+
+    let mut feed_items = Vec::new();
+    let mut last_modified: Option<NaiveDate> = None;
+    for category in &app_config.allowed_categories {
+        let items = load_content_list(app_config, category, github_cache).await?;
+
+        for item in &items {
+            // Items render with every shortcode resolved, like the item endpoint
+            let file_path = create_safe_content_path(&app_config.content_path, &app_config.allowed_categories, category, Some(&item.slug))?;
+            let markdown = fs::read_to_string(&file_path)?;
+            let shortcode_context = build_shortcode_context(&markdown, github_cache, app_config, true).await;
+            let item = parse_markdown_source(&file_path, &markdown, category, &shortcode_context)?;
+
+            if let Some(date) = content_date(&item) {
+                last_modified = last_modified.max(Some(date));
+            }
+            write_api_json(out_dir, &format!("api/content/{}/{}.json", category, item.slug), &item)?;
+            written += 1;
+            if category == FEED_CATEGORY {
+                feed_items.push(item);
+            }
+        }
+
+        write_api_json(out_dir, &format!("api/content/{}.json", category), items)?;
+        written += 1;
+    }
+
+    write_api_json(out_dir, "api/content/tags.json", load_content_tags(app_config))?;
+    written += 1;
+
+    // Sites without GitHub projects have no config to export
+    if Path::new(&app_config.content_path).join("github/config.yaml").is_file() {
+        let projects = load_github_projects(app_config, github_cache, false).await?;
+        write_api_json(out_dir, "api/github/projects.json", projects)?;
+        written += 1;
+    }
+
+    if app_config.allowed_categories.iter().any(|category| category == FEED_CATEGORY) {
+        feed_items.sort_by(|a, b| b.metadata.date.cmp(&a.metadata.date));
+        feed_items.truncate(FEED_ENTRIES);
+        write_export_file(out_dir, "rss.xml", render_rss(&feed_items, site_url).as_bytes())?;
+        write_export_file(out_dir, "atom.xml", render_atom(&feed_items, site_url).as_bytes())?;
+        written += 2;
+    }
+
+    pages.sort();
+    write_export_file(out_dir, "sitemap.xml", render_sitemap(&pages, site_url, last_modified).as_bytes())?;
+    written += 1;
+
+    Ok(written)
+}
+
+/// Copy the files under `dir` to `out_dir`, collecting the URL paths of the HTML pages
+///
+/// Skips the directories `InlineHashes::scan` skips, hidden files, the strict
+/// admin and login pages (they need the server) and `out_dir` itself when it
+/// lies inside the frontend.
+fn copy_frontend(root: &Path, dir: &Path, out_dir: &Path, pages: &mut Vec<String>) -> Result<usize, Box<dyn std::error::Error>> {
+    let out_dir = out_dir.canonicalize().unwrap_or_else(|_| out_dir.to_path_buf());
+    let mut copied = 0;
+
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+        if name.starts_with('.') || is_strict_path(&format!("/{}", relative)) {
+            continue;
+        }
+
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_str()) && path.canonicalize()? != out_dir {
+                copied += copy_frontend(root, &path, &out_dir, pages)?;
+            }
+        } else {
+            let target = out_dir.join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&path, &target)?;
+            copied += 1;
+
+            if let Some(page) = relative.strip_suffix(".html") {
+                pages.push(if page == "index" || page.ends_with("/index") {
+                    format!("/{}", page.trim_end_matches("index"))
+                } else {
+                    format!("/{}.html", page)
+                });
+            }
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Frontmatter date of an item, if it is a valid `YYYY-MM-DD` date
+fn content_date(item: &ContentItem) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&item.metadata.date, "%Y-%m-%d").ok()
+}
+
+/// Unique id of an item in the feeds
+///
+/// Items only open as modals on the home page, so feeds link there and use
+/// this URL as the entry id.
+fn item_id(site_url: &str, item: &ContentItem) -> String {
+    format!("{}/#{}/{}", site_url, item.category, item.slug)
+}
+
+fn render_rss(items: &[ContentItem], site_url: &str) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape_html(site_url)));
+    xml.push_str(&format!("  <link>{}/</link>\n", escape_html(site_url)));
+    xml.push_str(&format!("  <description>Latest posts from {}</description>\n", escape_html(site_url)));
+    xml.push_str(&format!(
+        "  <atom:link href=\"{}/rss.xml\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+        escape_html(site_url)
+    ));
+
+    for item in items {
+        xml.push_str("  <item>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape_html(&item.metadata.title)));
+        xml.push_str(&format!("    <link>{}/</link>\n", escape_html(site_url)));
+        xml.push_str(&format!("    <guid isPermaLink=\"false\">{}</guid>\n", escape_html(&item_id(site_url, item))));
+        if let Some(date) = content_date(item) {
+            let published = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default());
+            xml.push_str(&format!("    <pubDate>{}</pubDate>\n", published.to_rfc2822()));
+        }
+        for tag in &item.metadata.tags {
+            xml.push_str(&format!("    <category>{}</category>\n", escape_html(tag)));
+        }
+        xml.push_str(&format!("    <description>{}</description>\n", escape_html(&item.html_content)));
+        xml.push_str("  </item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn render_atom(items: &[ContentItem], site_url: &str) -> String {
+    let timestamp = |item: &ContentItem| {
+        content_date(item)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|time| Utc.from_utc_datetime(&time).to_rfc3339())
+    };
+    let updated = items
+        .iter()
+        .filter_map(timestamp)
+        .max()
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape_html(site_url)));
+    xml.push_str(&format!("  <id>{}/</id>\n", escape_html(site_url)));
+    xml.push_str(&format!("  <link href=\"{}/\"/>\n", escape_html(site_url)));
+    xml.push_str(&format!("  <link href=\"{}/atom.xml\" rel=\"self\"/>\n", escape_html(site_url)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated));
+
+    for item in items {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape_html(&item.metadata.title)));
+        xml.push_str(&format!("    <id>{}</id>\n", escape_html(&item_id(site_url, item))));
+        xml.push_str(&format!("    <link href=\"{}/\"/>\n", escape_html(site_url)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", timestamp(item).unwrap_or_else(|| updated.clone())));
+        if !item.metadata.description.is_empty() {
+            xml.push_str(&format!("    <summary>{}</summary>\n", escape_html(&item.metadata.description)));
+        }
+        for tag in &item.metadata.tags {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", escape_html(tag)));
+        }
+        xml.push_str(&format!("    <content type=\"html\">{}</content>\n", escape_html(&item.html_content)));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Sitemap of the public pages; the home page is dated by the newest content
+fn render_sitemap(pages: &[String], site_url: &str, last_modified: Option<NaiveDate>) -> String {
+    let mut urls = pages.to_vec();
+    if !urls.iter().any(|page| page == "/") {
+        urls.insert(0, "/".to_string());
+    }
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for page in &urls {
+        xml.push_str("  <url>\n");
+        xml.push_str(&format!("    <loc>{}{}</loc>\n", escape_html(site_url), escape_html(page)));
+        if let (true, Some(date)) = (page == "/", last_modified) {
+            xml.push_str(&format!("    <lastmod>{}</lastmod>\n", date.format("%Y-%m-%d")));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
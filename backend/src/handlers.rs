@@ -1,82 +1,216 @@
-use actix_web::{web, HttpResponse, Result, HttpRequest};
-use chrono::{DateTime, Utc, Duration};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::{web, HttpResponse, Result, HttpRequest, ResponseError};
+use chrono::{Utc, Duration};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, LazyLock, Mutex};
+use tracing::{error, warn};
+use crate::api_tokens::*;
+use crate::audit::*;
+use crate::error::*;
+use crate::health::*;
+use crate::metrics::*;
 use crate::models::*;
+use crate::permissions::*;
+use crate::history::*;
+use crate::sessions::*;
+use crate::throttle::*;
+use crate::totp::*;
+use crate::trash::*;
 use crate::utils::*;
 use crate::AppConfig;
 
+/// One lock per saved file, keyed by normalized content path, so the If-Match
+/// check and the write of a save see the same version of that file
+static SAVE_LOCKS: LazyLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> = LazyLock::new(Default::default);
+
+fn save_lock(relative_path: &str) -> Arc<Mutex<()>> {
+    let mut locks = SAVE_LOCKS.lock().unwrap();
+    // Forget locks no save is holding or waiting for
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+    locks.entry(normalize_relative_path(relative_path)).or_default().clone()
+}
+
+/// Result of writing a file only if it is still at the version the client saw
+enum SaveOutcome {
+    Saved,
+    /// The file is at another version, or the client sent none; holds the current one
+    Conflict { current: Vec<u8>, etag: String },
+}
+
+/// Compare the current version of a file with `if_match` and write `content` if it matches
+///
+/// Blocking: runs on the blocking thread pool, holding the lock of this file
+/// from the read until the new version and its history are written.
+fn save_if_match(
+    app_config: &AppConfig,
+    user: &str,
+    file_path: &str,
+    safe_path: &str,
+    content: &str,
+    if_match: Option<&str>,
+) -> Result<SaveOutcome, AppError> {
+    let lock = save_lock(file_path);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    match std::fs::read(safe_path) {
+        Ok(current) => {
+            let etag = content_etag(&current);
+            if !if_match.is_some_and(|if_match| if_match_satisfied(if_match, &etag)) {
+                return Ok(SaveOutcome::Conflict { current, etag });
+            }
+        }
+        // A specific version was expected but the file is gone
+        Err(_) if if_match.is_some() => return Err(AppError::Conflict("File no longer exists".to_string())),
+        Err(_) => {}
+    }
+
+    // Keep the pre-edit version of files that have no history yet
+    if let Err(e) = ensure_baseline_revision(&app_config.content_path, &app_config.history_path(), user, file_path) {
+        error!(path = %file_path, error = %e, "Failed to record history");
+    }
+
+    write_file_atomic(safe_path, content.as_bytes())?;
+    if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::Save, user, file_path, None) {
+        error!(path = %file_path, error = %e, "Failed to record history");
+    }
+    Ok(SaveOutcome::Saved)
+}
+
+/// The request's `If-Match` header, if any
+fn if_match_header(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("If-Match")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Refuse to overwrite an existing `target` unless `if_match` names its current version
+///
+/// Folders have no ETag, so only `If-Match: *` matches them. Callers hold the
+/// save lock of the target, so the version checked is the one replaced.
+fn require_if_match(target: &std::path::Path, if_match: Option<&str>) -> Result<(), AppError> {
+    if !target.exists() {
+        return Ok(());
+    }
+    let Some(if_match) = if_match else {
+        return Err(AppError::PreconditionRequired(
+            "If-Match header required when overwriting an existing file".to_string(),
+        ));
+    };
+
+    let matches = if target.is_dir() {
+        if_match.split(',').any(|tag| tag.trim() == "*")
+    } else {
+        if_match_satisfied(if_match, &content_etag(&std::fs::read(target)?))
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::Conflict("File was modified by someone else".to_string()))
+    }
+}
+
+/// Move a staged upload into `folder_path` if every file it replaces matches `if_match`
+///
+/// Blocking: holds the save lock of every uploaded path from the check until
+/// the files and their history are written.
+fn commit_upload_if_match(
+    app_config: &AppConfig,
+    user: &str,
+    folder_path: &str,
+    upload: StagedUpload,
+    if_match: Option<&str>,
+) -> Result<Vec<String>, AppError> {
+    let relative_path = |filename: &str| format!("{}/{}", folder_path, filename);
+
+    // Locked in sorted order, so concurrent uploads of the same files cannot deadlock
+    let mut lock_paths: Vec<String> = upload.filenames().iter()
+        .map(|filename| normalize_relative_path(&relative_path(filename)))
+        .collect();
+    lock_paths.sort();
+    lock_paths.dedup();
+    let locks: Vec<_> = lock_paths.iter().map(|path| save_lock(path)).collect();
+    let _guards: Vec<_> = locks.iter().map(|lock| lock.lock().unwrap_or_else(|e| e.into_inner())).collect();
+
+    let overwritten = upload.overwritten();
+    for filename in &overwritten {
+        require_if_match(&std::path::Path::new(&app_config.content_path).join(relative_path(filename)), if_match)?;
+    }
+    // Keep the pre-upload version of overwritten files that have no history yet
+    for filename in &overwritten {
+        if let Err(e) = ensure_baseline_revision(&app_config.content_path, &app_config.history_path(), user, &relative_path(filename)) {
+            error!(path = %relative_path(filename), error = %e, "Failed to record history");
+        }
+    }
+
+    let filenames = upload.commit()?;
+    for filename in &filenames {
+        if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::Upload, user, &relative_path(filename), None) {
+            error!(path = %relative_path(filename), error = %e, "Failed to record history");
+        }
+    }
+    Ok(filenames)
+}
+
+/// Liveness: the process is up and serving requests
 pub async fn health_check() -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(ApiResponse::success("Server is running")))
 }
 
+/// Readiness: content and GitHub projects can be served; 503 with the failed checks otherwise
+pub async fn readiness_check(
+    req: HttpRequest,
+    github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    let warmup = req.app_data::<web::Data<CacheWarmup>>();
+    let readiness = check_readiness(&app_config, &github_cache, warmup.map(|warmup| warmup.get_ref()));
+    
+    if readiness.ready {
+        Ok(HttpResponse::Ok().json(ApiResponse::success(readiness)))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(ApiResponse {
+            success: false,
+            data: Some(readiness),
+            message: "Not ready".to_string(),
+            code: Some("not_ready".to_string()),
+        }))
+    }
+}
+
 pub async fn get_content_list(
     path: web::Path<String>,
-    content_cache: web::Data<Mutex<HashMap<String, CachedContent>>>,
+    github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
     let category = path.into_inner();
     
     // Validate category parameter
-    if let Err(validation_error) = validate_category(&category) {
-        return Ok(HttpResponse::BadRequest().json(
-            ApiResponse::<()>::error(&format!("Invalid category parameter: {}", validation_error))
-        ));
+    if let Err(validation_error) = validate_category(&category, &app_config.allowed_categories) {
+        return Err(AppError::invalid("Invalid category parameter", validation_error).into());
     }
     
-    match get_content_files(&category, &app_config.content_path) {
-        Ok(files) => {
-            let mut content_items = Vec::new();
-            
-            for file in files {
-                // Use safe path creation for additional security
-                let file_stem = file.trim_end_matches(".md");
-                match create_safe_content_path(&app_config.content_path, &category, Some(file_stem)) {
-                    Ok(file_path) => {
-                        match parse_markdown_file(&file_path, &category) {
-                            Ok(content) => content_items.push(content),
-                            Err(e) => {
-                                eprintln!("Error parsing {}: {}", file_path, e);
-                                continue;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Invalid file path for {}: {}", file, e);
-                        continue;
-                    }
-                }
-            }
-            
-            // Sort by date (newest first)
-            content_items.sort_by(|a, b| b.metadata.date.cmp(&a.metadata.date));
-            
-            Ok(HttpResponse::Ok().json(ApiResponse::success(content_items)))
-        }
-        Err(e) => {
-            Ok(HttpResponse::InternalServerError().json(ApiResponse::<()>::error(&format!("Error reading content: {}", e))))
-        }
+    match load_content_list(&app_config, &category, &github_cache).await {
+        Ok(content_items) => Ok(HttpResponse::Ok().json(ApiResponse::success(content_items))),
+        Err(e) => Err(AppError::Io(e).into()),
     }
 }
 
 pub async fn get_content_item(
     path: web::Path<(String, String)>,
     content_cache: web::Data<Mutex<HashMap<String, CachedContent>>>,
+    github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
     let (category, slug) = path.into_inner();
     
     // Validate category and slug parameters
-    if let Err(validation_error) = validate_category(&category) {
-        return Ok(HttpResponse::BadRequest().json(
-            ApiResponse::<()>::error(&format!("Invalid category parameter: {}", validation_error))
-        ));
+    if let Err(validation_error) = validate_category(&category, &app_config.allowed_categories) {
+        return Err(AppError::invalid("Invalid category parameter", validation_error).into());
     }
     
     if let Err(validation_error) = validate_slug(&slug) {
-        return Ok(HttpResponse::BadRequest().json(
-            ApiResponse::<()>::error(&format!("Invalid slug parameter: {}", validation_error))
-        ));
+        return Err(AppError::invalid("Invalid slug parameter", validation_error).into());
     }
     
     let cache_key = format!("{}/{}", category, slug);
@@ -85,24 +219,26 @@ pub async fn get_content_item(
     {
         let cache = content_cache.lock().unwrap();
         if let Some(cached_content) = cache.get(&cache_key) {
-            // Check if cache is still valid (1 hour)
-            if Utc::now() - cached_content.cached_at < Duration::hours(1) {
+            // Check if cache is still valid
+            if Utc::now() - cached_content.cached_at < Duration::seconds(app_config.content_cache_ttl_seconds) {
+                METRICS.record_cache_lookup("content", true);
                 return Ok(HttpResponse::Ok().json(ApiResponse::success(&cached_content.content)));
             }
         }
     }
+    METRICS.record_cache_lookup("content", false);
     
     // Create safe file path
-    let file_path = match create_safe_content_path(&app_config.content_path, &category, Some(&slug)) {
+    let file_path = match create_safe_content_path(&app_config.content_path, &app_config.allowed_categories, &category, Some(&slug)) {
         Ok(path) => path,
-        Err(validation_error) => {
-            return Ok(HttpResponse::BadRequest().json(
-                ApiResponse::<()>::error(&format!("Invalid file path: {}", validation_error))
-            ));
-        }
+        Err(validation_error) => return Err(AppError::invalid("Invalid file path", validation_error).into()),
     };
     
-    match parse_markdown_file(&file_path, &category) {
+    let markdown = std::fs::read_to_string(&file_path).map_err(AppError::from)?;
+    // Only cached GitHub data is used here; the cache warmer fetches the rest
+    let shortcode_context = build_shortcode_context(&markdown, &github_cache, &app_config, false).await;
+    
+    match parse_markdown_source(&file_path, &markdown, &category, &shortcode_context) {
         Ok(content) => {
             // Update cache
             let cached_content = CachedContent {
@@ -117,219 +253,483 @@ pub async fn get_content_item(
             
             Ok(HttpResponse::Ok().json(ApiResponse::success(content)))
         }
-        Err(e) => {
-            Ok(HttpResponse::NotFound().json(ApiResponse::<()>::error(&format!("Content not found: {}", e))))
-        }
+        Err(e) => Err(AppError::from_boxed(e).into()),
     }
 }
 
 pub async fn get_content_tags(
-    content_cache: web::Data<Mutex<HashMap<String, CachedContent>>>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    let mut all_tags = std::collections::HashSet::new();
-    
-    // Get tags from all categories
-    for category in &["project", "blog"] {
-        // Validate each category (should be safe since we control the list, but good practice)
-        if validate_category(category).is_err() {
-            continue;
-        }
-        
-        if let Ok(files) = get_content_files(category, &app_config.content_path) {
-            for file in files {
-                let file_stem = file.trim_end_matches(".md");
-                if let Ok(file_path) = create_safe_content_path(&app_config.content_path, category, Some(file_stem)) {
-                    if let Ok(content) = parse_markdown_file(&file_path, category) {
-                        for tag in content.metadata.tags {
-                            all_tags.insert(tag);
-                        }
-                    }
-                }
-            }
-        }
-    }
-    
-    let mut tags: Vec<String> = all_tags.into_iter().collect();
-    tags.sort();
-    
-    Ok(HttpResponse::Ok().json(ApiResponse::success(tags)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(load_content_tags(&app_config))))
 }
 
 pub async fn get_github_projects(
     github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Load GitHub config
-    let config = match load_github_config(&app_config.content_path) {
-        Ok(config) => config,
-        Err(e) => {
-            return Ok(HttpResponse::InternalServerError().json(
-                ApiResponse::<()>::error(&format!("Failed to load GitHub config: {}", e))
-            ));
+    match load_github_projects(&app_config, &github_cache, false).await {
+        Ok(projects) => Ok(HttpResponse::Ok().json(ApiResponse::success(projects))),
+        Err(e) => Err(AppError::from_boxed(e).into()),
+    }
+}
+
+pub async fn refresh_github_cache(
+    req: HttpRequest,
+    github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
+    _app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "refresh_github", AuditSubject::default(), async move {
+        // Check permission
+        require_permission(&req, Permission::GithubRefresh)?;
+        
+        // Clear the cache
+        {
+            let mut cache = github_cache.lock().unwrap();
+            cache.clear();
         }
-    };
-    
-    let mut projects = Vec::new();
-    
-    for repo in config.repositories {
-        let cache_key = format!("{}/{}", repo.owner, repo.repo);
         
-        // Check cache first
-        let cached_project = {
-            let cache = github_cache.lock().unwrap();
-            cache.get(&cache_key).cloned()
-        };
+        Ok(HttpResponse::Ok().json(ApiResponse::success("GitHub cache refreshed")))
+    }).await
+}
+
+// Authentication handlers
+
+/// Login endpoint - validates credentials and returns JWT token
+pub async fn login(
+    req: HttpRequest,
+    login_request: web::Json<LoginRequest>,
+    app_config: web::Data<AppConfig>,
+    users: web::Data<UserStore>,
+    refresh_tokens: web::Data<RefreshTokenStore>,
+    throttle: web::Data<LoginThrottle>,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "login", AuditSubject::user(&login_request.username), async move {
+        let ip = client_ip(&req, app_config.trust_proxy);
         
-        if let Some(cached) = cached_project {
-            // Check if cache is still valid (24 hours)
-            if Utc::now() - cached.cached_at < Duration::hours(24) {
-                let mut project = cached.project;
-                // Add feature and image properties from config
-                project.feature = repo.feature;
-                project.image = repo.image.clone();
-                projects.push(project);
-                continue;
-            }
+        // Refuse attempts while this IP or username is backing off or locked out
+        if let Some(wait) = throttle.check(&ip, &login_request.username) {
+            warn!(username = %login_request.username, %ip, retry_after_seconds = wait, "Throttled login attempt");
+            return Err(AppError::TooManyRequests {
+                message: "Too many failed login attempts, try again later".to_string(),
+                retry_after: wait,
+            }.into());
         }
         
-        // Fetch from GitHub API
-        match fetch_github_project(&repo).await {
-            Ok(mut project) => {
-                // Add feature and image properties from config
-                project.feature = repo.feature;
-                project.image = repo.image.clone();
-                
-                // Cache the project
-                let cached_project = CachedGithubProject {
-                    project: project.clone(),
-                    cached_at: Utc::now(),
-                };
-                
-                {
-                    let mut cache = github_cache.lock().unwrap();
-                    cache.insert(cache_key, cached_project);
+        // Validate credentials
+        let user = match users.authenticate(&login_request.username, &login_request.password) {
+            Some(user) => user,
+            None => {
+                let wait = throttle.record_failure(&ip, &login_request.username);
+                warn!(username = %login_request.username, %ip, "Failed login attempt");
+                let mut response = AppError::Unauthorized("Invalid username or password".to_string()).error_response();
+                if wait > 0 {
+                    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(wait));
                 }
-                
-                projects.push(project);
-            }
-            Err(e) => {
-                eprintln!("Failed to fetch GitHub project {}/{}: {}", repo.owner, repo.repo, e);
-                
-                // Create minimal project from config if API fails
-                let project = GitHubProject {
-                    owner: repo.owner.clone(),
-                    repo: repo.repo.clone(),
-                    display_name: repo.display_name.clone(),
-                    feature: repo.feature,
-                    image: repo.image.clone(),
-                    readme_html: "README not available".to_string(),
-                    url: format!("https://github.com/{}/{}", repo.owner, repo.repo),
-                    stars: 0,
-                    forks: 0,
-                    description: Some("GitHub repository".to_string()),
-                };
-                projects.push(project);
+                return Ok(response);
             }
+        };
+        
+        // Password alone is not enough when a second factor is enrolled, so failures
+        // are only cleared once the session starts; otherwise each password login
+        // would reset the count of wrong codes
+        if two_factor.is_enabled(&user.username) {
+            let (challenge_token, expires_at) = two_factor.create_challenge(&user.username);
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(TwoFactorChallenge {
+                two_factor_required: true,
+                challenge_token,
+                expires_at,
+            })));
         }
-    }
+        
+        throttle.record_success(&ip, &user.username);
+        Ok(start_session(&user, &app_config, &refresh_tokens))
+    }).await
+}
+
+/// Issue an access token and a new refresh token family for an authenticated user
+fn start_session(user: &AuthUser, app_config: &AppConfig, refresh_tokens: &RefreshTokenStore) -> HttpResponse {
+    // Start a refresh token family for this login
+    let (refresh_token, refresh_expires_at) = match refresh_tokens.issue(&user.username) {
+        Ok(issued) => issued,
+        Err(e) => return AppError::Internal(format!("Refresh token creation failed: {}", e)).error_response(),
+    };
     
-    Ok(HttpResponse::Ok().json(ApiResponse::success(projects)))
+    session_response(user, &refresh_token, refresh_expires_at, app_config)
 }
 
-pub async fn refresh_github_cache(
-    req: HttpRequest,
-    github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
-    _app_config: web::Data<AppConfig>,
-) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            // Clear the cache
-            {
-                let mut cache = github_cache.lock().unwrap();
-                cache.clear();
+/// Respond with a new access token and the session cookies
+///
+/// The refresh token always travels as an HttpOnly cookie. With cookie sessions
+/// enabled the access token does too, alongside a readable CSRF token cookie that
+/// mutating requests must echo in the `X-CSRF-Token` header.
+fn session_response(
+    user: &AuthUser,
+    refresh_token: &str,
+    refresh_expires_at: chrono::DateTime<Utc>,
+    app_config: &AppConfig,
+) -> HttpResponse {
+    // Create short-lived JWT access token
+    match create_session_token(&app_config.jwt_keys, &user.username, &user.role, app_config.access_token_minutes) {
+        Ok((token, expires_at, csrf_token)) => {
+            let mut response = HttpResponse::Ok();
+            response.cookie(refresh_token_cookie(refresh_token, refresh_expires_at, app_config.production));
+            if app_config.cookie_sessions {
+                response.cookie(access_token_cookie(&token, expires_at, app_config.production));
+                response.cookie(csrf_token_cookie(&csrf_token, expires_at, app_config.production));
             }
             
-            Ok(HttpResponse::Ok().json(ApiResponse::success("GitHub cache refreshed")))
+            response.json(ApiResponse::success(LoginResponse {
+                token,
+                expires_at,
+            }))
         }
-        Err(response) => Ok(response),
+        Err(e) => AppError::Internal(format!("JWT creation failed: {}", e)).error_response(),
     }
 }
 
-// Authentication handlers
+/// Second login step - exchanges a login challenge and TOTP or recovery code for a token
+pub async fn verify_two_factor_login(
+    req: HttpRequest,
+    verify_request: web::Json<TwoFactorVerifyRequest>,
+    app_config: web::Data<AppConfig>,
+    users: web::Data<UserStore>,
+    refresh_tokens: web::Data<RefreshTokenStore>,
+    throttle: web::Data<LoginThrottle>,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    let challenge_user = two_factor.challenge_user(&verify_request.challenge_token);
+    let subject = AuditSubject::user(challenge_user.as_deref().unwrap_or("unknown"));
+    audited(req.clone(), "login_two_factor", subject, async move {
+        let ip = client_ip(&req, app_config.trust_proxy);
+        
+        let username = match challenge_user {
+            Some(username) => username,
+            None => return Err(AppError::Unauthorized("Login challenge expired, log in again".to_string()).into()),
+        };
+        
+        if let Some(wait) = throttle.check(&ip, &username) {
+            return Err(AppError::TooManyRequests {
+                message: "Too many failed login attempts, try again later".to_string(),
+                retry_after: wait,
+            }.into());
+        }
+        
+        match two_factor.complete_challenge(&verify_request.challenge_token, &verify_request.code) {
+            Ok(_) => {}
+            Err(e) => {
+                throttle.record_failure(&ip, &username);
+                warn!(%username, %ip, "Failed two-factor attempt");
+                return Err(AppError::from_boxed(e).into());
+            }
+        }
+        
+        throttle.record_success(&ip, &username);
+        
+        match users.find(&username) {
+            Some(user) => Ok(start_session(&user, &app_config, &refresh_tokens)),
+            None => Err(AppError::Unauthorized("User no longer exists".to_string()).into()),
+        }
+    }).await
+}
 
-/// Login endpoint - validates credentials and returns JWT token
-pub async fn login(
-    login_request: web::Json<LoginRequest>,
+/// Exchange the refresh token cookie for a new access token, rotating the refresh token
+pub async fn refresh_session(
+    req: HttpRequest,
+    app_config: web::Data<AppConfig>,
+    users: web::Data<UserStore>,
+    refresh_tokens: web::Data<RefreshTokenStore>,
 ) -> Result<HttpResponse> {
-    let admin_user = get_admin_user();
+    let unauthorized = |message: &str| {
+        let mut response = AppError::Unauthorized(message.to_string()).error_response();
+        for cookie in clear_session_cookies(app_config.production) {
+            let _ = response.add_cookie(&cookie);
+        }
+        response
+    };
     
-    // Validate credentials
-    if login_request.username != admin_user.username {
-        return Ok(HttpResponse::Unauthorized().json(
-            ApiResponse::<()>::error("Invalid username or password")
-        ));
-    }
+    let presented = match req.cookie(REFRESH_COOKIE_NAME) {
+        Some(cookie) if !cookie.value().is_empty() => cookie.value().to_string(),
+        _ => return Ok(unauthorized("No refresh token")),
+    };
     
-    if !verify_password(&login_request.password, &admin_user.password_hash) {
-        return Ok(HttpResponse::Unauthorized().json(
-            ApiResponse::<()>::error("Invalid username or password")
-        ));
-    }
+    let (username, refresh_token, refresh_expires_at) = match refresh_tokens.rotate(&presented) {
+        Ok(rotated) => rotated,
+        Err(e) => match AppError::from_boxed(e) {
+            AppError::Unauthorized(message) => return Ok(unauthorized(&message)),
+            error => return Err(error.into()),
+        },
+    };
     
-    // Create JWT token
-    match create_jwt_token(&admin_user.username, &admin_user.role) {
-        Ok((token, expires_at)) => {
-            let response = LoginResponse {
-                token,
-                expires_at,
-            };
-            Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
-        }
-        Err(e) => {
-            eprintln!("JWT creation error: {}", e);
-            Ok(HttpResponse::InternalServerError().json(
-                ApiResponse::<()>::error("Failed to create authentication token")
-            ))
+    // Look the user up again so removed accounts and role changes take effect on refresh
+    let user = match users.find(&username) {
+        Some(user) => user,
+        None => {
+            let _ = refresh_tokens.revoke_family(&refresh_token);
+            return Ok(unauthorized("User no longer exists"));
         }
-    }
+    };
+    
+    Ok(session_response(&user, &refresh_token, refresh_expires_at, &app_config))
 }
 
 /// Verify token endpoint - checks if provided JWT token is valid
 pub async fn verify_token(
     req: actix_web::HttpRequest,
+    app_config: web::Data<AppConfig>,
+    revocations: web::Data<TokenRevocations>,
 ) -> Result<HttpResponse> {
     let auth_header = req.headers().get("authorization");
     
     if let Some(auth_value) = auth_header {
         if let Ok(auth_str) = auth_value.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let token = &auth_str[7..]; // Remove "Bearer " prefix
-                
-                match verify_jwt_token(token) {
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                match verify_jwt_token(&app_config.jwt_keys, &revocations, token) {
                     Ok(claims) => {
                         return Ok(HttpResponse::Ok().json(ApiResponse::success(claims)));
                     }
                     Err(e) => {
-                        eprintln!("Token verification error: {}", e);
-                        return Ok(HttpResponse::Unauthorized().json(
-                            ApiResponse::<()>::error("Invalid or expired token")
-                        ));
+                        warn!(error = %e, "Token verification failed");
+                        return Err(AppError::Unauthorized("Invalid or expired token".to_string()).into());
                     }
                 }
             }
         }
     }
     
-    Ok(HttpResponse::Unauthorized().json(
-        ApiResponse::<()>::error("Missing or invalid authorization header")
-    ))
+    Err(AppError::Unauthorized("Missing or invalid authorization header".to_string()).into())
+}
+
+/// Logout endpoint - revokes the presented token until it expires
+pub async fn logout(
+    req: HttpRequest,
+    app_config: web::Data<AppConfig>,
+    revocations: web::Data<TokenRevocations>,
+    refresh_tokens: web::Data<RefreshTokenStore>,
+) -> Result<HttpResponse> {
+    // A cookie session without its CSRF header is refused rather than half logged
+    // out, which would report success while the access token stays usable.
+    // Logging out with a missing or already invalid token is still a successful logout.
+    let claims = match extract_token_from_request(&req) {
+        Ok(claims) => Some(claims),
+        Err(AppError::Csrf) => return Err(AppError::Csrf.into()),
+        Err(_) => None,
+    };
+    
+    if let Some(cookie) = req.cookie(REFRESH_COOKIE_NAME) {
+        if let Err(e) = refresh_tokens.revoke_family(cookie.value()) {
+            return Err(AppError::Internal(format!("Failed to persist refresh token revocation: {}", e)).into());
+        }
+    }
+    
+    if let Some(claims) = claims {
+        if !claims.jti.is_empty() {
+            if let Err(e) = revocations.revoke(&claims.jti, claims.exp) {
+                return Err(AppError::Internal(format!("Failed to persist token revocation: {}", e)).into());
+            }
+        }
+    }
+    
+    let mut response = HttpResponse::Ok();
+    for cookie in clear_session_cookies(app_config.production) {
+        response.cookie(cookie);
+    }
+    Ok(response.json(ApiResponse::success("Logged out successfully")))
+}
+
+/// Revoke every issued token, logging out all sessions including the caller's
+pub async fn revoke_all_sessions(
+    req: HttpRequest,
+    revocations: web::Data<TokenRevocations>,
+    refresh_tokens: web::Data<RefreshTokenStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "revoke_all_sessions", AuditSubject::default(), async move {
+        // Check permission
+        require_permission(&req, Permission::ManageSessions)?;
+        
+        match revocations.revoke_all().and_then(|_| refresh_tokens.revoke_all()) {
+            Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse::success("All sessions logged out"))),
+            Err(e) => Err(AppError::Internal(format!("Failed to persist token revocation: {}", e)).into()),
+        }
+    }).await
+}
+
+// Audit log handlers
+
+/// Query the audit log, newest first
+pub async fn list_audit_events(
+    req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse> {
+    // Check permission
+    require_permission(&req, Permission::ViewAuditLog)?;
+    
+    let events = web::block(move || audit_log.query(&query).map_err(AppError::Io))
+        .await
+        .map_err(|e| AppError::Internal(format!("Audit query task failed: {}", e)))??;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(events)))
+}
+
+// API token handlers
+
+/// List API tokens (never their secrets)
+pub async fn list_api_tokens(
+    req: HttpRequest,
+    api_tokens: web::Data<ApiTokenStore>,
+) -> Result<HttpResponse> {
+    // Check permission
+    require_permission(&req, Permission::ManageApiTokens)?;
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::success(api_tokens.list())))
 }
 
-/// Logout endpoint - client-side token invalidation (server just confirms)
-pub async fn logout() -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().json(ApiResponse::success("Logged out successfully")))
+/// Create a named, scoped API token; the secret is only returned in this response
+pub async fn create_api_token(
+    req: HttpRequest,
+    create_request: web::Json<ApiTokenCreateRequest>,
+    api_tokens: web::Data<ApiTokenStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "create_api_token", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ManageApiTokens)?;
+        
+        match api_tokens.create(&create_request.name, &create_request.scopes, &claims.sub, create_request.expires_in_days) {
+            Ok((token, api_token)) => {
+                Ok(HttpResponse::Ok().json(ApiResponse::success(ApiTokenCreated {
+                    token,
+                    api_token,
+                })))
+            }
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+/// Revoke an API token
+pub async fn revoke_api_token(
+    req: HttpRequest,
+    revoke_request: web::Json<ApiTokenRevokeRequest>,
+    api_tokens: web::Data<ApiTokenStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "revoke_api_token", AuditSubject::default(), async move {
+        // Check permission
+        require_permission(&req, Permission::ManageApiTokens)?;
+        
+        match api_tokens.revoke(&revoke_request.id) {
+            Ok(api_token) => Ok(HttpResponse::Ok().json(ApiResponse::success(api_token))),
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+// Two-factor authentication handlers
+
+/// Two-factor status of the calling user
+pub async fn get_two_factor_status(
+    req: HttpRequest,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    // Check permission
+    let claims = require_permission(&req, Permission::ManageOwnAccount)?;
+    
+    let remaining = two_factor.remaining_recovery_codes(&claims.sub);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(TwoFactorStatus {
+        enabled: remaining.is_some(),
+        recovery_codes_remaining: remaining.unwrap_or(0),
+    })))
+}
+
+/// Start TOTP enrollment, returning the secret and otpauth URI for an authenticator app
+pub async fn enroll_two_factor(
+    req: HttpRequest,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "enroll_two_factor", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ManageOwnAccount)?;
+        
+        match two_factor.begin_enrollment(&claims.sub) {
+            Ok((secret, otpauth_uri)) => {
+                Ok(HttpResponse::Ok().json(ApiResponse::success(TwoFactorEnrollment {
+                    secret,
+                    otpauth_uri,
+                })))
+            }
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+/// Finish enrollment with a code from the authenticator app, returning recovery codes
+pub async fn confirm_two_factor(
+    req: HttpRequest,
+    code_request: web::Json<TwoFactorCodeRequest>,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "confirm_two_factor", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ManageOwnAccount)?;
+        
+        match two_factor.confirm_enrollment(&claims.sub, &code_request.code) {
+            Ok(recovery_codes) => {
+                Ok(HttpResponse::Ok().json(ApiResponse::success(RecoveryCodesResponse {
+                    recovery_codes,
+                })))
+            }
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+/// Replace recovery codes; requires a current TOTP or recovery code
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    code_request: web::Json<TwoFactorCodeRequest>,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "regenerate_recovery_codes", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ManageOwnAccount)?;
+        
+        match two_factor.verify_code(&claims.sub, &code_request.code) {
+            Ok(true) => {}
+            Ok(false) => return Err(AppError::BadRequest("Invalid authentication code".to_string()).into()),
+            Err(e) => return Err(AppError::Internal(format!("Two-factor verification failed: {}", e)).into()),
+        }
+        
+        match two_factor.regenerate_recovery_codes(&claims.sub) {
+            Ok(recovery_codes) => {
+                Ok(HttpResponse::Ok().json(ApiResponse::success(RecoveryCodesResponse {
+                    recovery_codes,
+                })))
+            }
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+/// Turn two-factor off; requires a current TOTP or recovery code
+pub async fn disable_two_factor(
+    req: HttpRequest,
+    code_request: web::Json<TwoFactorCodeRequest>,
+    two_factor: web::Data<TwoFactorStore>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "disable_two_factor", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ManageOwnAccount)?;
+        
+        match two_factor.verify_code(&claims.sub, &code_request.code) {
+            Ok(true) => {}
+            Ok(false) => return Err(AppError::BadRequest("Invalid authentication code".to_string()).into()),
+            Err(e) => return Err(AppError::Internal(format!("Two-factor verification failed: {}", e)).into()),
+        }
+        
+        match two_factor.disable(&claims.sub) {
+            Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse::success("Two-factor authentication disabled"))),
+            Err(e) => Err(AppError::Internal(format!("Failed to persist two-factor settings: {}", e)).into()),
+        }
+    }).await
 }
 
 // File Management handlers
@@ -340,33 +740,22 @@ pub async fn list_files(
     path: web::Path<String>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            let folder_path = path.into_inner();
-            
-            // Validate and create safe path
-            match create_safe_file_path(&app_config.content_path, &folder_path) {
-                Ok(safe_path) => {
-                    match list_directory_contents(&safe_path) {
-                        Ok(contents) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success(contents)))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Failed to list directory: {}", e))
-                            ))
-                        }
-                    }
-                }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
+    // Check permission
+    require_permission(&req, Permission::ContentRead)?;
+    
+    let folder_path = path.into_inner();
+    
+    // Validate and create safe path
+    match create_safe_file_path(&app_config.content_path, &folder_path) {
+        Ok(safe_path) => {
+            match list_directory_contents(&safe_path) {
+                Ok(contents) => {
+                    Ok(HttpResponse::Ok().json(ApiResponse::success(contents)))
                 }
+                Err(e) => Err(AppError::from_boxed(e).into()),
             }
         }
-        Err(response) => Ok(response),
+        Err(e) => Err(AppError::invalid("Invalid path", e).into()),
     }
 }
 
@@ -377,67 +766,71 @@ pub async fn upload_file(
     path: web::Path<String>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            let folder_path = path.into_inner();
-            
-            // Validate and create safe path
-            match create_safe_file_path(&app_config.content_path, &folder_path) {
-                Ok(safe_path) => {
-                    match handle_file_upload(&mut payload, &safe_path).await {
-                        Ok(filename) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success(format!("File '{}' uploaded successfully", filename))))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Upload failed: {}", e))
-                            ))
-                        }
-                    }
-                }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
-                }
+    audited(req.clone(), "upload", AuditSubject::path(&path), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        let folder_path = path.into_inner();
+        
+        authorize_paths(&claims, &[&folder_path])?;
+        
+        // Validate and create safe path
+        match create_safe_file_path(&app_config.content_path, &folder_path) {
+            Ok(safe_path) => {
+                let upload = stage_file_upload(&mut payload, &safe_path).await
+                    .map_err(AppError::from_boxed)?;
+                
+                let filenames = {
+                    let app_config = app_config.clone();
+                    let if_match = if_match_header(&req);
+                    web::block(move || {
+                        commit_upload_if_match(&app_config, &claims.sub, &folder_path, upload, if_match.as_deref())
+                    })
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Upload task failed: {}", e)))??
+                };
+        
+                let message = if filenames.len() == 1 {
+                    format!("File '{}' uploaded successfully", filenames[0])
+                } else {
+                    format!("{} files uploaded successfully", filenames.len())
+                };
+                Ok(HttpResponse::Ok().json(ApiResponse::success(message)))
             }
+            Err(e) => Err(AppError::invalid("Invalid path", e).into()),
         }
-        Err(response) => Ok(response),
-    }
+    }).await
 }
 
-/// Delete file or folder
+/// Delete file or folder by moving it to the trash
 pub async fn delete_file(
     req: HttpRequest,
     delete_request: web::Json<FileOperationRequest>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            match create_safe_file_path(&app_config.content_path, &delete_request.path) {
-                Ok(safe_path) => {
-                    match delete_file_or_folder(&safe_path) {
-                        Ok(_) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success("File/folder deleted successfully")))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Delete failed: {}", e))
-                            ))
+    audited(req.clone(), "delete", AuditSubject::path(&delete_request.path), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentDelete)?;
+        
+        authorize_paths(&claims, &[&delete_request.path])?;
+        
+        match create_safe_file_path(&app_config.content_path, &delete_request.path) {
+            Ok(_) => {
+                match move_to_trash(&app_config.content_path, &app_config.trash_path, &delete_request.path, &claims.sub) {
+                    Ok(item) => {
+                        // Recorded once the item is gone, so the revision keeps no snapshot:
+                        // the trash holds the content and its retention decides how long
+                        if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::Delete, &claims.sub, &item.original_path, None) {
+                            error!(path = %item.original_path, error = %e, "Failed to record history");
                         }
+                        Ok(HttpResponse::Ok().json(ApiResponse::success(item)))
                     }
-                }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
+                    Err(e) => Err(AppError::from_boxed(e).into()),
                 }
             }
+            Err(e) => Err(AppError::invalid("Invalid path", e).into()),
         }
-        Err(response) => Ok(response),
-    }
+    }).await
 }
 
 /// Rename file or folder
@@ -446,34 +839,30 @@ pub async fn rename_file(
     rename_request: web::Json<FileRenameRequest>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            match (
-                create_safe_file_path(&app_config.content_path, &rename_request.old_path),
-                create_safe_file_path(&app_config.content_path, &rename_request.new_path)
-            ) {
-                (Ok(old_safe_path), Ok(new_safe_path)) => {
-                    match rename_file_or_folder(&old_safe_path, &new_safe_path) {
-                        Ok(_) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success("File/folder renamed successfully")))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Rename failed: {}", e))
-                            ))
+    audited(req.clone(), "rename", AuditSubject::paths(&rename_request.old_path, &rename_request.new_path), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        authorize_paths(&claims, &[&rename_request.old_path, &rename_request.new_path])?;
+        
+        match (
+            create_safe_file_path(&app_config.content_path, &rename_request.old_path),
+            create_safe_file_path(&app_config.content_path, &rename_request.new_path)
+        ) {
+            (Ok(old_safe_path), Ok(new_safe_path)) => {
+                match rename_file_or_folder(&old_safe_path, &new_safe_path) {
+                    Ok(_) => {
+                        if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::Rename, &claims.sub, &rename_request.new_path, Some(&rename_request.old_path)) {
+                            error!(path = %rename_request.new_path, error = %e, "Failed to record history");
                         }
+                        Ok(HttpResponse::Ok().json(ApiResponse::success("File/folder renamed successfully")))
                     }
-                }
-                _ => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error("Invalid file paths")
-                    ))
+                    Err(e) => Err(AppError::from_boxed(e).into()),
                 }
             }
+            (Err(e), _) | (_, Err(e)) => Err(AppError::invalid("Invalid path", e).into()),
         }
-        Err(response) => Ok(response),
-    }
+    }).await
 }
 
 /// Move file or folder
@@ -482,34 +871,30 @@ pub async fn move_file(
     move_request: web::Json<FileMoveRequest>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            match (
-                create_safe_file_path(&app_config.content_path, &move_request.source_path),
-                create_safe_file_path(&app_config.content_path, &move_request.destination_path)
-            ) {
-                (Ok(source_safe_path), Ok(dest_safe_path)) => {
-                    match move_file_or_folder(&source_safe_path, &dest_safe_path) {
-                        Ok(_) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success("File/folder moved successfully")))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Move failed: {}", e))
-                            ))
+    audited(req.clone(), "move", AuditSubject::paths(&move_request.source_path, &move_request.destination_path), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        authorize_paths(&claims, &[&move_request.source_path, &move_request.destination_path])?;
+        
+        match (
+            create_safe_file_path(&app_config.content_path, &move_request.source_path),
+            create_safe_file_path(&app_config.content_path, &move_request.destination_path)
+        ) {
+            (Ok(source_safe_path), Ok(dest_safe_path)) => {
+                match move_file_or_folder(&source_safe_path, &dest_safe_path) {
+                    Ok(_) => {
+                        if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::Move, &claims.sub, &move_request.destination_path, Some(&move_request.source_path)) {
+                            error!(path = %move_request.destination_path, error = %e, "Failed to record history");
                         }
+                        Ok(HttpResponse::Ok().json(ApiResponse::success("File/folder moved successfully")))
                     }
-                }
-                _ => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error("Invalid file paths")
-                    ))
+                    Err(e) => Err(AppError::from_boxed(e).into()),
                 }
             }
+            (Err(e), _) | (_, Err(e)) => Err(AppError::invalid("Invalid path", e).into()),
         }
-        Err(response) => Ok(response),
-    }
+    }).await
 }
 
 /// Create new folder
@@ -518,31 +903,27 @@ pub async fn create_folder(
     folder_request: web::Json<FileOperationRequest>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            match create_safe_file_path(&app_config.content_path, &folder_request.path) {
-                Ok(safe_path) => {
-                    match create_directory(&safe_path) {
-                        Ok(_) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success("Folder created successfully")))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Folder creation failed: {}", e))
-                            ))
+    audited(req.clone(), "create_folder", AuditSubject::path(&folder_request.path), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        authorize_paths(&claims, &[&folder_request.path])?;
+        
+        match create_safe_file_path(&app_config.content_path, &folder_request.path) {
+            Ok(safe_path) => {
+                match create_directory(&safe_path) {
+                    Ok(_) => {
+                        if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::CreateFolder, &claims.sub, &folder_request.path, None) {
+                            error!(path = %folder_request.path, error = %e, "Failed to record history");
                         }
+                        Ok(HttpResponse::Ok().json(ApiResponse::success("Folder created successfully")))
                     }
-                }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
+                    Err(e) => Err(AppError::from_boxed(e).into()),
                 }
             }
+            Err(e) => Err(AppError::invalid("Invalid path", e).into()),
         }
-        Err(response) => Ok(response),
-    }
+    }).await
 }
 
 /// Download file
@@ -551,30 +932,19 @@ pub async fn download_file(
     path: web::Path<String>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            let file_path = path.into_inner();
-            
-            match create_safe_file_path(&app_config.content_path, &file_path) {
-                Ok(safe_path) => {
-                    match serve_file_download(&safe_path) {
-                        Ok(response) => Ok(response),
-                        Err(e) => {
-                            Ok(HttpResponse::NotFound().json(
-                                ApiResponse::<()>::error(&format!("File not found: {}", e))
-                            ))
-                        }
-                    }
-                }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
-                }
+    // Check permission
+    require_permission(&req, Permission::ContentRead)?;
+    
+    let file_path = path.into_inner();
+    
+    match create_safe_file_path(&app_config.content_path, &file_path) {
+        Ok(safe_path) => {
+            match serve_file_download(&safe_path) {
+                Ok(response) => Ok(response),
+                Err(e) => Err(AppError::from_boxed(e).into()),
             }
         }
-        Err(response) => Ok(response),
+        Err(e) => Err(AppError::invalid("Invalid path", e).into()),
     }
 }
 
@@ -584,64 +954,52 @@ pub async fn read_file_content(
     path: web::Path<String>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            let file_path = path.into_inner();
-            
-            match create_safe_file_path(&app_config.content_path, &file_path) {
-                Ok(safe_path) => {
-                    match std::fs::read_to_string(&safe_path) {
-                        Ok(content) => {
-                            // Check if file is text-based
-                            let file_ext = std::path::Path::new(&safe_path)
-                                .extension()
-                                .and_then(|ext| ext.to_str())
-                                .unwrap_or("")
-                                .to_lowercase();
-                            
-                            let is_text_file = matches!(file_ext.as_str(), 
-                                "txt" | "md" | "markdown" | "json" | "yaml" | "yml" | 
-                                "js" | "ts" | "html" | "css" | "scss" | "rs" | "py" | 
-                                "toml" | "env" | "log" | "cfg" | "conf" | "xml"
-                            );
-                            
-                            if is_text_file {
-                                let response_data = serde_json::json!({
-                                    "content": content,
-                                    "file_type": file_ext,
-                                    "is_editable": matches!(file_ext.as_str(), 
-                                        "txt" | "md" | "markdown" | "json" | "yaml" | "yml"
-                                    )
-                                });
-                                Ok(HttpResponse::Ok().json(ApiResponse::success(response_data)))
-                            } else {
-                                Ok(HttpResponse::BadRequest().json(
-                                    ApiResponse::<()>::error("File type not supported for viewing")
-                                ))
-                            }
-                        }
-                        Err(e) => {
-                            if e.kind() == std::io::ErrorKind::InvalidData {
-                                Ok(HttpResponse::BadRequest().json(
-                                    ApiResponse::<()>::error("File contains non-text data")
-                                ))
-                            } else {
-                                Ok(HttpResponse::NotFound().json(
-                                    ApiResponse::<()>::error(&format!("Could not read file: {}", e))
-                                ))
-                            }
-                        }
+    // Check permission
+    require_permission(&req, Permission::ContentRead)?;
+    
+    let file_path = path.into_inner();
+    
+    match create_safe_file_path(&app_config.content_path, &file_path) {
+        Ok(safe_path) => {
+            match std::fs::read_to_string(&safe_path) {
+                Ok(content) => {
+                    // Check if file is text-based
+                    let file_ext = std::path::Path::new(&safe_path)
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .unwrap_or("")
+                        .to_lowercase();
+    
+                    let is_text_file = matches!(file_ext.as_str(), 
+                        "txt" | "md" | "markdown" | "json" | "yaml" | "yml" | 
+                        "js" | "ts" | "html" | "css" | "scss" | "rs" | "py" | 
+                        "toml" | "env" | "log" | "cfg" | "conf" | "xml"
+                    );
+    
+                    if is_text_file {
+                        let etag = content_etag(content.as_bytes());
+                        let response_data = serde_json::json!({
+                            "content": content,
+                            "etag": etag,
+                            "file_type": file_ext,
+                            "is_editable": matches!(file_ext.as_str(), 
+                                "txt" | "md" | "markdown" | "json" | "yaml" | "yml"
+                            )
+                        });
+                        Ok(HttpResponse::Ok()
+                            .insert_header(("ETag", etag))
+                            .json(ApiResponse::success(response_data)))
+                    } else {
+                        Err(AppError::BadRequest("File type not supported for viewing".to_string()).into())
                     }
                 }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    Err(AppError::BadRequest("File contains non-text data".to_string()).into())
                 }
+                Err(e) => Err(AppError::Io(e).into()),
             }
         }
-        Err(response) => Ok(response),
+        Err(e) => Err(AppError::invalid("Invalid path", e).into()),
     }
 }
 
@@ -652,48 +1010,238 @@ pub async fn save_file_content(
     body: web::Json<FileContentRequest>,
     app_config: web::Data<AppConfig>,
 ) -> Result<HttpResponse> {
-    // Check admin authentication
-    match check_admin_auth(&req) {
-        Ok(_) => {
-            let file_path = path.into_inner();
-            
-            match create_safe_file_path(&app_config.content_path, &file_path) {
-                Ok(safe_path) => {
-                    // Check if file is editable
-                    let file_ext = std::path::Path::new(&safe_path)
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("")
-                        .to_lowercase();
-                    
-                    let is_editable = matches!(file_ext.as_str(), 
-                        "txt" | "md" | "markdown" | "json" | "yaml" | "yml"
-                    );
-                    
-                    if !is_editable {
-                        return Ok(HttpResponse::BadRequest().json(
-                            ApiResponse::<()>::error("File type not editable")
-                        ));
-                    }
-                    
-                    match std::fs::write(&safe_path, &body.content) {
-                        Ok(_) => {
-                            Ok(HttpResponse::Ok().json(ApiResponse::success("File saved successfully")))
-                        }
-                        Err(e) => {
-                            Ok(HttpResponse::InternalServerError().json(
-                                ApiResponse::<()>::error(&format!("Could not save file: {}", e))
-                            ))
-                        }
+    audited(req.clone(), "save", AuditSubject::path(&path), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        let file_path = path.into_inner();
+        
+        authorize_paths(&claims, &[&file_path])?;
+        
+        match create_safe_file_path(&app_config.content_path, &file_path) {
+            Ok(safe_path) => {
+                // Check if file is editable
+                let file_ext = std::path::Path::new(&safe_path)
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .unwrap_or("")
+                    .to_lowercase();
+        
+                let is_editable = matches!(file_ext.as_str(), 
+                    "txt" | "md" | "markdown" | "json" | "yaml" | "yml"
+                );
+        
+                if !is_editable {
+                    return Err(AppError::BadRequest("File type not editable".to_string()).into());
+                }
+        
+                // Optimistic concurrency: the client must prove it saw the current version
+                let if_match = if_match_header(&req);
+                let etag = content_etag(body.content.as_bytes());
+        
+                let outcome = {
+                    let app_config = app_config.clone();
+                    let if_match = if_match.clone();
+                    let content = body.into_inner().content;
+                    web::block(move || {
+                        save_if_match(&app_config, &claims.sub, &file_path, &safe_path, &content, if_match.as_deref())
+                    })
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Save task failed: {}", e)))??
+                };
+        
+                match outcome {
+                    SaveOutcome::Saved => Ok(HttpResponse::Ok()
+                        .insert_header(("ETag", etag))
+                        .json(ApiResponse::success("File saved successfully"))),
+                    SaveOutcome::Conflict { current, etag } => {
+                        let (mut builder, code, message) = match if_match {
+                            None => (
+                                HttpResponse::PreconditionRequired(),
+                                "precondition_required",
+                                "If-Match header required when saving an existing file",
+                            ),
+                            Some(_) => (HttpResponse::Conflict(), "conflict", "File was modified by someone else"),
+                        };
+                        let current_version = serde_json::json!({
+                            "content": String::from_utf8_lossy(&current),
+                            "etag": etag,
+                        });
+                        let response = ApiResponse {
+                            success: false,
+                            data: Some(current_version),
+                            message: message.to_string(),
+                            code: Some(code.to_string()),
+                        };
+                        Ok(builder.insert_header(("ETag", etag)).json(response))
                     }
                 }
-                Err(e) => {
-                    Ok(HttpResponse::BadRequest().json(
-                        ApiResponse::<()>::error(&format!("Invalid path: {}", e))
-                    ))
+            }
+            Err(e) => Err(AppError::invalid("Invalid path", e).into()),
+        }
+    }).await
+}
+
+// Content history handlers
+
+/// List recorded revisions, optionally filtered to a single path
+pub async fn list_history(
+    req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    // Check permission
+    require_permission(&req, Permission::ContentRead)?;
+    
+    match list_revisions(&app_config.history_path(), query.path.as_deref()) {
+        Ok(revisions) => Ok(HttpResponse::Ok().json(ApiResponse::success(revisions))),
+        Err(e) => Err(AppError::from_boxed(e).into()),
+    }
+}
+
+/// Diff two revisions, or a revision against the current file
+pub async fn diff_history(
+    req: HttpRequest,
+    query: web::Query<HistoryDiffQuery>,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    // Check permission
+    require_permission(&req, Permission::ContentRead)?;
+    
+    match diff_revisions(&app_config.content_path, &app_config.history_path(), &query.from, query.to.as_deref()) {
+        Ok(diff) => Ok(HttpResponse::Ok().json(ApiResponse::success(diff))),
+        Err(e) => Err(AppError::from_boxed(e).into()),
+    }
+}
+
+/// Restore a file or folder to the state captured by a revision
+pub async fn restore_history(
+    req: HttpRequest,
+    restore_request: web::Json<RevisionRestoreRequest>,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "restore_history", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        let revision = get_revision(&app_config.history_path(), &restore_request.revision_id)
+            .map_err(AppError::from_boxed)?;
+        authorize_paths(&claims, &[&revision.path])?;
+        
+        // Restoring overwrites the current version, so it is guarded like a save
+        let restored = {
+            let lock = save_lock(&revision.path);
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            let target = std::path::Path::new(&app_config.content_path).join(normalize_relative_path(&revision.path));
+            require_if_match(&target, if_match_header(&req).as_deref())?;
+            restore_revision(&app_config.content_path, &app_config.history_path(), &restore_request.revision_id, &claims.sub)
+        };
+        
+        match restored {
+            Ok(revision) => Ok(HttpResponse::Ok().json(ApiResponse::success(revision))),
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+// Trash handlers
+
+/// List items in the trash
+pub async fn list_trash_items(
+    req: HttpRequest,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    // Check permission
+    require_permission(&req, Permission::ContentRead)?;
+    
+    if let Err(e) = purge_expired_trash(&app_config.trash_path, app_config.trash_retention_days) {
+        error!(error = %e, "Failed to apply trash retention");
+    }
+    
+    match list_trash(&app_config.trash_path) {
+        Ok(items) => Ok(HttpResponse::Ok().json(ApiResponse::success(items))),
+        Err(e) => Err(AppError::from_boxed(e).into()),
+    }
+}
+
+/// Restore an item from the trash into the content directory
+pub async fn restore_trash_item(
+    req: HttpRequest,
+    restore_request: web::Json<TrashRestoreRequest>,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "restore_trash", AuditSubject::target(restore_request.destination_path.as_deref()), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentWrite)?;
+        
+        // Validate the destination like any other file manager path
+        let destination = restore_request.destination_path.as_deref();
+        if let Some(destination) = destination {
+            if let Err(e) = create_safe_file_path(&app_config.content_path, destination) {
+                return Err(AppError::invalid("Invalid path", e).into());
+            }
+        }
+        
+        if let Ok(item) = get_trash_item(&app_config.trash_path, &restore_request.id) {
+            authorize_paths(&claims, &[destination.unwrap_or(&item.original_path)])?;
+        }
+        
+        match restore_from_trash(&app_config.content_path, &app_config.trash_path, &restore_request.id, destination) {
+            Ok(item) => {
+                if let Err(e) = record_revision(&app_config.content_path, &app_config.history_path(), RevisionAction::Restore, &claims.sub, &item.original_path, None) {
+                    error!(path = %item.original_path, error = %e, "Failed to record history");
                 }
+                Ok(HttpResponse::Ok().json(ApiResponse::success(item)))
             }
+            Err(e) => Err(AppError::from_boxed(e).into()),
+        }
+    }).await
+}
+
+/// Permanently delete one item from the trash, or empty it when no id is given
+pub async fn purge_trash(
+    req: HttpRequest,
+    purge_request: web::Json<TrashPurgeRequest>,
+    app_config: web::Data<AppConfig>,
+) -> Result<HttpResponse> {
+    audited(req.clone(), "purge_trash", AuditSubject::default(), async move {
+        // Check permission
+        let claims = require_permission(&req, Permission::ContentDelete)?;
+        
+        // Purging a protected entry loses it for good, so it needs the same
+        // permission as modifying it in place
+        let items = match &purge_request.id {
+            Some(id) => get_trash_item(&app_config.trash_path, id).map(|item| vec![item]),
+            None => list_trash(&app_config.trash_path),
+        }.map_err(AppError::from_boxed)?;
+        let original_paths: Vec<&str> = items.iter().map(|item| item.original_path.as_str()).collect();
+        authorize_paths(&claims, &original_paths)?;
+        
+        let result = match &purge_request.id {
+            Some(id) => purge_trash_item(&app_config.trash_path, id).map(|_| 1),
+            None => purge_all_trash(&app_config.trash_path),
+        };
+        
+        match result {
+            Ok(count) => Ok(HttpResponse::Ok().json(ApiResponse::success(format!("{} item(s) purged", count)))),
+            Err(e) => Err(AppError::from_boxed(e).into()),
         }
-        Err(response) => Ok(response),
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_of_the_same_file_share_a_lock() {
+        let lock = save_lock("blog/post.md");
+        assert!(Arc::ptr_eq(&lock, &save_lock("/blog//./post.md")));
+        assert!(!Arc::ptr_eq(&lock, &save_lock("blog/other.md")));
+
+        // Locks nobody holds are dropped on the next save
+        drop(lock);
+        save_lock("blog/other.md");
+        assert!(!SAVE_LOCKS.lock().unwrap().contains_key("blog/post.md"));
     }
-}
\ No newline at end of file
+}
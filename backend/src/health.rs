@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::models::*;
+use crate::utils::load_github_config;
+use crate::AppConfig;
+
+/// Seconds in-flight requests get to finish once shutdown starts
+pub const SHUTDOWN_TIMEOUT_SECONDS: u64 = 30;
+
+/// Outcome of one readiness check
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Body of the readiness endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<HealthCheck>,
+}
+
+/// Whether the startup GitHub cache warm-up has run
+///
+/// The warm-up counts as done even if GitHub was unreachable: projects missing
+/// from the cache are then served with placeholder details, which is better
+/// than never becoming ready.
+#[derive(Debug, Default)]
+pub struct CacheWarmup {
+    finished: AtomicBool,
+}
+
+impl CacheWarmup {
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Release);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+fn check(name: &'static str, result: Result<String, String>) -> HealthCheck {
+    match result {
+        Ok(detail) => HealthCheck { name, ok: true, detail },
+        Err(detail) => HealthCheck { name, ok: false, detail },
+    }
+}
+
+/// Check the server can serve content: the content path is readable,
+/// `github/config.yaml` parses and the GitHub cache is warm
+///
+/// The cache is warm once `warmup` has finished, or when every configured
+/// repository is cached if there is no warm-up to wait for.
+pub fn check_readiness(
+    app_config: &AppConfig,
+    github_cache: &Mutex<HashMap<String, CachedGithubProject>>,
+    warmup: Option<&CacheWarmup>,
+) -> Readiness {
+    let content = fs::read_dir(&app_config.content_path)
+        .map(|_| "readable".to_string())
+        .map_err(|e| format!("cannot read content directory: {}", e));
+
+    let has_github_config = Path::new(&app_config.content_path).join("github/config.yaml").is_file();
+    let github_config = if has_github_config {
+        load_github_config(&app_config.content_path).map_err(|e| format!("invalid github/config.yaml: {}", e))
+    } else {
+        Ok(GitHubConfig { repositories: Vec::new() })
+    };
+
+    let caches = match &github_config {
+        Ok(config) => {
+            let cache = github_cache.lock().unwrap();
+            let cached = config
+                .repositories
+                .iter()
+                .filter(|repo| cache.contains_key(&format!("{}/{}", repo.owner, repo.repo)))
+                .count();
+            let detail = format!("{} of {} repositories cached", cached, config.repositories.len());
+            if warmup.is_some_and(CacheWarmup::is_finished) || cached == config.repositories.len() {
+                Ok(detail)
+            } else {
+                Err(format!("warming up, {}", detail))
+            }
+        }
+        Err(_) => Err("GitHub config unavailable".to_string()),
+    };
+
+    let checks = vec![
+        check("content_path", content),
+        check(
+            "github_config",
+            github_config.map(|_| if has_github_config { "parsed" } else { "not configured" }.to_string()),
+        ),
+        check("caches", caches),
+    ];
+    Readiness {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A content root configuring the `octo/widget` repository, removed with the `TempDir`
+    fn readiness_config() -> (TempDir, AppConfig) {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/github", content_path)).unwrap();
+        fs::write(
+            format!("{}/github/config.yaml", content_path),
+            "repositories:\n  - owner: octo\n    repo: widget\n    display_name: Widget\n",
+        ).unwrap();
+        let mut config = AppConfig::from_sources(&crate::config::ConfigFile::default(), &|_| None).unwrap();
+        config.content_path = content_path.to_string();
+        (content_dir, config)
+    }
+
+    fn cached_widget() -> CachedGithubProject {
+        CachedGithubProject {
+            project: GitHubProject {
+                owner: "octo".to_string(),
+                repo: "widget".to_string(),
+                display_name: "Widget".to_string(),
+                readme_html: String::new(),
+                url: "https://github.com/octo/widget".to_string(),
+                stars: 0,
+                forks: 0,
+                description: None,
+                feature: None,
+                image: None,
+            },
+            cached_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn without_a_warmup_every_repository_must_be_cached() {
+        let (_content_path, config) = readiness_config();
+        let github_cache = Mutex::new(HashMap::new());
+        assert!(!check_readiness(&config, &github_cache, None).ready);
+
+        github_cache.lock().unwrap().insert("octo/widget".to_string(), cached_widget());
+        assert!(check_readiness(&config, &github_cache, None).ready);
+    }
+
+    #[test]
+    fn a_broken_github_config_is_not_ready() {
+        let (_content_path, config) = readiness_config();
+        let warmup = CacheWarmup::default();
+        warmup.finish();
+        fs::write(format!("{}/github/config.yaml", config.content_path), "repositories: [").unwrap();
+
+        let report = check_readiness(&config, &Mutex::new(HashMap::new()), Some(&warmup));
+        assert!(!report.ready);
+        assert!(report.checks.iter().any(|check| check.name == "github_config" && !check.ok));
+    }
+
+    #[test]
+    fn an_unreadable_content_path_is_not_ready() {
+        let (_content_path, mut config) = readiness_config();
+        let warmup = CacheWarmup::default();
+        warmup.finish();
+        config.content_path = format!("{}/missing", config.content_path);
+
+        let report = check_readiness(&config, &Mutex::new(HashMap::new()), Some(&warmup));
+        assert!(!report.ready);
+        assert!(report.checks.iter().any(|check| check.name == "content_path" && !check.ok));
+    }
+}
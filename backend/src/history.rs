@@ -0,0 +1,420 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use crate::error::AppError;
+use crate::models::*;
+use crate::utils::{temp_path_for, write_file_atomic};
+
+/// Directory under the data path that holds revision history
+///
+/// Kept out of the content path, which production mounts read-only.
+pub const HISTORY_DIR: &str = "history";
+
+fn history_index(history_path: &str) -> PathBuf {
+    Path::new(history_path).join("index.jsonl")
+}
+
+fn history_blob(history_path: &str, revision_id: &str) -> PathBuf {
+    Path::new(history_path).join("blobs").join(revision_id)
+}
+
+/// Normalize a relative content path so revisions of the same file share one key
+pub fn normalize_relative_path(relative_path: &str) -> String {
+    relative_path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Revisions always name something inside the content root; a revision of the
+/// root itself would snapshot the history into itself and restore over everything
+fn require_revision_path(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if path.is_empty() {
+        return Err(AppError::BadRequest("The content root has no revision history".to_string()).into());
+    }
+    Ok(())
+}
+
+fn new_revision_id() -> String {
+    let uuid = uuid::Uuid::new_v4().simple().to_string();
+    format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%6fZ"), &uuid[..8])
+}
+
+/// Copy a file or a whole directory tree
+pub fn copy_recursively(source: &Path, destination: &Path) -> Result<(), std::io::Error> {
+    if source.is_dir() {
+        fs::create_dir_all(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+    } else {
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source, destination)?;
+    }
+    Ok(())
+}
+
+/// Record a revision for `relative_path`, snapshotting whatever currently exists there
+///
+/// Actions are recorded after they succeed. A delete moves the item to the
+/// trash first, so its revision has no snapshot and the deleted content is
+/// only kept as long as the trash retention allows. Actions that change no
+/// content only record the path, so renaming a folder does not copy it.
+pub fn record_revision(
+    content_path: &str,
+    history_path: &str,
+    action: RevisionAction,
+    user: &str,
+    relative_path: &str,
+    previous_path: Option<&str>,
+) -> Result<Revision, Box<dyn std::error::Error>> {
+    let path = normalize_relative_path(relative_path);
+    require_revision_path(&path)?;
+    let full_path = Path::new(content_path).join(&path);
+    let id = new_revision_id();
+
+    let is_directory = full_path.is_dir();
+    let has_snapshot = full_path.exists() && action.changes_content();
+    if has_snapshot {
+        copy_recursively(&full_path, &history_blob(history_path, &id))?;
+    }
+
+    let revision = Revision {
+        id,
+        timestamp: Utc::now(),
+        user: user.to_string(),
+        action,
+        path,
+        previous_path: previous_path.map(normalize_relative_path),
+        is_directory,
+        has_snapshot,
+    };
+
+    fs::create_dir_all(history_path)?;
+    let mut index = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_index(history_path))?;
+    writeln!(index, "{}", serde_json::to_string(&revision)?)?;
+
+    Ok(revision)
+}
+
+/// Record the current state of a file as a baseline if it has no history yet,
+/// so the first admin edit of an existing file can still be undone
+pub fn ensure_baseline_revision(
+    content_path: &str,
+    history_path: &str,
+    user: &str,
+    relative_path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = normalize_relative_path(relative_path);
+    require_revision_path(&path)?;
+    if !Path::new(content_path).join(&path).exists() {
+        return Ok(());
+    }
+
+    let has_history = list_revisions(history_path, Some(&path))?
+        .iter()
+        .any(|revision| revision.has_snapshot);
+    if !has_history {
+        record_revision(content_path, history_path, RevisionAction::Baseline, user, &path, None)?;
+    }
+
+    Ok(())
+}
+
+/// List revisions, newest first, optionally only those touching `relative_path`
+pub fn list_revisions(history_path: &str, relative_path: Option<&str>) -> Result<Vec<Revision>, Box<dyn std::error::Error>> {
+    let index_path = history_index(history_path);
+    if !index_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let filter = relative_path.map(normalize_relative_path);
+    let mut revisions = Vec::new();
+
+    for line in fs::read_to_string(index_path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let revision: Revision = serde_json::from_str(line)?;
+
+        if let Some(filter) = &filter {
+            let matches = |path: &str| path == filter || path.starts_with(&format!("{}/", filter));
+            if !matches(&revision.path) && !revision.previous_path.as_deref().is_some_and(matches) {
+                continue;
+            }
+        }
+
+        revisions.push(revision);
+    }
+
+    revisions.reverse();
+    Ok(revisions)
+}
+
+/// Look up a single revision by id
+pub fn get_revision(history_path: &str, revision_id: &str) -> Result<Revision, Box<dyn std::error::Error>> {
+    list_revisions(history_path, None)?
+        .into_iter()
+        .find(|revision| revision.id == revision_id)
+        .ok_or_else(|| AppError::NotFound(format!("Revision '{}' not found", revision_id)).into())
+}
+
+fn read_revision_text(history_path: &str, revision: &Revision) -> Result<String, Box<dyn std::error::Error>> {
+    if !revision.has_snapshot {
+        // The item did not exist after this revision
+        return Ok(String::new());
+    }
+    if revision.is_directory {
+        return Err(AppError::BadRequest("Only file revisions can be compared".to_string()).into());
+    }
+    Ok(fs::read_to_string(history_blob(history_path, &revision.id))?)
+}
+
+/// Produce a unified diff between two revisions, or between a revision and
+/// the current file when `to_revision_id` is None
+pub fn diff_revisions(
+    content_path: &str,
+    history_path: &str,
+    from_revision_id: &str,
+    to_revision_id: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let from = get_revision(history_path, from_revision_id)?;
+    let from_text = read_revision_text(history_path, &from)?;
+
+    let (to_label, to_text) = match to_revision_id {
+        Some(to_id) => {
+            let to = get_revision(history_path, to_id)?;
+            (format!("{} ({})", to.path, to.id), read_revision_text(history_path, &to)?)
+        }
+        None => {
+            let current = Path::new(content_path).join(&from.path);
+            let text = if current.is_file() { fs::read_to_string(&current)? } else { String::new() };
+            (format!("{} (current)", from.path), text)
+        }
+    };
+
+    let diff = similar::TextDiff::from_lines(&from_text, &to_text);
+    Ok(diff
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("{} ({})", from.path, from.id), &to_label)
+        .to_string())
+}
+
+/// Restore the snapshot of a revision to its path, recording the restore itself
+pub fn restore_revision(
+    content_path: &str,
+    history_path: &str,
+    revision_id: &str,
+    user: &str,
+) -> Result<Revision, Box<dyn std::error::Error>> {
+    let revision = get_revision(history_path, revision_id)?;
+    if !revision.has_snapshot {
+        return Err(AppError::BadRequest("Revision has no content to restore".to_string()).into());
+    }
+    require_revision_path(&normalize_relative_path(&revision.path))?;
+
+    let target = Path::new(content_path).join(&revision.path);
+    ensure_baseline_revision(content_path, history_path, user, &revision.path)?;
+
+    let blob = history_blob(history_path, &revision.id);
+    if blob.is_file() && !target.is_dir() {
+        write_file_atomic(&target.to_string_lossy(), &fs::read(&blob)?)?;
+    } else {
+        replace_tree(&blob, &target)?;
+    }
+
+    record_revision(content_path, history_path, RevisionAction::Restore, user, &revision.path, None)
+}
+
+/// Replace `target` with a copy of `source`, leaving `target` untouched if the copy fails
+///
+/// The copy is built in a temp sibling; the old target is only moved aside and
+/// removed once the copy is complete, and is moved back if the swap fails.
+fn replace_tree(source: &Path, target: &Path) -> Result<(), std::io::Error> {
+    let staged = temp_path_for(target);
+    if let Err(e) = copy_recursively(source, &staged) {
+        let _ = remove_path(&staged);
+        return Err(e);
+    }
+
+    if !target.exists() {
+        return fs::rename(&staged, target).inspect_err(|_| {
+            let _ = remove_path(&staged);
+        });
+    }
+
+    let replaced = temp_path_for(target);
+    if let Err(e) = fs::rename(target, &replaced) {
+        let _ = remove_path(&staged);
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(&staged, target) {
+        let _ = fs::rename(&replaced, target);
+        let _ = remove_path(&staged);
+        return Err(e);
+    }
+    remove_path(&replaced)
+}
+
+fn remove_path(path: &Path) -> Result<(), std::io::Error> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn renames_and_moves_record_the_path_only() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let history_dir = TempDir::new().unwrap();
+        let history_path = history_dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+        fs::write(format!("{}/blog/post.md", content_path), "post").unwrap();
+
+        for action in [RevisionAction::Rename, RevisionAction::Move, RevisionAction::CreateFolder] {
+            let revision = record_revision(content_path, history_path, action, "admin", "blog", Some("old")).unwrap();
+            assert!(!revision.has_snapshot);
+            assert!(revision.is_directory);
+            assert!(!history_blob(history_path, &revision.id).exists());
+        }
+
+        let revision = record_revision(content_path, history_path, RevisionAction::Save, "admin", "blog/post.md", None).unwrap();
+        assert!(revision.has_snapshot);
+    }
+
+    #[test]
+    fn paths_of_the_same_file_share_a_key() {
+        assert_eq!(normalize_relative_path("/blog//./post.md"), "blog/post.md");
+        assert_eq!(normalize_relative_path("./"), "");
+    }
+
+    #[test]
+    fn saves_diff_against_the_baseline() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let history_dir = TempDir::new().unwrap();
+        let history_path = history_dir.path().to_str().unwrap();
+        let file_path = format!("{}/post.md", content_path);
+        fs::write(&file_path, "first line\n").unwrap();
+
+        // The first save keeps the original version as a baseline
+        ensure_baseline_revision(content_path, history_path, "admin", "post.md").unwrap();
+        fs::write(&file_path, "second line\n").unwrap();
+        record_revision(content_path, history_path, RevisionAction::Save, "admin", "/post.md", None).unwrap();
+
+        let revisions = list_revisions(history_path, Some("post.md")).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].action, RevisionAction::Save);
+        assert_eq!(revisions[1].action, RevisionAction::Baseline);
+
+        let diff = diff_revisions(content_path, history_path, &revisions[1].id, Some(&revisions[0].id)).unwrap();
+        assert!(diff.contains("-first line"));
+        assert!(diff.contains("+second line"));
+    }
+
+    #[test]
+    fn deleted_files_restore_from_earlier_revisions() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let history_dir = TempDir::new().unwrap();
+        let history_path = history_dir.path().to_str().unwrap();
+        let file_path = format!("{}/post.md", content_path);
+        fs::write(&file_path, "first line\n").unwrap();
+        ensure_baseline_revision(content_path, history_path, "admin", "post.md").unwrap();
+        fs::write(&file_path, "second line\n").unwrap();
+        let saved = record_revision(content_path, history_path, RevisionAction::Save, "admin", "post.md", None).unwrap();
+        let baseline = list_revisions(history_path, Some("post.md")).unwrap().remove(1);
+
+        // Deletes keep no snapshot of their own
+        fs::remove_file(&file_path).unwrap();
+        let deleted = record_revision(content_path, history_path, RevisionAction::Delete, "admin", "post.md", None).unwrap();
+        assert!(!deleted.has_snapshot);
+        assert!(restore_revision(content_path, history_path, &deleted.id, "admin").is_err());
+
+        restore_revision(content_path, history_path, &saved.id, "admin").unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "second line\n");
+        restore_revision(content_path, history_path, &baseline.id, "admin").unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "first line\n");
+    }
+
+    #[test]
+    fn folders_restore_as_a_whole_and_failed_restores_change_nothing() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let history_dir = TempDir::new().unwrap();
+        let history_path = history_dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+        fs::write(format!("{}/blog/post.md", content_path), "post").unwrap();
+        let snapshot = record_revision(content_path, history_path, RevisionAction::Upload, "admin", "blog", None).unwrap();
+        fs::write(format!("{}/blog/post.md", content_path), "edited").unwrap();
+        fs::write(format!("{}/blog/new.md", content_path), "new").unwrap();
+
+        restore_revision(content_path, history_path, &snapshot.id, "admin").unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/blog/post.md", content_path)).unwrap(), "post");
+        assert!(!Path::new(&format!("{}/blog/new.md", content_path)).exists());
+
+        // A snapshot that cannot be copied leaves the current folder in place
+        fs::write(format!("{}/blog/post.md", content_path), "current").unwrap();
+        fs::remove_dir_all(history_blob(history_path, &snapshot.id)).unwrap();
+        assert!(restore_revision(content_path, history_path, &snapshot.id, "admin").is_err());
+        assert_eq!(fs::read_to_string(format!("{}/blog/post.md", content_path)).unwrap(), "current");
+        let names: Vec<_> = fs::read_dir(content_path).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names.len(), 1, "only blog remains: {:?}", names);
+    }
+
+    #[test]
+    fn the_content_root_is_never_recorded() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let history_dir = TempDir::new().unwrap();
+        let history_path = history_dir.path().to_str().unwrap();
+
+        assert!(record_revision(content_path, history_path, RevisionAction::Save, "admin", "./", None).is_err());
+        assert!(ensure_baseline_revision(content_path, history_path, "admin", "/").is_err());
+        assert!(list_revisions(history_path, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn root_revisions_are_never_restored() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let history_dir = TempDir::new().unwrap();
+        let history_path = history_dir.path().to_str().unwrap();
+        fs::write(format!("{}/post.md", content_path), "post").unwrap();
+
+        // A root revision in the index can never be restored over the content
+        let revision = Revision {
+            id: "20250101T000000000000Z-deadbeef".to_string(),
+            timestamp: Utc::now(),
+            user: "admin".to_string(),
+            action: RevisionAction::CreateFolder,
+            path: String::new(),
+            previous_path: None,
+            is_directory: true,
+            has_snapshot: true,
+        };
+        fs::create_dir_all(history_blob(history_path, &revision.id)).unwrap();
+        fs::write(
+            history_index(history_path),
+            format!("{}\n", serde_json::to_string(&revision).unwrap()),
+        ).unwrap();
+        assert!(restore_revision(content_path, history_path, &revision.id, "admin").is_err());
+        assert_eq!(fs::read_to_string(format!("{}/post.md", content_path)).unwrap(), "post");
+    }
+}
@@ -1,39 +1,43 @@
-use std::env;
-
+pub mod api_tokens;
+pub mod audit;
+pub mod cli;
+pub mod config;
+pub mod error;
+pub mod export;
 pub mod handlers;
+pub mod health;
+pub mod history;
+pub mod logging;
+pub mod metrics;
+pub mod middleware;
 pub mod models;
+pub mod permissions;
+pub mod security_headers;
+pub mod sessions;
+pub mod shortcodes;
+pub mod throttle;
+pub mod totp;
+pub mod trash;
 pub mod utils;
 
+pub use api_tokens::*;
+pub use audit::*;
+pub use cli::*;
+pub use config::*;
+pub use error::*;
+pub use export::*;
 pub use handlers::*;
+pub use health::*;
+pub use history::*;
+pub use logging::*;
+pub use metrics::*;
+pub use middleware::*;
 pub use models::*;
+pub use permissions::*;
+pub use security_headers::*;
+pub use sessions::*;
+pub use shortcodes::*;
+pub use throttle::*;
+pub use totp::*;
+pub use trash::*;
 pub use utils::*;
-
-#[derive(Clone)]
-pub struct AppConfig {
-    pub host: String,
-    pub port: u16,
-    pub content_path: String,
-    pub frontend_path: String,
-    pub frontend_url: String,
-}
-
-impl AppConfig {
-    pub fn from_env() -> Result<Self, String> {
-        let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-        let port = env::var("PORT")
-            .unwrap_or_else(|_| "4000".to_string())
-            .parse()
-            .map_err(|_| "Invalid PORT value")?;
-        let content_path = env::var("CONTENT_PATH").unwrap_or_else(|_| "../content".to_string());
-        let frontend_path = env::var("FRONTEND_PATH").unwrap_or_else(|_| "../frontend".to_string());
-        let frontend_url = env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
-
-        Ok(AppConfig {
-            host,
-            port,
-            content_path,
-            frontend_path,
-            frontend_url,
-        })
-    }
-}
\ No newline at end of file
@@ -1,53 +1,152 @@
-use actix_web::{web, App, HttpServer, middleware::Logger};
+use actix_web::{web, App, HttpServer};
 use actix_files as fs;
 use std::collections::HashMap;
 use std::sync::Mutex;
+use clap::Parser;
+use tracing::{error, info, warn};
 use portfolio_backend::*;
 
-#[derive(Clone)]
-pub struct AppState {
-    pub github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
-    pub content_cache: web::Data<Mutex<HashMap<String, CachedContent>>>,
-    pub config: AppConfig,
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let cli = Cli::parse();
+    let config_file = cli.config.as_deref();
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(load_server_config(config_file)?).await,
+        Command::New { kind, title } => new_content_command(&load_config(config_file)?, kind, &title),
+        Command::Lint => lint_command(&load_config(config_file)?),
+        Command::Build { out, frontend, base_url } => build_command(&load_config(config_file)?, &out, frontend, base_url).await,
+        Command::HashPassword => hash_password_command(),
+        Command::Github { command: GithubCommand::Refresh } => github_refresh_command(&load_config(config_file)?).await,
+        Command::Cache { command: CacheCommand::Warm } => cache_warm_command(&load_config(config_file)?).await,
+        Command::Config { command: ConfigCommand::Check } => config_check_command(config_file),
+    }
+}
+
+/// Run the HTTP server until it is stopped
+async fn serve(config: AppConfig) -> std::io::Result<()> {
+    let revocations_path = format!("{}/revoked_tokens.json", config.data_path);
+    let revocations = web::Data::new(TokenRevocations::load(&revocations_path).map_err(|e| {
+        error!(path = %revocations_path, error = %e, "Failed to load token revocations");
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    })?);
+
+    let refresh_tokens_path = format!("{}/refresh_tokens.json", config.data_path);
+    let refresh_tokens = web::Data::new(RefreshTokenStore::load(&refresh_tokens_path).map_err(|e| {
+        error!(path = %refresh_tokens_path, error = %e, "Failed to load refresh tokens");
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    })?.with_lifetime_days(config.refresh_token_days));
+
+    let two_factor_path = format!("{}/two_factor.json", config.data_path);
+    let two_factor = web::Data::new(TwoFactorStore::load(&two_factor_path, &config.two_factor_key).map_err(|e| {
+        error!(path = %two_factor_path, error = %e, "Failed to load two-factor settings");
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    })?);
+
+    let api_tokens_path = format!("{}/api_tokens.json", config.data_path);
+    let api_tokens = web::Data::new(ApiTokenStore::load(&api_tokens_path).map_err(|e| {
+        error!(path = %api_tokens_path, error = %e, "Failed to load API tokens");
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    })?);
+
+    let audit_log_path = format!("{}/audit.log", config.data_path);
+    let audit_log = web::Data::new(AuditLog::open(&audit_log_path).map_err(|e| {
+        error!(path = %audit_log_path, error = %e, "Failed to open audit log");
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    })?);
+
+    let users = web::Data::new(UserStore::from_config(&config).map_err(|e| {
+        error!(error = %e, "Failed to load users");
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    })?);
+
+    let login_throttle = web::Data::new(LoginThrottle::new());
+    // Start from the GitHub cache persisted by `github refresh` / `cache warm`
+    let github_cache_file = github_cache_path(&config);
+    let github_cache = web::Data::new(Mutex::new(load_github_cache(&github_cache_file).unwrap_or_else(|e| {
+        warn!(path = %github_cache_file, error = %e, "Ignoring unreadable GitHub cache");
+        HashMap::new()
+    })));
+    let content_cache = web::Data::new(Mutex::new(HashMap::<String, CachedContent>::new()));
+
+    // Fetch uncached GitHub projects in the background; readiness waits for it
+    let cache_warmup = web::Data::new(CacheWarmup::default());
+    {
+        let config = config.clone();
+        let github_cache = github_cache.clone();
+        let cache_warmup = cache_warmup.clone();
+        let github_cache_file = github_cache_file.clone();
+        actix_web::rt::spawn(async move {
+            match warm_github_cache(&config, &github_cache).await {
+                Ok(count) => {
+                    info!(repositories = count, "GitHub cache warmed");
+                    if let Err(e) = save_github_cache(&github_cache_file, &github_cache) {
+                        error!(path = %github_cache_file, error = %e, "Failed to save GitHub cache");
+                    }
+                }
+                Err(e) => warn!(error = %e, "Failed to warm GitHub cache"),
+            }
+            cache_warmup.finish();
+        });
+    }
 
-    let config = AppConfig::from_env().map_err(|e| {
-        eprintln!("Configuration error: {}", e);
-        std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
-    })?;
+    // Remove temp files left behind by writes interrupted by a crash
+    match cleanup_stale_temp_files(&config.content_path) {
+        Ok(0) => {}
+        Ok(count) => info!(count, "Removed stale temp files"),
+        Err(e) => error!(error = %e, "Failed to clean up temp files"),
+    }
 
-    let github_cache = web::Data::new(Mutex::new(HashMap::new()));
-    let content_cache = web::Data::new(Mutex::new(HashMap::new()));
+    // Apply the trash retention policy hourly
+    let trash_path = config.trash_path.clone();
+    let trash_retention_days = config.trash_retention_days;
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match purge_expired_trash(&trash_path, trash_retention_days) {
+                Ok(0) => {}
+                Ok(count) => info!(count, "Purged expired trash items"),
+                Err(e) => error!(error = %e, "Failed to apply trash retention"),
+            }
+        }
+    });
 
-    let app_state = AppState {
-        github_cache: github_cache.clone(),
-        content_cache: content_cache.clone(),
-        config: config.clone(),
-    };
+    // Hash the inline scripts and styles of the built pages for the Content-Security-Policy
+    let inline_hashes = InlineHashes::scan(&config.frontend_path).unwrap_or_else(|e| {
+        warn!(path = %config.frontend_path, error = %e, "Failed to hash inline scripts");
+        InlineHashes::default()
+    });
+    let security_headers = SecurityHeaders::new(&config, &inline_hashes);
 
     let bind_addr = format!("{}:{}", config.host, config.port);
-    println!("Starting portfolio server on http://{}", bind_addr);
+    info!(address = %bind_addr, "Starting portfolio server");
 
     let config_clone = config.clone();
+    let server_github_cache = github_cache.clone();
     HttpServer::new(move || {
         App::new()
-            .app_data(github_cache.clone())
+            .app_data(server_github_cache.clone())
             .app_data(content_cache.clone())
+            .app_data(cache_warmup.clone())
+            .app_data(revocations.clone())
+            .app_data(refresh_tokens.clone())
+            .app_data(users.clone())
+            .app_data(login_throttle.clone())
+            .app_data(two_factor.clone())
+            .app_data(api_tokens.clone())
+            .app_data(audit_log.clone())
             .app_data(web::Data::new(config_clone.clone()))
-            .wrap(Logger::default())
-            .wrap(
-                actix_web::middleware::DefaultHeaders::new()
-                    .add(("Access-Control-Allow-Origin", config_clone.frontend_url.as_str()))
-                    .add(("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS"))
-                    .add(("Access-Control-Allow-Headers", "Content-Type, Authorization"))
-            )
+            .wrap(security_headers.clone())
+            .wrap(RequestMetrics)
+            .wrap(RequestId)
+            .route("/metrics", web::get().to(metrics_endpoint))
             .service(
                 web::scope("/api")
+                    .wrap(api_cors(&config_clone))
                     .route("/health", web::get().to(health_check))
+                    .route("/health/live", web::get().to(health_check))
+                    .route("/health/ready", web::get().to(readiness_check))
                     .service(
                         web::scope("/content")
                             .route("/{category}", web::get().to(get_content_list))
@@ -61,12 +160,31 @@ async fn main() -> std::io::Result<()> {
                     .service(
                         web::scope("/auth")
                             .route("/login", web::post().to(login))
+                            .route("/refresh", web::post().to(refresh_session))
+                            .route("/2fa/verify", web::post().to(verify_two_factor_login))
                             .route("/verify", web::get().to(verify_token))
                             .route("/logout", web::post().to(logout))
                     )
                     .service(
                         web::scope("/admin")
+                            .wrap(RequireAuth)
                             .route("/refresh-github", web::post().to(refresh_github_cache))
+                            .route("/sessions/revoke-all", web::post().to(revoke_all_sessions))
+                            .route("/audit", web::get().to(list_audit_events))
+                            .service(
+                                web::scope("/tokens")
+                                    .route("", web::get().to(list_api_tokens))
+                                    .route("", web::post().to(create_api_token))
+                                    .route("/revoke", web::post().to(revoke_api_token))
+                            )
+                            .service(
+                                web::scope("/2fa")
+                                    .route("", web::get().to(get_two_factor_status))
+                                    .route("/enroll", web::post().to(enroll_two_factor))
+                                    .route("/confirm", web::post().to(confirm_two_factor))
+                                    .route("/recovery-codes", web::post().to(regenerate_recovery_codes))
+                                    .route("/disable", web::post().to(disable_two_factor))
+                            )
                             .service(
                                 web::scope("/files")
                                     .route("/list/{path:.*}", web::get().to(list_files))
@@ -79,11 +197,33 @@ async fn main() -> std::io::Result<()> {
                                     .route("/move", web::post().to(move_file))
                                     .route("/create-folder", web::post().to(create_folder))
                             )
+                            .service(
+                                web::scope("/trash")
+                                    .route("", web::get().to(list_trash_items))
+                                    .route("/restore", web::post().to(restore_trash_item))
+                                    .route("/purge", web::post().to(purge_trash))
+                            )
+                            .service(
+                                web::scope("/history")
+                                    .route("", web::get().to(list_history))
+                                    .route("/diff", web::get().to(diff_history))
+                                    .route("/restore", web::post().to(restore_history))
+                            )
                     )
             )
             .service(fs::Files::new("/", &config_clone.frontend_path).index_file("index.html"))
     })
     .bind(&bind_addr)?
+    // On SIGTERM/SIGINT stop accepting connections and let in-flight requests,
+    // and the file writes they make, finish
+    .shutdown_timeout(SHUTDOWN_TIMEOUT_SECONDS)
     .run()
-    .await
+    .await?;
+
+    // Persist the projects fetched while running so the next start is warm
+    info!("Server stopped, flushing caches");
+    save_github_cache(&github_cache_file, &github_cache).map_err(|e| {
+        error!(path = %github_cache_file, error = %e, "Failed to save GitHub cache");
+        std::io::Error::other(e.to_string())
+    })
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+use std::future::{ready, Ready};
+use std::sync::LazyLock;
+use std::time::Instant;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Result};
+use futures_util::future::LocalBoxFuture;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use crate::error::AppError;
+use crate::models::AuditResult;
+use crate::totp::constant_time_eq;
+use crate::AppConfig;
+
+/// Prefix of every metric name
+const NAMESPACE: &str = "portfolio";
+
+/// Route label of requests no route matched, e.g. 404s and static files
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Process-wide Prometheus metrics
+///
+/// Kept global rather than in app data so the content and GitHub helpers can
+/// record into it without threading a handle through every call, including
+/// from the CLI where nothing scrapes them.
+pub struct Metrics {
+    registry: Registry,
+    /// Requests by method, route pattern and status code
+    pub http_requests: IntCounterVec,
+    /// Request latency by method and route pattern
+    pub http_request_duration: HistogramVec,
+    /// Cache lookups by cache (`content`, `github`) and result (`hit`, `miss`)
+    pub cache_lookups: IntCounterVec,
+    /// GitHub API requests by endpoint (`repo`, `readme`) and status code, or `error`
+    pub github_api_calls: IntCounterVec,
+    /// `X-RateLimit-Remaining` of the latest GitHub API response
+    pub github_rate_limit_remaining: IntGauge,
+    /// Content files whose frontmatter failed to parse
+    pub markdown_parse_errors: IntCounter,
+    /// Audited admin operations and logins by action and result
+    pub admin_operations: IntCounterVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        let opts = |name: &str, help: &str| Opts::new(name, help).namespace(NAMESPACE);
+        let metrics = Metrics {
+            registry: Registry::new(),
+            http_requests: IntCounterVec::new(
+                opts("http_requests_total", "HTTP requests by method, route and status"),
+                &["method", "route", "status"],
+            ).unwrap(),
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by method and route")
+                    .namespace(NAMESPACE),
+                &["method", "route"],
+            ).unwrap(),
+            cache_lookups: IntCounterVec::new(
+                opts("cache_lookups_total", "Cache lookups by cache and result"),
+                &["cache", "result"],
+            ).unwrap(),
+            github_api_calls: IntCounterVec::new(
+                opts("github_api_calls_total", "GitHub API requests by endpoint and status"),
+                &["endpoint", "status"],
+            ).unwrap(),
+            github_rate_limit_remaining: IntGauge::with_opts(
+                opts("github_rate_limit_remaining", "Requests left in the GitHub API rate limit window"),
+            ).unwrap(),
+            markdown_parse_errors: IntCounter::with_opts(
+                opts("markdown_parse_errors_total", "Content files whose frontmatter failed to parse"),
+            ).unwrap(),
+            admin_operations: IntCounterVec::new(
+                opts("admin_operations_total", "Audited admin operations and logins by action and result"),
+                &["action", "result"],
+            ).unwrap(),
+        };
+
+        let registry = &metrics.registry;
+        registry.register(Box::new(metrics.http_requests.clone())).unwrap();
+        registry.register(Box::new(metrics.http_request_duration.clone())).unwrap();
+        registry.register(Box::new(metrics.cache_lookups.clone())).unwrap();
+        registry.register(Box::new(metrics.github_api_calls.clone())).unwrap();
+        registry.register(Box::new(metrics.github_rate_limit_remaining.clone())).unwrap();
+        registry.register(Box::new(metrics.markdown_parse_errors.clone())).unwrap();
+        registry.register(Box::new(metrics.admin_operations.clone())).unwrap();
+        metrics
+    }
+
+    /// Count a lookup in `cache`
+    pub fn record_cache_lookup(&self, cache: &str, hit: bool) {
+        self.cache_lookups
+            .with_label_values(&[cache, if hit { "hit" } else { "miss" }])
+            .inc();
+    }
+
+    /// Count an audited admin operation or login
+    pub fn record_admin_operation(&self, action: &str, result: &AuditResult) {
+        self.admin_operations.with_label_values(&[action, result.as_str()]).inc();
+    }
+
+    /// Count a GitHub API request and track the rate limit it reports
+    pub fn record_github_call(&self, endpoint: &str, response: &Result<reqwest::Response, reqwest::Error>) {
+        let status = match response {
+            Ok(response) => {
+                let remaining = response
+                    .headers()
+                    .get("x-ratelimit-remaining")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<i64>().ok());
+                if let Some(remaining) = remaining {
+                    self.github_rate_limit_remaining.set(remaining);
+                }
+                response.status().as_u16().to_string()
+            }
+            Err(_) => "error".to_string(),
+        };
+        self.github_api_calls.with_label_values(&[endpoint, &status]).inc();
+    }
+
+    /// Every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!(error = %e, "Failed to encode metrics");
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Serve the metrics, behind a bearer token when `metrics_token` is configured
+pub async fn metrics_endpoint(req: HttpRequest, app_config: web::Data<AppConfig>) -> Result<HttpResponse> {
+    if let Some(token) = &app_config.metrics_token {
+        let presented = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or("");
+        if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+            return Err(AppError::Unauthorized("Authentication required".to_string()).into());
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(TextEncoder::new().format_type())
+        .body(METRICS.render()))
+}
+
+/// Middleware counting requests and timing them per route pattern
+///
+/// Routes are labelled by their pattern (`/api/content/{category}`), not the
+/// request path, so the number of series stays bounded.
+pub struct RequestMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware { service }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let started = Instant::now();
+        let method = req.method().to_string();
+        let future = self.service.call(req);
+
+        Box::pin(async move {
+            let result = future.await;
+            let (route, status) = match &result {
+                Ok(response) => (
+                    response.request().match_pattern(),
+                    response.status(),
+                ),
+                Err(e) => (None, e.as_response_error().status_code()),
+            };
+            let route = route.unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+
+            METRICS.http_requests
+                .with_label_values(&[&method, &route, status.as_str()])
+                .inc();
+            METRICS.http_request_duration
+                .with_label_values(&[&method, &route])
+                .observe(started.elapsed().as_secs_f64());
+            result
+        })
+    }
+}
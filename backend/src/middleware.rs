@@ -0,0 +1,89 @@
+use std::future::{ready, Ready};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpMessage, ResponseError};
+use futures_util::future::LocalBoxFuture;
+use crate::logging::REQUEST_ID_HEADER;
+use crate::utils::extract_token_from_request;
+use crate::AppConfig;
+
+/// Middleware requiring a valid token for every route it wraps
+///
+/// The verified `Claims` are stored as a request extension, so handlers only
+/// need to check the permission they require. Wrap the whole `/api/admin`
+/// scope with it so newly added routes are authenticated by default.
+pub struct RequireAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAuthMiddleware { service }))
+    }
+}
+
+pub struct RequireAuthMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        match extract_token_from_request(req.request()) {
+            Ok(claims) => {
+                req.extensions_mut().insert(claims);
+                let future = self.service.call(req);
+                Box::pin(async move { future.await.map(ServiceResponse::map_into_left_body) })
+            }
+            Err(e) => {
+                let response = e.error_response();
+                Box::pin(async move { Ok(req.into_response(response).map_into_right_body()) })
+            }
+        }
+    }
+}
+
+/// CORS policy for the API: configured origins only, with credentials and cached preflights
+///
+/// Only the `/api` scope is wrapped, so static files are served without CORS headers.
+pub fn api_cors(config: &AppConfig) -> actix_cors::Cors {
+    let mut cors = actix_cors::Cors::default()
+        .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+        .allowed_headers(vec![
+            actix_web::http::header::CONTENT_TYPE,
+            actix_web::http::header::AUTHORIZATION,
+            actix_web::http::header::IF_MATCH,
+            actix_web::http::header::HeaderName::from_static("x-csrf-token"),
+        ])
+        .expose_headers(vec![
+            actix_web::http::header::ETAG,
+            actix_web::http::header::RETRY_AFTER,
+            actix_web::http::header::HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .supports_credentials()
+        .max_age(config.cors_max_age);
+
+    for origin in &config.cors_allowed_origins {
+        cors = cors.allowed_origin(origin);
+    }
+    cors
+}
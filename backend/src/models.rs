@@ -1,6 +1,5 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentMetadata {
@@ -20,6 +19,13 @@ pub struct ContentItem {
     pub category: String,
 }
 
+/// A problem found by `portfolio-backend lint`, relative to the content root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintIssue {
+    pub path: String,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedContent {
     pub content: ContentItem,
@@ -65,6 +71,9 @@ pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub message: String,
+    /// Machine-readable error code of failed requests, see `AppError::code`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
 }
 
 impl<T> ApiResponse<T> {
@@ -73,6 +82,7 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             message: "Success".to_string(),
+            code: None,
         }
     }
 
@@ -81,6 +91,16 @@ impl<T> ApiResponse<T> {
             success: false,
             data: None,
             message: message.to_string(),
+            code: None,
+        }
+    }
+
+    pub fn error_with_code(code: &str, message: &str) -> Self {
+        Self {
+            success: false,
+            data: None,
+            message: message.to_string(),
+            code: Some(code.to_string()),
         }
     }
 }
@@ -98,15 +118,91 @@ pub struct LoginResponse {
     pub expires_at: DateTime<Utc>,
 }
 
+/// Returned by login instead of a token when the user has two-factor enabled
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorVerifyRequest {
+    pub challenge_token: String,
+    /// A current TOTP code or an unused recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_remaining: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,  // username
     pub exp: i64,     // expiration timestamp
     pub iat: i64,     // issued at timestamp
     pub role: String, // user role (admin)
+    #[serde(default)]
+    pub jti: String,  // unique token id, used for revocation
+    #[serde(default)]
+    pub iat_micros: i64, // issued at in microseconds, ordered against "log out all sessions"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>, // API token scopes, empty for interactive logins
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub csrf: String, // CSRF token cookie-authenticated requests must echo
+}
+
+/// A named API token as shown to admins; the secret itself is never stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenCreateRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Omit for a token that never expires
+    pub expires_in_days: Option<i64>,
 }
 
+/// A newly created API token; `token` is only ever returned here
 #[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenCreated {
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenRevokeRequest {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthUser {
     pub username: String,
     pub password_hash: String,
@@ -152,3 +248,123 @@ pub struct FileContentRequest {
     pub content: String,
 }
 
+
+// Content history models
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionAction {
+    Baseline,
+    Save,
+    Upload,
+    Rename,
+    Move,
+    Delete,
+    CreateFolder,
+    Restore,
+}
+
+impl RevisionAction {
+    /// Whether the action changes content and so needs a snapshot; renames,
+    /// moves and new folders are recorded by path only
+    pub fn changes_content(&self) -> bool {
+        !matches!(self, RevisionAction::Rename | RevisionAction::Move | RevisionAction::CreateFolder)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub action: RevisionAction,
+    pub path: String,
+    pub previous_path: Option<String>,
+    pub is_directory: bool,
+    pub has_snapshot: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryDiffQuery {
+    pub from: String,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionRestoreRequest {
+    pub revision_id: String,
+}
+
+// Trash models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub id: String,
+    pub name: String,
+    pub original_path: String,
+    pub is_directory: bool,
+    pub size: u64,
+    pub deleted_at: DateTime<Utc>,
+    pub deleted_by: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashRestoreRequest {
+    pub id: String,
+    pub destination_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashPurgeRequest {
+    pub id: Option<String>,
+}
+
+/// Outcome of an audited action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditResult {
+    Success,
+    /// Refused by authentication, permissions, preconditions or throttling
+    Denied,
+    Failure,
+}
+
+impl AuditResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditResult::Success => "success",
+            AuditResult::Denied => "denied",
+            AuditResult::Failure => "failure",
+        }
+    }
+}
+
+/// One entry of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub ip: String,
+    pub action: String,
+    pub path: Option<String>,
+    /// Destination of renames and moves
+    pub target_path: Option<String>,
+    pub result: AuditResult,
+    pub status: u16,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub user: Option<String>,
+    pub action: Option<String>,
+    pub result: Option<AuditResult>,
+    /// Matches events whose path or target path starts with this prefix
+    pub path: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum number of events, newest first (default 100, at most 1000)
+    pub limit: Option<usize>,
+}
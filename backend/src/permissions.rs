@@ -0,0 +1,161 @@
+use actix_web::{HttpMessage, HttpRequest};
+use crate::api_tokens::API_TOKEN_ROLE;
+use crate::error::AppError;
+use crate::history::normalize_relative_path;
+use crate::models::*;
+use crate::utils::extract_token_from_request;
+
+/// Content paths only admins may modify, relative to the content root
+pub const PROTECTED_PATHS: &[&str] = &["github/config.yaml"];
+
+/// User roles, from most to least privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Editor,
+    Viewer,
+}
+
+/// Actions guarded by a role check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Manage one's own account, e.g. two-factor settings
+    ManageOwnAccount,
+    /// List, read and download content, history and trash
+    ContentRead,
+    /// Upload, save, rename, move, create folders and restore
+    ContentWrite,
+    /// Delete content and purge the trash
+    ContentDelete,
+    /// Modify the files in `PROTECTED_PATHS`
+    ProtectedWrite,
+    /// Refresh the GitHub project cache
+    GithubRefresh,
+    /// Log out every session
+    ManageSessions,
+    /// Create, list and revoke API tokens
+    ManageApiTokens,
+    /// Query the audit log
+    ViewAuditLog,
+}
+
+impl Permission {
+    /// The API token scope granting this permission, if tokens can be granted it at all
+    pub fn api_scope(self) -> Option<&'static str> {
+        match self {
+            Permission::ContentRead => Some("content:read"),
+            Permission::ContentWrite => Some("content:write"),
+            Permission::ContentDelete => Some("content:delete"),
+            Permission::GithubRefresh => Some("github:refresh"),
+            _ => None,
+        }
+    }
+}
+
+impl Role {
+    pub fn parse(role: &str) -> Option<Role> {
+        match role {
+            "admin" => Some(Role::Admin),
+            "editor" => Some(Role::Editor),
+            "viewer" => Some(Role::Viewer),
+            _ => None,
+        }
+    }
+
+    pub fn allows(self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Editor => matches!(
+                permission,
+                Permission::ManageOwnAccount
+                    | Permission::ContentRead
+                    | Permission::ContentWrite
+                    | Permission::GithubRefresh
+            ),
+            Role::Viewer => matches!(permission, Permission::ManageOwnAccount | Permission::ContentRead),
+        }
+    }
+}
+
+/// Whether a role name from a token grants `permission`; unknown roles grant nothing
+pub fn role_allows(role: &str, permission: Permission) -> bool {
+    Role::parse(role).is_some_and(|role| role.allows(permission))
+}
+
+/// Whether the caller's claims grant `permission`, by role or by API token scope
+pub fn claims_allow(claims: &Claims, permission: Permission) -> bool {
+    if claims.role == API_TOKEN_ROLE {
+        return permission
+            .api_scope()
+            .is_some_and(|scope| claims.scopes.iter().any(|granted| granted == scope));
+    }
+    role_allows(&claims.role, permission)
+}
+
+/// Claims of the authenticated caller, as verified by `RequireAuth`
+///
+/// Falls back to verifying the token directly for handlers mounted outside the
+/// middleware, so a missing `wrap` can never skip authentication.
+pub fn authenticated_claims(req: &HttpRequest) -> Result<Claims, AppError> {
+    if let Some(claims) = req.extensions().get::<Claims>() {
+        return Ok(claims.clone());
+    }
+    extract_token_from_request(req)
+}
+
+/// Check the authenticated caller's role grants `permission`
+pub fn require_permission(req: &HttpRequest, permission: Permission) -> Result<Claims, AppError> {
+    let claims = authenticated_claims(req)?;
+    if claims_allow(&claims, permission) {
+        Ok(claims)
+    } else {
+        Err(AppError::Forbidden("Insufficient permissions".to_string()))
+    }
+}
+
+/// Whether modifying `relative_path` would modify a protected path, either the
+/// path itself or a folder containing it
+pub fn touches_protected_path(relative_path: &str) -> bool {
+    let path = normalize_relative_path(relative_path);
+    PROTECTED_PATHS.iter().any(|protected| {
+        path.is_empty() || *protected == path || protected.starts_with(&format!("{}/", path))
+    })
+}
+
+/// Check the caller may modify every path in `relative_paths`
+pub fn authorize_paths(claims: &Claims, relative_paths: &[&str]) -> Result<(), AppError> {
+    if claims_allow(claims, Permission::ProtectedWrite) {
+        return Ok(());
+    }
+
+    if let Some(path) = relative_paths.iter().find(|path| touches_protected_path(path)) {
+        return Err(AppError::Forbidden(format!("Insufficient permissions to modify '{}'", path)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_paths_are_touched_by_their_folders() {
+        assert!(touches_protected_path("github/config.yaml"));
+        assert!(touches_protected_path("/github//./config.yaml"));
+        assert!(touches_protected_path("github"));
+        assert!(touches_protected_path("./"));
+        assert!(!touches_protected_path("github/other.yaml"));
+        assert!(!touches_protected_path("githubs"));
+        assert!(!touches_protected_path("blog/config.yaml"));
+    }
+
+    #[test]
+    fn unknown_roles_grant_nothing() {
+        assert!(role_allows("viewer", Permission::ContentRead));
+        assert!(!role_allows("viewer", Permission::ContentWrite));
+        assert!(!role_allows("editor", Permission::ContentDelete));
+        assert!(!role_allows("intern", Permission::ContentRead));
+        assert!(!role_allows("Admin", Permission::ContentRead));
+    }
+}
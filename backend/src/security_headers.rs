@@ -0,0 +1,294 @@
+use std::fs;
+use std::future::{ready, Ready};
+use std::path::Path;
+use std::sync::Arc;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::Error;
+use base64::Engine;
+use futures_util::future::LocalBoxFuture;
+use sha2::{Digest, Sha256};
+use crate::{AppConfig, YOUTUBE_EMBED_ORIGIN};
+
+/// Paths getting the strict policy: the admin API, the login page and the admin UI
+pub const STRICT_PATH_PREFIXES: &[&str] = &["/api/admin", "/knockknock", "/admin"];
+
+/// External origin the public pages load Prism from
+const PUBLIC_CDN_ORIGIN: &str = "https://cdnjs.cloudflare.com";
+
+/// Directories under the frontend path never scanned for pages
+pub const SKIPPED_DIRS: &[&str] = &["node_modules", "src", "tests", "test-results", "playwright-report"];
+
+/// CSP hash sources of the inline scripts and styles of the built frontend pages
+#[derive(Debug, Clone, Default)]
+pub struct InlineHashes {
+    pub scripts: Vec<String>,
+    pub styles: Vec<String>,
+}
+
+impl InlineHashes {
+    /// Hash the inline `<script>` and `<style>` elements of every HTML file under `frontend_path`
+    ///
+    /// Astro pages are static, so hashes computed once at startup stay valid for
+    /// every response, unlike nonces which would require rewriting each page.
+    pub fn scan(frontend_path: &str) -> Result<Self, std::io::Error> {
+        let mut hashes = InlineHashes::default();
+        let dir = Path::new(frontend_path);
+        if dir.is_dir() {
+            hashes.scan_dir(dir)?;
+        }
+
+        hashes.scripts.sort();
+        hashes.scripts.dedup();
+        hashes.styles.sort();
+        hashes.styles.dedup();
+        Ok(hashes)
+    }
+
+    fn scan_dir(&mut self, dir: &Path) -> Result<(), std::io::Error> {
+        for entry in fs::read_dir(dir)?.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if path.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_str()) {
+                    self.scan_dir(&path)?;
+                }
+            } else if name.ends_with(".html") {
+                let html = fs::read_to_string(&path)?;
+                self.scripts.extend(inline_element_hashes(&html, "script"));
+                self.styles.extend(inline_element_hashes(&html, "style"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// CSP `'sha256-...'` sources for the inline `<tag>` elements of a page
+///
+/// Elements loading an external file (`src=`) are skipped; the hash covers the
+/// exact text between the tags, as browsers compute it.
+pub fn inline_element_hashes(html: &str, tag: &str) -> Vec<String> {
+    // ASCII lowercasing keeps byte offsets, so indexes into `lower` are valid in `html`
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", tag);
+    let close = format!("</{}", tag);
+    let mut hashes = Vec::new();
+    let mut position = 0;
+
+    while let Some(start) = lower[position..].find(&open).map(|i| i + position) {
+        let after_name = start + open.len();
+        if !lower[after_name..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            position = after_name;
+            continue;
+        }
+        let Some(tag_end) = lower[after_name..].find('>').map(|i| i + after_name) else {
+            break;
+        };
+        let Some(content_end) = lower[tag_end..].find(&close).map(|i| i + tag_end) else {
+            break;
+        };
+
+        let attributes = &lower[after_name..tag_end];
+        let content = &html[tag_end + 1..content_end];
+        if !attributes.contains("src=") && !content.trim().is_empty() {
+            let digest = Sha256::digest(content.as_bytes());
+            hashes.push(format!(
+                "'sha256-{}'",
+                base64::engine::general_purpose::STANDARD.encode(digest)
+            ));
+        }
+        position = content_end + close.len();
+    }
+
+    hashes
+}
+
+/// Whether `path` gets the strict policy of `STRICT_PATH_PREFIXES`
+pub fn is_strict_path(path: &str) -> bool {
+    STRICT_PATH_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || rest.starts_with('.'))
+    })
+}
+
+/// Header values for each class of path, built once at startup
+struct SecurityPolicies {
+    csp_header: HeaderName,
+    public_csp: String,
+    strict_csp: String,
+    api_csp: String,
+    hsts: Option<String>,
+}
+
+/// Middleware adding CSP, HSTS, X-Content-Type-Options, Referrer-Policy and
+/// frame headers to every response
+///
+/// Public pages may be framed by the site itself, load Prism from the CDN and
+/// frame the embeds of the `youtube` shortcode;
+/// `STRICT_PATH_PREFIXES` get a policy without inline styles or third-party
+/// sources, no referrer, no framing and no caching. Headers already set by a
+/// handler are left as they are.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    policies: Arc<SecurityPolicies>,
+}
+
+impl SecurityHeaders {
+    pub fn new(config: &AppConfig, hashes: &InlineHashes) -> Self {
+        let connect_src = std::iter::once("'self'".to_string())
+            .chain(config.csp_connect_sources.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let script_hashes = hashes.scripts.join(" ");
+        let style_hashes = hashes.styles.join(" ");
+
+        let public_csp = format!(
+            "default-src 'self'; script-src 'self' {cdn} {script_hashes}; \
+             style-src 'self' 'unsafe-inline' {cdn}; img-src 'self' data: https:; \
+             font-src 'self' data:; connect-src {connect_src}; object-src 'none'; \
+             frame-src {youtube}; base-uri 'self'; form-action 'self'; frame-ancestors 'self'",
+            cdn = PUBLIC_CDN_ORIGIN,
+            youtube = YOUTUBE_EMBED_ORIGIN,
+        );
+        let strict_csp = format!(
+            "default-src 'self'; script-src 'self' {script_hashes}; style-src 'self' {style_hashes}; \
+             img-src 'self' data:; connect-src {connect_src}; object-src 'none'; \
+             base-uri 'none'; form-action 'self'; frame-ancestors 'none'",
+        );
+
+        SecurityHeaders {
+            policies: Arc::new(SecurityPolicies {
+                csp_header: if config.csp_report_only {
+                    header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+                } else {
+                    header::CONTENT_SECURITY_POLICY
+                },
+                public_csp: collapse_spaces(&public_csp),
+                strict_csp: collapse_spaces(&strict_csp),
+                api_csp: "default-src 'none'; frame-ancestors 'none'".to_string(),
+                hsts: (config.hsts_max_age > 0)
+                    .then(|| format!("max-age={}; includeSubDomains", config.hsts_max_age)),
+            }),
+        }
+    }
+}
+
+/// Drop the double spaces left by empty hash lists
+fn collapse_spaces(policy: &str) -> String {
+    policy.split_whitespace().collect::<Vec<_>>().join(" ").replace(" ;", ";")
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SecurityHeadersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service,
+            policies: self.policies.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: S,
+    policies: Arc<SecurityPolicies>,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let path = req.path().to_string();
+        let policies = self.policies.clone();
+        let future = self.service.call(req);
+
+        Box::pin(async move {
+            let mut response = future.await?;
+            let strict = is_strict_path(&path);
+            let api = path == "/api" || path.starts_with("/api/");
+
+            let csp = if api {
+                &policies.api_csp
+            } else if strict {
+                &policies.strict_csp
+            } else {
+                &policies.public_csp
+            };
+            let (referrer_policy, frame_options) = if strict || api {
+                ("no-referrer", "DENY")
+            } else {
+                ("strict-origin-when-cross-origin", "SAMEORIGIN")
+            };
+
+            let headers = response.headers_mut();
+            let mut set_default = |name: HeaderName, value: &str| {
+                if !headers.contains_key(&name) {
+                    if let Ok(value) = HeaderValue::from_str(value) {
+                        headers.insert(name, value);
+                    }
+                }
+            };
+            set_default(policies.csp_header.clone(), csp);
+            set_default(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+            set_default(header::REFERRER_POLICY, referrer_policy);
+            set_default(header::X_FRAME_OPTIONS, frame_options);
+            if let Some(hsts) = &policies.hsts {
+                set_default(header::STRICT_TRANSPORT_SECURITY, hsts);
+            }
+            if strict {
+                set_default(header::CACHE_CONTROL, "no-store");
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn scan_hashes_inline_elements_of_built_pages() {
+        let frontend_dir = TempDir::new().unwrap();
+        let frontend_path = frontend_dir.path().to_str().unwrap();
+        fs::create_dir_all(frontend_dir.path().join("knockknock")).unwrap();
+        fs::write(
+            frontend_dir.path().join("knockknock/index.html"),
+            "<html><head><style>body{color:red}</style></head><body>\
+             <script type=\"module\" src=\"/assets/app.js\"></script>\
+             <script>console.log('hi')</script></body></html>",
+        ).unwrap();
+
+        // Scripts loading a file are left to 'self'
+        let hashes = InlineHashes::scan(frontend_path).unwrap();
+        assert_eq!(hashes.scripts, inline_element_hashes("<script>console.log('hi')</script>", "script"));
+        assert_eq!(hashes.styles, inline_element_hashes("<style>body{color:red}</style>", "style"));
+    }
+
+    #[test]
+    fn strict_paths_match_whole_segments() {
+        assert!(is_strict_path("/admin"));
+        assert!(is_strict_path("/knockknock.html"));
+        assert!(!is_strict_path("/administrator"));
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::models::*;
+use crate::utils::write_file_atomic;
+
+/// Persisted revocation state
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RevocationState {
+    /// Revoked token ids mapped to their expiry, kept only until they expire
+    revoked: HashMap<String, i64>,
+    /// Tokens issued up to this time, in microseconds, are revoked ("log out
+    /// all sessions")
+    #[serde(default)]
+    revoked_before_micros: i64,
+}
+
+/// When the token was issued in microseconds; tokens from before `iat_micros`
+/// existed only have whole seconds, so they count as issued at the start of theirs
+fn issued_at_micros(claims: &Claims) -> i64 {
+    if claims.iat_micros > 0 {
+        claims.iat_micros
+    } else {
+        claims.iat.saturating_mul(1_000_000)
+    }
+}
+
+/// Server-side list of revoked JWTs, persisted so logouts survive restarts
+pub struct TokenRevocations {
+    path: Option<PathBuf>,
+    state: Mutex<RevocationState>,
+}
+
+impl TokenRevocations {
+    /// Load the revocation list from `path`, starting empty if it does not exist yet
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let state = if Path::new(path).exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            RevocationState::default()
+        };
+
+        Ok(TokenRevocations {
+            path: Some(PathBuf::from(path)),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// A revocation list that is never written to disk
+    pub fn in_memory() -> Self {
+        TokenRevocations {
+            path: None,
+            state: Mutex::new(RevocationState::default()),
+        }
+    }
+
+    fn persist(&self, state: &RevocationState) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_file_atomic(&path.to_string_lossy(), serde_json::to_string(state)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Revoke a single token until it would have expired anyway
+    pub fn revoke(&self, jti: &str, expires_at: i64) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now().timestamp();
+        state.revoked.retain(|_, exp| *exp >= now);
+        state.revoked.insert(jti.to_string(), expires_at);
+        self.persist(&state)
+    }
+
+    /// Revoke every token issued up to now
+    pub fn revoke_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.revoked_before_micros = Utc::now().timestamp_micros();
+        state.revoked.clear();
+        self.persist(&state)
+    }
+
+    /// Whether the token described by `claims` has been revoked
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        let state = self.state.lock().unwrap();
+        issued_at_micros(claims) <= state.revoked_before_micros || state.revoked.contains_key(&claims.jti)
+    }
+}
+
+/// Default refresh token lifetime (7 days)
+pub const REFRESH_TOKEN_EXPIRATION_DAYS: i64 = 7;
+
+/// Default seconds a just-rotated refresh token is still accepted, so concurrent
+/// refreshes from several tabs are not mistaken for token theft
+pub const REFRESH_REUSE_GRACE_SECONDS: i64 = 5;
+
+/// Name of the HttpOnly cookie carrying the refresh token
+pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
+
+/// A stored refresh token; only a hash of the token itself is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RefreshTokenRecord {
+    /// All tokens rotated from the same login share a family
+    family_id: String,
+    username: String,
+    expires_at: i64,
+    /// Set once the token has been exchanged; presenting it again means it leaked
+    used: bool,
+    /// When the token was exchanged, for the reuse grace window
+    #[serde(default)]
+    used_at: i64,
+    /// The token this one was exchanged for, handed out again to refreshes racing
+    /// the rotation; kept in memory only and dropped once the grace window ends
+    #[serde(skip)]
+    successor: Option<String>,
+}
+
+/// Server-side store of rotating refresh tokens with reuse detection
+pub struct RefreshTokenStore {
+    path: Option<PathBuf>,
+    tokens: Mutex<HashMap<String, RefreshTokenRecord>>,
+    lifetime_days: i64,
+    reuse_grace_seconds: i64,
+}
+
+fn hash_refresh_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generate_refresh_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+impl RefreshTokenStore {
+    /// Load refresh tokens from `path`, starting empty if it does not exist yet
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let tokens = if Path::new(path).exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(RefreshTokenStore {
+            path: Some(PathBuf::from(path)),
+            tokens: Mutex::new(tokens),
+            lifetime_days: REFRESH_TOKEN_EXPIRATION_DAYS,
+            reuse_grace_seconds: REFRESH_REUSE_GRACE_SECONDS,
+        })
+    }
+
+    /// A refresh token store that is never written to disk
+    pub fn in_memory() -> Self {
+        RefreshTokenStore {
+            path: None,
+            tokens: Mutex::new(HashMap::new()),
+            lifetime_days: REFRESH_TOKEN_EXPIRATION_DAYS,
+            reuse_grace_seconds: REFRESH_REUSE_GRACE_SECONDS,
+        }
+    }
+
+    /// Issue refresh tokens valid for `days` instead of the default lifetime
+    pub fn with_lifetime_days(mut self, days: i64) -> Self {
+        self.lifetime_days = days;
+        self
+    }
+
+    /// Accept a just-rotated token for `seconds` instead of the default grace window
+    pub fn with_reuse_grace_seconds(mut self, seconds: i64) -> Self {
+        self.reuse_grace_seconds = seconds;
+        self
+    }
+
+    fn persist(&self, tokens: &mut HashMap<String, RefreshTokenRecord>) -> Result<(), Box<dyn std::error::Error>> {
+        let now = Utc::now().timestamp();
+        tokens.retain(|_, record| record.expires_at >= now);
+        for record in tokens.values_mut() {
+            if record.used && now - record.used_at >= self.reuse_grace_seconds {
+                record.successor = None;
+            }
+        }
+
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_file_atomic(&path.to_string_lossy(), serde_json::to_string(tokens)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn insert_token(
+        &self,
+        tokens: &mut HashMap<String, RefreshTokenRecord>,
+        family_id: String,
+        username: &str,
+    ) -> Result<(String, chrono::DateTime<Utc>), Box<dyn std::error::Error>> {
+        let token = generate_refresh_token();
+        let expires_at = Utc::now() + chrono::Duration::days(self.lifetime_days);
+
+        tokens.insert(hash_refresh_token(&token), RefreshTokenRecord {
+            family_id,
+            username: username.to_string(),
+            expires_at: expires_at.timestamp(),
+            used: false,
+            used_at: 0,
+            successor: None,
+        });
+        self.persist(tokens)?;
+
+        Ok((token, expires_at))
+    }
+
+    /// Start a new refresh token family for a fresh login
+    pub fn issue(&self, username: &str) -> Result<(String, chrono::DateTime<Utc>), Box<dyn std::error::Error>> {
+        let mut tokens = self.tokens.lock().unwrap();
+        self.insert_token(&mut tokens, uuid::Uuid::new_v4().to_string(), username)
+    }
+
+    /// Exchange a refresh token for a new one in the same family
+    ///
+    /// Returns the username with the new token. Presenting a token that was already
+    /// exchanged revokes its whole family, since either the client or an attacker
+    /// holds a stolen copy; within the grace window after its rotation it instead
+    /// returns the token it was already exchanged for, so concurrent refreshes
+    /// share one successor. Rejected tokens are `AppError::Unauthorized`; anything
+    /// else is a failure to persist the store.
+    pub fn rotate(&self, token: &str) -> Result<(String, String, chrono::DateTime<Utc>), Box<dyn std::error::Error>> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let token_hash = hash_refresh_token(token);
+
+        let record = match tokens.get(&token_hash) {
+            Some(record) => record.clone(),
+            None => return Err(AppError::Unauthorized("Invalid refresh token".to_string()).into()),
+        };
+
+        let now = Utc::now().timestamp();
+        if record.used {
+            // A refresh racing the one that rotated this token, e.g. from another tab
+            if now - record.used_at < self.reuse_grace_seconds && record.expires_at >= now {
+                if let Some((successor, expires_at)) = latest_successor(&tokens, &record) {
+                    return Ok((record.username, successor, expires_at));
+                }
+            }
+
+            tokens.retain(|_, other| other.family_id != record.family_id);
+            self.persist(&mut tokens)?;
+            tracing::warn!(username = %record.username, "Refresh token reuse detected, session revoked");
+            return Err(AppError::Unauthorized("Refresh token reuse detected".to_string()).into());
+        }
+
+        if record.expires_at < now {
+            return Err(AppError::Unauthorized("Refresh token expired".to_string()).into());
+        }
+
+        if let Some(stored) = tokens.get_mut(&token_hash) {
+            stored.used = true;
+            stored.used_at = now;
+        }
+        let (new_token, expires_at) = self.insert_token(&mut tokens, record.family_id, &record.username)?;
+        if let Some(stored) = tokens.get_mut(&token_hash) {
+            stored.successor = Some(new_token.clone());
+        }
+
+        Ok((record.username, new_token, expires_at))
+    }
+
+    /// Revoke the whole family of a refresh token (logout)
+    pub fn revoke_family(&self, token: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tokens = self.tokens.lock().unwrap();
+        if let Some(record) = tokens.get(&hash_refresh_token(token)).cloned() {
+            tokens.retain(|_, other| other.family_id != record.family_id);
+            self.persist(&mut tokens)?;
+        }
+        Ok(())
+    }
+
+    /// Revoke every refresh token
+    pub fn revoke_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.clear();
+        self.persist(&mut tokens)
+    }
+}
+
+/// The live token `record` was rotated into, following later rotations of its
+/// successor; `None` once the chain is gone or has left the grace window
+fn latest_successor(
+    tokens: &HashMap<String, RefreshTokenRecord>,
+    record: &RefreshTokenRecord,
+) -> Option<(String, chrono::DateTime<Utc>)> {
+    let mut successor = record.successor.clone()?;
+    loop {
+        let next = tokens.get(&hash_refresh_token(&successor))?;
+        if !next.used {
+            return Some((successor, chrono::DateTime::from_timestamp(next.expires_at, 0)?));
+        }
+        successor = next.successor.clone()?;
+    }
+}
+
+/// Build the HttpOnly refresh token cookie, scoped to the auth endpoints
+pub fn refresh_token_cookie(token: &str, expires_at: chrono::DateTime<Utc>, secure: bool) -> actix_web::cookie::Cookie<'static> {
+    let max_age = (expires_at - Utc::now()).num_seconds().max(0);
+    actix_web::cookie::Cookie::build(REFRESH_COOKIE_NAME, token.to_string())
+        .path("/api/auth")
+        .http_only(true)
+        .secure(secure)
+        .same_site(actix_web::cookie::SameSite::Strict)
+        .max_age(actix_web::cookie::time::Duration::seconds(max_age))
+        .finish()
+}
+
+/// Name of the HttpOnly cookie carrying the access token when cookie sessions are enabled
+pub const ACCESS_COOKIE_NAME: &str = "access_token";
+
+/// Name of the readable cookie holding the CSRF token for the current access token
+pub const CSRF_COOKIE_NAME: &str = "csrf_token";
+
+/// Header mutating cookie-authenticated requests must echo the CSRF token in
+pub const CSRF_HEADER_NAME: &str = "X-CSRF-Token";
+
+/// Build the HttpOnly access token cookie, sent with every API request
+pub fn access_token_cookie(token: &str, expires_at: chrono::DateTime<Utc>, secure: bool) -> actix_web::cookie::Cookie<'static> {
+    let max_age = (expires_at - Utc::now()).num_seconds().max(0);
+    actix_web::cookie::Cookie::build(ACCESS_COOKIE_NAME, token.to_string())
+        .path("/api")
+        .http_only(true)
+        .secure(secure)
+        .same_site(actix_web::cookie::SameSite::Strict)
+        .max_age(actix_web::cookie::time::Duration::seconds(max_age))
+        .finish()
+}
+
+/// Build the CSRF token cookie; readable by scripts so the admin UI can echo it
+pub fn csrf_token_cookie(csrf_token: &str, expires_at: chrono::DateTime<Utc>, secure: bool) -> actix_web::cookie::Cookie<'static> {
+    let max_age = (expires_at - Utc::now()).num_seconds().max(0);
+    actix_web::cookie::Cookie::build(CSRF_COOKIE_NAME, csrf_token.to_string())
+        .path("/")
+        .http_only(false)
+        .secure(secure)
+        .same_site(actix_web::cookie::SameSite::Strict)
+        .max_age(actix_web::cookie::time::Duration::seconds(max_age))
+        .finish()
+}
+
+/// Build cookies that clear every session cookie in the browser
+pub fn clear_session_cookies(secure: bool) -> Vec<actix_web::cookie::Cookie<'static>> {
+    let now = Utc::now();
+    let mut cookies = vec![
+        refresh_token_cookie("", now, secure),
+        access_token_cookie("", now, secure),
+        csrf_token_cookie("", now, secure),
+    ];
+    for cookie in &mut cookies {
+        cookie.make_removal();
+    }
+    cookies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn claims_issued_at(iat_micros: i64) -> Claims {
+        let iat = iat_micros.div_euclid(1_000_000);
+        Claims {
+            sub: "admin".to_string(),
+            exp: iat + 900,
+            iat,
+            role: "admin".to_string(),
+            jti: uuid::Uuid::new_v4().to_string(),
+            iat_micros,
+            scopes: Vec::new(),
+            csrf: String::new(),
+        }
+    }
+
+    #[test]
+    fn revocations_survive_reloading() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let path = format!("{}/revoked_tokens.json", dir);
+        let claims = claims_issued_at(Utc::now().timestamp_micros());
+
+        TokenRevocations::load(&path).unwrap().revoke(&claims.jti, claims.exp).unwrap();
+        assert!(TokenRevocations::load(&path).unwrap().is_revoked(&claims));
+    }
+
+    #[test]
+    fn revoke_all_revokes_tokens_issued_in_the_same_second() {
+        let revocations = TokenRevocations::in_memory();
+        let claims = claims_issued_at(Utc::now().timestamp_micros());
+        assert!(!revocations.is_revoked(&claims));
+
+        revocations.revoke_all().unwrap();
+        assert!(revocations.is_revoked(&claims));
+    }
+
+    #[test]
+    fn tokens_issued_after_revoke_all_stay_valid() {
+        let revocations = TokenRevocations::in_memory();
+        revocations.revoke_all().unwrap();
+        let revoked_before = revocations.state.lock().unwrap().revoked_before_micros;
+
+        // Signing in again right after logging out everywhere works
+        assert!(!revocations.is_revoked(&claims_issued_at(revoked_before + 1)));
+        assert!(revocations.is_revoked(&claims_issued_at(revoked_before)));
+    }
+
+    #[test]
+    fn concurrent_refreshes_within_the_grace_window_share_the_successor() {
+        let store = RefreshTokenStore::in_memory();
+        let (token, _) = store.issue("admin").unwrap();
+
+        // A second refresh with the just-rotated token gets the same successor
+        // instead of revoking the family or starting a second live token
+        let (_, first, _) = store.rotate(&token).unwrap();
+        let (username, second, _) = store.rotate(&token).unwrap();
+        assert_eq!(username, "admin");
+        assert_eq!(first, second);
+
+        // Once the successor rotates too, the racing refresh follows it
+        let (_, third, _) = store.rotate(&first).unwrap();
+        assert_eq!(store.rotate(&token).unwrap().1, third);
+        assert!(store.rotate(&third).is_ok());
+    }
+}
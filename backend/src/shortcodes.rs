@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::LazyLock;
+use pulldown_cmark::{Event, Parser, Tag};
+use regex::Regex;
+use crate::models::*;
+use crate::utils::markdown_options;
+
+/// Origin the `youtube` shortcode embeds videos from; the public CSP must allow it in `frame-src`
+pub const YOUTUBE_EMBED_ORIGIN: &str = "https://www.youtube-nocookie.com";
+
+/// Data available to shortcodes while a markdown file is being rendered
+#[derive(Debug, Clone, Default)]
+pub struct ShortcodeContext {
+    /// GitHub projects keyed by "owner/repo", usually a snapshot of the GitHub cache
+    pub github_projects: HashMap<String, GitHubProject>,
+}
+
+/// A single parsed `{{< name args >}}` shortcode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shortcode {
+    pub name: String,
+    pub positional: Vec<String>,
+    pub named: HashMap<String, String>,
+}
+
+impl Shortcode {
+    /// Get an argument by name, falling back to its position
+    pub fn arg(&self, name: &str, position: usize) -> Option<&str> {
+        self.named
+            .get(name)
+            .map(|s| s.as_str())
+            .or_else(|| self.positional.get(position).map(|s| s.as_str()))
+    }
+}
+
+static SHORTCODE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{\{<\s*([a-zA-Z][a-zA-Z0-9_-]*)((?:[^>]|>[^}])*?)\s*>\}\}").unwrap()
+});
+
+static SHORTCODE_ARG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?:([a-zA-Z_][a-zA-Z0-9_-]*)=)?(?:"([^"]*)"|(\S+))"#).unwrap()
+});
+
+static YOUTUBE_ID_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9_-]{1,32}$").unwrap());
+
+static OWNER_REPO_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([a-zA-Z0-9][a-zA-Z0-9-]*)/([a-zA-Z0-9_.-]+)$").unwrap()
+});
+
+/// Parse the argument list of a shortcode: `owner/repo`, `"quoted value"` or `key="value"`
+fn parse_shortcode_args(args: &str) -> (Vec<String>, HashMap<String, String>) {
+    let mut positional = Vec::new();
+    let mut named = HashMap::new();
+
+    for caps in SHORTCODE_ARG_REGEX.captures_iter(args) {
+        let value = caps.get(2)
+            .or_else(|| caps.get(3))
+            .map(|m| m.as_str().to_string())
+            .unwrap_or_default();
+
+        match caps.get(1) {
+            Some(key) => {
+                named.insert(key.as_str().to_string(), value);
+            }
+            None => positional.push(value),
+        }
+    }
+
+    (positional, named)
+}
+
+/// A run of a markdown document that is either code or text shortcodes apply to
+struct Segment<'a> {
+    text: &'a str,
+    is_code: bool,
+}
+
+/// Split a markdown document into code and the text between it
+///
+/// Code is whatever the renderer treats as code: fenced and indented code
+/// blocks and inline code spans. The document is parsed with the renderer's own
+/// parser, so a fence is only closed by a fence of the same character that is at
+/// least as long.
+fn segments(markdown: &str) -> Vec<Segment<'_>> {
+    let code_ranges: Vec<Range<usize>> = Parser::new_ext(markdown, markdown_options())
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Start(Tag::CodeBlock(_)) | Event::Code(_)))
+        .map(|(_, range)| range)
+        .collect();
+
+    let mut segments = Vec::new();
+    let mut position = 0;
+    for range in code_ranges {
+        // Code blocks contain no code spans, so ranges never overlap
+        if range.start > position {
+            segments.push(Segment { text: &markdown[position..range.start], is_code: false });
+        }
+        segments.push(Segment { text: &markdown[range.clone()], is_code: true });
+        position = range.end;
+    }
+    if position < markdown.len() {
+        segments.push(Segment { text: &markdown[position..], is_code: false });
+    }
+    segments
+}
+
+fn parse_shortcode(caps: &regex::Captures) -> Shortcode {
+    let (positional, named) = parse_shortcode_args(&caps[2]);
+    Shortcode {
+        name: caps[1].to_string(),
+        positional,
+        named,
+    }
+}
+
+/// Find every shortcode in a markdown document, ignoring code
+pub fn find_shortcodes(markdown: &str) -> Vec<Shortcode> {
+    let mut shortcodes = Vec::new();
+
+    for segment in segments(markdown).into_iter().filter(|segment| !segment.is_code) {
+        shortcodes.extend(SHORTCODE_REGEX.captures_iter(segment.text).map(|caps| parse_shortcode(&caps)));
+    }
+
+    shortcodes
+}
+
+/// Collect the "owner/repo" references of all `github` shortcodes in a document
+pub fn find_github_shortcode_repos(markdown: &str) -> Vec<(String, String)> {
+    let mut repos = Vec::new();
+
+    for shortcode in find_shortcodes(markdown) {
+        if shortcode.name != "github" {
+            continue;
+        }
+        if let Some((owner, repo)) = shortcode.arg("repo", 0).and_then(split_owner_repo) {
+            if !repos.contains(&(owner.clone(), repo.clone())) {
+                repos.push((owner, repo));
+            }
+        }
+    }
+
+    repos
+}
+
+/// Expand all shortcodes in a markdown document into HTML
+///
+/// Shortcodes inside code blocks and code spans are left untouched, as are
+/// unknown shortcodes, so documentation about shortcodes can still be written.
+pub fn expand_shortcodes(markdown: &str, context: &ShortcodeContext) -> String {
+    let mut output = String::with_capacity(markdown.len());
+
+    for segment in segments(markdown) {
+        if segment.is_code {
+            output.push_str(segment.text);
+            continue;
+        }
+        let expanded = SHORTCODE_REGEX.replace_all(segment.text, |caps: &regex::Captures| {
+            render_shortcode(&parse_shortcode(caps), context).unwrap_or_else(|| caps[0].to_string())
+        });
+        output.push_str(&expanded);
+    }
+
+    output
+}
+
+/// Render a single shortcode, returning None for unknown or malformed shortcodes
+fn render_shortcode(shortcode: &Shortcode, context: &ShortcodeContext) -> Option<String> {
+    match shortcode.name.as_str() {
+        "github" => render_github_shortcode(shortcode, context),
+        "youtube" => render_youtube_shortcode(shortcode),
+        "figure" => render_figure_shortcode(shortcode),
+        _ => None,
+    }
+}
+
+fn render_github_shortcode(shortcode: &Shortcode, context: &ShortcodeContext) -> Option<String> {
+    let (owner, repo) = shortcode.arg("repo", 0).and_then(split_owner_repo)?;
+    let key = format!("{}/{}", owner, repo);
+
+    let html = match context.github_projects.get(&key) {
+        Some(project) => {
+            let description = project.description.as_deref().unwrap_or("");
+            format!(
+                "<div class=\"shortcode-github\"><a href=\"{}\" target=\"_blank\" rel=\"noopener\"><span class=\"shortcode-github-name\">{}</span></a><p class=\"shortcode-github-description\">{}</p><span class=\"shortcode-github-stars\">★ {}</span> <span class=\"shortcode-github-forks\">⑂ {}</span></div>",
+                escape_html(&project.url),
+                escape_html(&key),
+                escape_html(description),
+                project.stars,
+                project.forks,
+            )
+        }
+        // Still link to the repository when GitHub data is unavailable
+        None => format!(
+            "<div class=\"shortcode-github\"><a href=\"https://github.com/{}\" target=\"_blank\" rel=\"noopener\"><span class=\"shortcode-github-name\">{}</span></a></div>",
+            escape_html(&key),
+            escape_html(&key),
+        ),
+    };
+
+    Some(html)
+}
+
+fn render_youtube_shortcode(shortcode: &Shortcode) -> Option<String> {
+    let id = shortcode.arg("id", 0)?;
+    if !YOUTUBE_ID_REGEX.is_match(id) {
+        return None;
+    }
+
+    let title = shortcode.arg("title", 1).unwrap_or("YouTube video");
+    Some(format!(
+        "<div class=\"shortcode-youtube\"><iframe src=\"{}/embed/{}\" title=\"{}\" frameborder=\"0\" allow=\"accelerometer; encrypted-media; gyroscope; picture-in-picture\" allowfullscreen loading=\"lazy\"></iframe></div>",
+        YOUTUBE_EMBED_ORIGIN,
+        id,
+        escape_html(title),
+    ))
+}
+
+/// Whether an image source is a relative path or an http(s) URL; anything else,
+/// such as `javascript:` or `data:` URLs, is refused
+fn is_allowed_image_src(src: &str) -> bool {
+    // Browsers drop tabs and newlines inside URLs, so "java\tscript:" is still a scheme
+    if src.is_empty() || src.chars().any(|c| c.is_control() || c == '\\') {
+        return false;
+    }
+
+    let lower = src.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        return true;
+    }
+
+    // A relative path has no scheme: no ':' before the first '/', '?' or '#'
+    !src.starts_with("//") && !src.split(['/', '?', '#']).next().unwrap_or_default().contains(':')
+}
+
+fn render_figure_shortcode(shortcode: &Shortcode) -> Option<String> {
+    let src = shortcode.arg("src", 0)?.trim();
+    if !is_allowed_image_src(src) {
+        return None;
+    }
+
+    let caption = shortcode.arg("caption", 1);
+    let alt = shortcode.arg("alt", 2).or(caption).unwrap_or("");
+
+    let mut html = format!(
+        "<figure class=\"shortcode-figure\"><img src=\"{}\" alt=\"{}\" loading=\"lazy\" />",
+        escape_html(src),
+        escape_html(alt),
+    );
+    if let Some(caption) = caption {
+        html.push_str(&format!("<figcaption>{}</figcaption>", escape_html(caption)));
+    }
+    html.push_str("</figure>");
+
+    Some(html)
+}
+
+/// Split an "owner/repo" reference, rejecting anything that isn't a plain GitHub name
+///
+/// Both parts end up in GitHub API URLs, so owners follow GitHub's rule (letters,
+/// digits and hyphens, not starting with a hyphen) and repos may not be `.` or `..`.
+fn split_owner_repo(value: &str) -> Option<(String, String)> {
+    let caps = OWNER_REPO_REGEX.captures(value)?;
+    if caps[2].chars().all(|c| c == '.') {
+        return None;
+    }
+    Some((caps[1].to_string(), caps[2].to_string()))
+}
+
+/// Escape text for safe inclusion in HTML attributes and content
+pub fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owner_repo_rejects_path_segments() {
+        assert_eq!(split_owner_repo("rust-lang/rust.vim"), Some(("rust-lang".to_string(), "rust.vim".to_string())));
+        assert_eq!(split_owner_repo("../.."), None);
+        assert_eq!(split_owner_repo("owner/.."), None);
+        assert_eq!(split_owner_repo("owner/."), None);
+        assert_eq!(split_owner_repo("-owner/repo"), None);
+        assert_eq!(split_owner_repo("own_er/repo"), None);
+        assert_eq!(split_owner_repo("owner/repo/issues"), None);
+    }
+
+    #[test]
+    fn expands_to_html() {
+        let mut context = ShortcodeContext::default();
+        context.github_projects.insert("octo/widget".to_string(), GitHubProject {
+            owner: "octo".to_string(),
+            repo: "widget".to_string(),
+            display_name: "Widget".to_string(),
+            readme_html: String::new(),
+            url: "https://github.com/octo/widget".to_string(),
+            stars: 42,
+            forks: 7,
+            description: Some("A widget".to_string()),
+            feature: None,
+            image: None,
+        });
+
+        let markdown = "{{< github octo/widget >}}\n\n{{< youtube dQw4w9WgXcQ >}}\n\n{{< figure src=\"/img/a.png\" caption=\"A <caption>\" >}}\n";
+        let html = expand_shortcodes(markdown, &context);
+
+        assert!(html.contains("https://github.com/octo/widget"));
+        assert!(html.contains("★ 42"));
+        assert!(html.contains("⑂ 7"));
+        assert!(html.contains("youtube-nocookie.com/embed/dQw4w9WgXcQ"));
+        assert!(html.contains("<figcaption>A &lt;caption&gt;</figcaption>"));
+        assert!(!html.contains("{{<"));
+    }
+
+    #[test]
+    fn leaves_code_blocks_and_unknown_shortcodes_alone() {
+        let markdown = "```\n{{< youtube abc >}}\n```\n{{< unknown thing >}}\n{{< github not-a-repo >}}\n";
+        let html = expand_shortcodes(markdown, &ShortcodeContext::default());
+
+        assert!(html.contains("```\n{{< youtube abc >}}\n```"));
+        assert!(html.contains("{{< unknown thing >}}"));
+        assert!(html.contains("{{< github not-a-repo >}}"));
+    }
+
+    #[test]
+    fn leaves_code_spans_indented_code_and_longer_fences_alone() {
+        let markdown = "Use `{{< youtube abc >}}` inline.\n\n    {{< youtube indented >}}\n\n````\n```\n{{< youtube fenced >}}\n````\n~~~\n```\n{{< youtube tilde >}}\n~~~\n{{< youtube after >}}\n";
+        let html = expand_shortcodes(markdown, &ShortcodeContext::default());
+
+        assert!(html.contains("`{{< youtube abc >}}`"));
+        assert!(html.contains("    {{< youtube indented >}}"));
+        assert!(html.contains("{{< youtube fenced >}}"));
+        assert!(html.contains("{{< youtube tilde >}}"));
+        assert!(!html.contains("{{< youtube after >}}"));
+        assert_eq!(find_shortcodes(markdown).len(), 1);
+    }
+
+    #[test]
+    fn figures_only_accept_relative_and_http_sources() {
+        for src in ["/img/a.png", "img/a.png", "../img/a.png", "a.png?v=2#top", "https://cdn.example.com/a.png", "HTTP://example.com/a.png"] {
+            assert!(is_allowed_image_src(src), "{}", src);
+        }
+        for src in ["javascript:alert(1)", "JaVaScRiPt:alert(1)", "java\tscript:alert(1)", "data:image/svg+xml,<svg/>", "vbscript:x", "//evil.example/a.png", "\\\\evil\\a.png", ""] {
+            assert!(!is_allowed_image_src(src), "{}", src);
+        }
+
+        let html = expand_shortcodes("{{< figure src=\"data:text/html,<b>\" >}}\n", &ShortcodeContext::default());
+        assert!(!html.contains("<img"));
+    }
+
+    #[test]
+    fn github_repos_are_found_once() {
+        assert_eq!(find_github_shortcode_repos("{{< github a/b >}} {{< github repo=\"a/b\" >}}"), vec![("a".to_string(), "b".to_string())]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use chrono::Utc;
+
+/// Failures allowed before backoff kicks in
+const FREE_ATTEMPTS: u32 = 3;
+/// First backoff delay in seconds, doubled on every further failure
+const BASE_BACKOFF_SECONDS: i64 = 2;
+/// Longest backoff delay between attempts
+const MAX_BACKOFF_SECONDS: i64 = 5 * 60;
+/// Failures after which an IP is locked out of a username entirely
+const USERNAME_LOCKOUT_THRESHOLD: u32 = 10;
+/// An IP may legitimately try several accounts, so it gets more room than a username
+const IP_LOCKOUT_THRESHOLD: u32 = 30;
+/// Failures against a username from every IP only ever back off; a hard lockout
+/// here would let anyone lock the admin out by guessing wrong on purpose
+const NO_LOCKOUT: u32 = u32::MAX;
+/// Longest backoff for a username across every IP, kept short for the same reason
+const USERNAME_MAX_BACKOFF_SECONDS: i64 = 8;
+/// How long a lockout lasts
+const LOCKOUT_SECONDS: i64 = 15 * 60;
+/// Failure counts are forgotten after this long without a new failure
+const FAILURE_WINDOW_SECONDS: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Default)]
+struct AttemptState {
+    failures: u32,
+    last_failure: i64,
+    blocked_until: i64,
+}
+
+/// Per-IP and per-username login throttling with exponential backoff, and
+/// lockouts per IP and per IP and username
+#[derive(Default)]
+pub struct LoginThrottle {
+    attempts: Mutex<HashMap<String, AttemptState>>,
+}
+
+fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+fn username_key(username: &str) -> String {
+    format!("user:{}", username.to_lowercase())
+}
+
+fn ip_username_key(ip: &str, username: &str) -> String {
+    format!("ip-user:{}:{}", ip, username.to_lowercase())
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seconds the caller must wait before another attempt, or None if allowed now
+    pub fn check(&self, ip: &str, username: &str) -> Option<i64> {
+        let attempts = self.attempts.lock().unwrap();
+        let now = Utc::now().timestamp();
+
+        [ip_key(ip), username_key(username), ip_username_key(ip, username)]
+            .iter()
+            .filter_map(|key| attempts.get(key))
+            .map(|state| state.blocked_until - now)
+            .filter(|wait| *wait > 0)
+            .max()
+    }
+
+    /// Record a failed attempt, returning the resulting wait in seconds (0 if none)
+    ///
+    /// Entries whose failures have been forgotten are evicted here, so the map only
+    /// holds clients that failed within the last window.
+    pub fn record_failure(&self, ip: &str, username: &str) -> i64 {
+        let mut attempts = self.attempts.lock().unwrap();
+        let now = Utc::now().timestamp();
+        attempts.retain(|_, state| now - state.last_failure < FAILURE_WINDOW_SECONDS || state.blocked_until > now);
+
+        let mut wait = 0;
+        for (key, lockout_threshold, max_backoff) in [
+            (ip_key(ip), IP_LOCKOUT_THRESHOLD, MAX_BACKOFF_SECONDS),
+            (username_key(username), NO_LOCKOUT, USERNAME_MAX_BACKOFF_SECONDS),
+            (ip_username_key(ip, username), USERNAME_LOCKOUT_THRESHOLD, MAX_BACKOFF_SECONDS),
+        ] {
+            let state = attempts.entry(key).or_default();
+            state.failures += 1;
+            state.last_failure = now;
+
+            let delay = if state.failures >= lockout_threshold {
+                LOCKOUT_SECONDS
+            } else if state.failures > FREE_ATTEMPTS {
+                let exponent = (state.failures - FREE_ATTEMPTS - 1).min(16);
+                (BASE_BACKOFF_SECONDS << exponent).min(max_backoff)
+            } else {
+                0
+            };
+            state.blocked_until = state.blocked_until.max(now + delay);
+            wait = wait.max(state.blocked_until - now);
+        }
+
+        wait
+    }
+
+    /// Clear the username's failure history after a successful login
+    ///
+    /// The IP's own count is kept, so logging into one account does not reset the
+    /// backoff for guesses against others.
+    pub fn record_success(&self, ip: &str, username: &str) {
+        let mut attempts = self.attempts.lock().unwrap();
+        attempts.remove(&username_key(username));
+        attempts.remove(&ip_username_key(ip, username));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_back_off_after_a_few_free_attempts() {
+        let throttle = LoginThrottle::new();
+        for _ in 0..3 {
+            assert_eq!(throttle.record_failure("10.0.0.1", "admin"), 0);
+        }
+        assert!(throttle.check("10.0.0.1", "admin").is_none());
+
+        let first_delay = throttle.record_failure("10.0.0.1", "admin");
+        assert!(first_delay > 0);
+        assert!(throttle.record_failure("10.0.0.1", "admin") > first_delay);
+    }
+
+    #[test]
+    fn usernames_and_ips_are_throttled_separately() {
+        let throttle = LoginThrottle::new();
+        for _ in 0..5 {
+            throttle.record_failure("10.0.0.1", "admin");
+        }
+
+        // The username stays throttled from another IP, and the IP for another username
+        assert!(throttle.check("10.0.0.2", "Admin").is_some());
+        assert!(throttle.check("10.0.0.1", "editor").is_some());
+        assert!(throttle.check("10.0.0.2", "editor").is_none());
+    }
+
+    #[test]
+    fn repeated_failures_lock_the_ip_out_of_the_username_until_a_success() {
+        let throttle = LoginThrottle::new();
+        for _ in 0..10 {
+            throttle.record_failure("10.0.0.3", "admin");
+        }
+        assert!(throttle.check("10.0.0.3", "admin").unwrap() > 600);
+        // Other IPs only see the username's short backoff, never the lockout
+        assert!(throttle.check("10.0.0.4", "admin").unwrap() <= USERNAME_MAX_BACKOFF_SECONDS);
+
+        throttle.record_success("10.0.0.3", "admin");
+        assert!(throttle.check("10.0.0.3", "admin").unwrap() <= MAX_BACKOFF_SECONDS);
+        assert!(throttle.check("10.0.0.4", "admin").is_none());
+    }
+
+    #[test]
+    fn a_success_does_not_reset_the_ips_backoff_for_other_usernames() {
+        let throttle = LoginThrottle::new();
+        for _ in 0..5 {
+            throttle.record_failure("10.0.0.8", "admin");
+        }
+
+        throttle.record_success("10.0.0.8", "editor");
+        assert!(throttle.check("10.0.0.8", "admin").is_some());
+        assert!(throttle.check("10.0.0.8", "viewer").is_some());
+    }
+
+    #[test]
+    fn failures_from_many_ips_never_lock_the_username_out() {
+        let throttle = LoginThrottle::new();
+        for attempt in 0..50 {
+            throttle.record_failure(&format!("10.0.1.{}", attempt), "admin");
+        }
+        assert!(throttle.check("10.0.0.5", "admin").unwrap() <= USERNAME_MAX_BACKOFF_SECONDS);
+    }
+
+    #[test]
+    fn forgotten_failures_are_evicted_on_the_next_failure() {
+        let throttle = LoginThrottle::new();
+        throttle.record_failure("10.0.0.6", "admin");
+        for state in throttle.attempts.lock().unwrap().values_mut() {
+            state.last_failure -= FAILURE_WINDOW_SECONDS;
+        }
+
+        throttle.record_failure("10.0.0.7", "editor");
+        let attempts = throttle.attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts.keys().all(|key| !key.contains("10.0.0.6") && !key.ends_with(":admin")));
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, Nonce};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::error::AppError;
+use crate::utils::write_file_atomic;
+
+/// TOTP time step in seconds (RFC 6238 default)
+const TOTP_STEP_SECONDS: i64 = 30;
+/// Number of digits in a TOTP code
+const TOTP_DIGITS: u32 = 6;
+/// Accept codes from one step before and after the current one to allow clock drift
+const TOTP_ALLOWED_DRIFT: i64 = 1;
+/// Issuer shown in authenticator apps
+const TOTP_ISSUER: &str = "Portfolio";
+/// Number of recovery codes generated on enrollment
+const RECOVERY_CODE_COUNT: usize = 10;
+/// How long a password-verified login waits for its second factor
+pub const TWO_FACTOR_CHALLENGE_SECONDS: i64 = 5 * 60;
+/// Wrong codes allowed against one challenge before it is discarded
+const MAX_CHALLENGE_ATTEMPTS: u32 = 5;
+/// Prefix of every stored TOTP secret, naming the encryption scheme
+const ENCRYPTED_SECRET_PREFIX: &str = "enc:v1:";
+/// AES-GCM nonce length in bytes
+const NONCE_LENGTH: usize = 12;
+
+/// Generate a random 160-bit TOTP secret, base32 encoded
+pub fn generate_totp_secret() -> String {
+    let mut secret = [0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    data_encoding::BASE32_NOPAD.encode(&secret)
+}
+
+/// Compute the TOTP code for a base32 secret at the given time step
+pub fn totp_code(secret: &str, step: i64) -> Result<String, Box<dyn std::error::Error>> {
+    let key = data_encoding::BASE32_NOPAD.decode(secret.trim_end_matches('=').as_bytes())?;
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(&key)?;
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation (RFC 4226 section 5.3)
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    Ok(format!("{:0width$}", binary % 10u32.pow(TOTP_DIGITS), width = TOTP_DIGITS as usize))
+}
+
+/// The TOTP time step for a unix timestamp
+pub fn totp_step(timestamp: i64) -> i64 {
+    timestamp / TOTP_STEP_SECONDS
+}
+
+/// Find the time step within the allowed drift whose code matches, if any
+fn matching_totp_step(secret: &str, code: &str, timestamp: i64) -> Option<i64> {
+    let code = code.trim();
+    let current = totp_step(timestamp);
+
+    (current - TOTP_ALLOWED_DRIFT..=current + TOTP_ALLOWED_DRIFT).find(|step| {
+        totp_code(secret, *step)
+            .map(|expected| constant_time_eq(expected.as_bytes(), code.as_bytes()))
+            .unwrap_or(false)
+    })
+}
+
+/// Build the otpauth:// URI authenticator apps import, usually via a QR code
+pub fn otpauth_uri(username: &str, secret: &str) -> String {
+    let label = format!("{}:{}", TOTP_ISSUER, username);
+    format!(
+        "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        url_encode(&label),
+        secret,
+        url_encode(TOTP_ISSUER),
+        TOTP_DIGITS,
+        TOTP_STEP_SECONDS
+    )
+}
+
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Compare two byte strings without short-circuiting on the first difference
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 5];
+            OsRng.fill_bytes(&mut bytes);
+            let code = data_encoding::HEXLOWER.encode(&bytes);
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Encrypts TOTP secrets at rest with AES-256-GCM under a key from the configuration
+struct SecretCipher(Aes256Gcm);
+
+impl SecretCipher {
+    /// A cipher keyed with the SHA-256 of `key`, so any long random string works
+    fn new(key: &str) -> Self {
+        SecretCipher(aes_gcm::KeyInit::new(&Sha256::digest(key.as_bytes())))
+    }
+
+    /// A cipher with a random key, for stores that are never written to disk
+    fn ephemeral() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        SecretCipher(aes_gcm::KeyInit::new(&key.into()))
+    }
+
+    fn encrypt(&self, secret: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .0
+            .encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+            .map_err(|_| "Failed to encrypt two-factor secret")?;
+        Ok(format!("{}{}", ENCRYPTED_SECRET_PREFIX, data_encoding::BASE64.encode(&[&nonce[..], &ciphertext].concat())))
+    }
+
+    /// Decrypt a stored secret
+    fn decrypt(&self, stored: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let encoded = stored
+            .strip_prefix(ENCRYPTED_SECRET_PREFIX)
+            .ok_or("Stored two-factor secret is not encrypted")?;
+
+        let bytes = data_encoding::BASE64.decode(encoded.as_bytes())?;
+        if bytes.len() < NONCE_LENGTH {
+            return Err("Stored two-factor secret is truncated".into());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let secret = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Cannot decrypt two-factor secrets; TWO_FACTOR_KEY does not match the one they were stored with")?;
+        Ok(String::from_utf8(secret)?)
+    }
+}
+
+/// Persisted second-factor settings of one user
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TwoFactorRecord {
+    /// Plaintext in memory, encrypted in the file
+    secret: String,
+    /// False while enrollment awaits its first valid code
+    enabled: bool,
+    /// SHA-256 hashes of unused recovery codes
+    recovery_codes: Vec<String>,
+    /// Last accepted time step, so a code cannot be replayed
+    last_used_step: i64,
+}
+
+/// A login that passed the password check and awaits its second factor
+struct LoginChallenge {
+    username: String,
+    expires_at: i64,
+    attempts: u32,
+}
+
+/// TOTP enrollment, verification and recovery codes for admin users
+pub struct TwoFactorStore {
+    path: Option<PathBuf>,
+    cipher: SecretCipher,
+    records: Mutex<HashMap<String, TwoFactorRecord>>,
+    challenges: Mutex<HashMap<String, LoginChallenge>>,
+}
+
+impl TwoFactorStore {
+    /// Load two-factor settings from `path`, starting empty if it does not exist yet
+    ///
+    /// TOTP secrets are encrypted with `key`.
+    pub fn load(path: &str, key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let cipher = SecretCipher::new(key);
+        let mut records: HashMap<String, TwoFactorRecord> = if Path::new(path).exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        for record in records.values_mut() {
+            record.secret = cipher.decrypt(&record.secret)?;
+        }
+
+        Ok(TwoFactorStore {
+            path: Some(PathBuf::from(path)),
+            cipher,
+            records: Mutex::new(records),
+            challenges: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// A two-factor store that is never written to disk
+    pub fn in_memory() -> Self {
+        TwoFactorStore {
+            path: None,
+            cipher: SecretCipher::ephemeral(),
+            records: Mutex::new(HashMap::new()),
+            challenges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn persist(&self, records: &HashMap<String, TwoFactorRecord>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let encrypted = records
+                .iter()
+                .map(|(username, record)| {
+                    let secret = self.cipher.encrypt(&record.secret)?;
+                    Ok((username, TwoFactorRecord { secret, ..record.clone() }))
+                })
+                .collect::<Result<HashMap<_, _>, Box<dyn std::error::Error>>>()?;
+            write_file_atomic(&path.to_string_lossy(), serde_json::to_string(&encrypted)?.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Whether the user must pass a second factor to log in
+    pub fn is_enabled(&self, username: &str) -> bool {
+        self.records.lock().unwrap().get(username).is_some_and(|record| record.enabled)
+    }
+
+    /// Start enrollment with a fresh secret, returning the secret and its otpauth URI
+    ///
+    /// Two-factor stays off until `confirm_enrollment` sees a valid code, so a user
+    /// cannot lock themselves out with a secret their app never imported.
+    pub fn begin_enrollment(&self, username: &str) -> Result<(String, String), Box<dyn std::error::Error>> {
+        let mut records = self.records.lock().unwrap();
+        if records.get(username).is_some_and(|record| record.enabled) {
+            return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()).into());
+        }
+
+        let secret = generate_totp_secret();
+        records.insert(username.to_string(), TwoFactorRecord {
+            secret: secret.clone(),
+            ..TwoFactorRecord::default()
+        });
+        self.persist(&records)?;
+
+        let uri = otpauth_uri(username, &secret);
+        Ok((secret, uri))
+    }
+
+    /// Enable two-factor after the first valid code, returning new recovery codes
+    pub fn confirm_enrollment(&self, username: &str, code: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut records = self.records.lock().unwrap();
+        let record = records.get_mut(username).ok_or_else(|| AppError::BadRequest("Two-factor enrollment has not been started".to_string()))?;
+        if record.enabled {
+            return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()).into());
+        }
+
+        let step = matching_totp_step(&record.secret, code, Utc::now().timestamp()).ok_or_else(|| AppError::BadRequest("Invalid authentication code".to_string()))?;
+        let recovery_codes = generate_recovery_codes();
+        record.enabled = true;
+        record.last_used_step = step;
+        record.recovery_codes = recovery_codes.iter().map(|code| hash_recovery_code(code)).collect();
+        self.persist(&records)?;
+
+        Ok(recovery_codes)
+    }
+
+    /// Check a TOTP code or single-use recovery code for an enrolled user
+    pub fn verify_code(&self, username: &str, code: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut records = self.records.lock().unwrap();
+        let record = match records.get_mut(username) {
+            Some(record) if record.enabled => record,
+            _ => return Ok(false),
+        };
+
+        if let Some(step) = matching_totp_step(&record.secret, code, Utc::now().timestamp()) {
+            if step <= record.last_used_step {
+                return Ok(false);
+            }
+            record.last_used_step = step;
+            self.persist(&records)?;
+            return Ok(true);
+        }
+
+        let code_hash = hash_recovery_code(code);
+        if let Some(index) = record.recovery_codes.iter().position(|stored| constant_time_eq(stored.as_bytes(), code_hash.as_bytes())) {
+            record.recovery_codes.remove(index);
+            tracing::info!(%username, remaining = record.recovery_codes.len(), "Recovery code used");
+            self.persist(&records)?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Number of unused recovery codes, or None when two-factor is off
+    pub fn remaining_recovery_codes(&self, username: &str) -> Option<usize> {
+        self.records
+            .lock()
+            .unwrap()
+            .get(username)
+            .filter(|record| record.enabled)
+            .map(|record| record.recovery_codes.len())
+    }
+
+    /// Replace the recovery codes of an enrolled user
+    pub fn regenerate_recovery_codes(&self, username: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut records = self.records.lock().unwrap();
+        let record = match records.get_mut(username) {
+            Some(record) if record.enabled => record,
+            _ => return Err(AppError::BadRequest("Two-factor authentication is not enabled".to_string()).into()),
+        };
+
+        let recovery_codes = generate_recovery_codes();
+        record.recovery_codes = recovery_codes.iter().map(|code| hash_recovery_code(code)).collect();
+        self.persist(&records)?;
+        Ok(recovery_codes)
+    }
+
+    /// Turn two-factor off for a user
+    pub fn disable(&self, username: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut records = self.records.lock().unwrap();
+        records.remove(username);
+        self.persist(&records)
+    }
+
+    /// Create a short-lived challenge for a login whose password was correct
+    pub fn create_challenge(&self, username: &str) -> (String, chrono::DateTime<Utc>) {
+        let mut challenges = self.challenges.lock().unwrap();
+        let now = Utc::now();
+        challenges.retain(|_, challenge| challenge.expires_at > now.timestamp());
+
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let expires_at = now + chrono::Duration::seconds(TWO_FACTOR_CHALLENGE_SECONDS);
+        challenges.insert(token.clone(), LoginChallenge {
+            username: username.to_string(),
+            expires_at: expires_at.timestamp(),
+            attempts: 0,
+        });
+
+        (token, expires_at)
+    }
+
+    /// Username a live challenge belongs to
+    pub fn challenge_user(&self, challenge_token: &str) -> Option<String> {
+        let challenges = self.challenges.lock().unwrap();
+        challenges
+            .get(challenge_token)
+            .filter(|challenge| challenge.expires_at > Utc::now().timestamp())
+            .map(|challenge| challenge.username.clone())
+    }
+
+    /// Complete a challenge with a TOTP or recovery code, returning the username
+    ///
+    /// A challenge is consumed on success and discarded after too many wrong codes.
+    pub fn complete_challenge(&self, challenge_token: &str, code: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let username = self.challenge_user(challenge_token).ok_or_else(|| AppError::Unauthorized("Login challenge expired, log in again".to_string()))?;
+
+        if self.verify_code(&username, code)? {
+            self.challenges.lock().unwrap().remove(challenge_token);
+            return Ok(username);
+        }
+
+        let mut challenges = self.challenges.lock().unwrap();
+        if let Some(challenge) = challenges.get_mut(challenge_token) {
+            challenge.attempts += 1;
+            if challenge.attempts >= MAX_CHALLENGE_ATTEMPTS {
+                challenges.remove(challenge_token);
+            }
+        }
+        Err(AppError::Unauthorized("Invalid authentication code".to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn codes_match_the_rfc_6238_vector() {
+        // Appendix B vector (SHA-1, T = 59s), truncated to 6 digits
+        assert_eq!(totp_code("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", totp_step(59)).unwrap(), "287082");
+    }
+
+    #[test]
+    fn enrollment_needs_a_current_code_that_cannot_be_replayed() {
+        let two_factor = TwoFactorStore::in_memory();
+        let (secret, uri) = two_factor.begin_enrollment("admin").unwrap();
+        assert!(uri.starts_with("otpauth://totp/Portfolio%3Aadmin?secret="));
+        assert!(!two_factor.is_enabled("admin"));
+
+        assert!(two_factor.confirm_enrollment("admin", "000000x").is_err());
+        let code = totp_code(&secret, totp_step(Utc::now().timestamp())).unwrap();
+        let recovery_codes = two_factor.confirm_enrollment("admin", &code).unwrap();
+        assert_eq!(recovery_codes.len(), 10);
+        assert!(two_factor.is_enabled("admin"));
+
+        assert!(!two_factor.verify_code("admin", &code).unwrap());
+    }
+
+    #[test]
+    fn secrets_are_encrypted_at_rest() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let path = format!("{}/two_factor.json", dir);
+        let key = "k".repeat(32);
+
+        let (secret, _) = TwoFactorStore::load(&path, &key).unwrap().begin_enrollment("admin").unwrap();
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(stored.contains(ENCRYPTED_SECRET_PREFIX));
+        assert!(!stored.contains(&secret));
+
+        let two_factor = TwoFactorStore::load(&path, &key).unwrap();
+        let code = totp_code(&secret, totp_step(Utc::now().timestamp())).unwrap();
+        assert!(two_factor.confirm_enrollment("admin", &code).is_ok());
+        assert!(TwoFactorStore::load(&path, "another key").is_err());
+    }
+
+    #[test]
+    fn unencrypted_secrets_are_refused() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let path = format!("{}/two_factor.json", dir);
+        std::fs::write(&path, format!(
+            r#"{{"admin":{{"secret":"{}","enabled":true,"recovery_codes":[],"last_used_step":0}}}}"#,
+            generate_totp_secret()
+        )).unwrap();
+
+        assert!(TwoFactorStore::load(&path, "key").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::Path;
+use chrono::{Duration, Utc};
+use crate::error::AppError;
+use crate::history::{copy_recursively, normalize_relative_path};
+use crate::models::*;
+
+const TRASH_METADATA_FILE: &str = "meta.json";
+const TRASH_ITEM_DIR: &str = "item";
+
+/// Move a file or directory, falling back to copy and delete across filesystems
+pub fn move_path(source: &Path, destination: &Path) -> Result<(), std::io::Error> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if fs::rename(source, destination).is_ok() {
+        return Ok(());
+    }
+
+    copy_recursively(source, destination)?;
+    if source.is_dir() {
+        fs::remove_dir_all(source)
+    } else {
+        fs::remove_file(source)
+    }
+}
+
+fn path_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|entry| path_size(&entry.path())).sum())
+            .unwrap_or(0)
+    } else {
+        path.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+    }
+}
+
+/// Move an item from the content directory into the trash
+pub fn move_to_trash(
+    content_path: &str,
+    trash_path: &str,
+    relative_path: &str,
+    user: &str,
+) -> Result<TrashItem, Box<dyn std::error::Error>> {
+    let original_path = normalize_relative_path(relative_path);
+    let source = Path::new(content_path).join(&original_path);
+
+    if original_path.is_empty() {
+        return Err(AppError::BadRequest("Cannot delete the content root".to_string()).into());
+    }
+    if !source.exists() {
+        return Err(AppError::NotFound("File or folder does not exist".to_string()).into());
+    }
+
+    let uuid = uuid::Uuid::new_v4().simple().to_string();
+    let id = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S"), &uuid[..8]);
+    let entry_dir = Path::new(trash_path).join(&id);
+
+    let item = TrashItem {
+        id,
+        name: source
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        original_path,
+        is_directory: source.is_dir(),
+        size: path_size(&source),
+        deleted_at: Utc::now(),
+        deleted_by: user.to_string(),
+    };
+
+    fs::create_dir_all(&entry_dir)?;
+    fs::write(entry_dir.join(TRASH_METADATA_FILE), serde_json::to_string_pretty(&item)?)?;
+
+    if let Err(e) = move_path(&source, &entry_dir.join(TRASH_ITEM_DIR)) {
+        let _ = fs::remove_dir_all(&entry_dir);
+        return Err(e.into());
+    }
+
+    Ok(item)
+}
+
+/// List trashed items, most recently deleted first
+pub fn list_trash(trash_path: &str) -> Result<Vec<TrashItem>, Box<dyn std::error::Error>> {
+    let trash_dir = Path::new(trash_path);
+    if !trash_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut items = Vec::new();
+    for entry in fs::read_dir(trash_dir)? {
+        let entry = entry?;
+        let metadata_path = entry.path().join(TRASH_METADATA_FILE);
+        if let Ok(metadata) = fs::read_to_string(&metadata_path) {
+            match serde_json::from_str::<TrashItem>(&metadata) {
+                Ok(item) => items.push(item),
+                Err(e) => tracing::warn!(path = %metadata_path.display(), error = %e, "Invalid trash metadata"),
+            }
+        }
+    }
+
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+    Ok(items)
+}
+
+fn validate_trash_id(id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(AppError::BadRequest("Invalid trash item id".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Read the metadata of one trashed item
+pub fn get_trash_item(trash_path: &str, id: &str) -> Result<TrashItem, Box<dyn std::error::Error>> {
+    validate_trash_id(id)?;
+    let metadata_path = Path::new(trash_path).join(id).join(TRASH_METADATA_FILE);
+    let metadata = fs::read_to_string(metadata_path)
+        .map_err(|_| AppError::NotFound(format!("Trash item '{}' not found", id)))?;
+    Ok(serde_json::from_str(&metadata)?)
+}
+
+/// Restore a trashed item to its original location, or to `destination_path` if given
+pub fn restore_from_trash(
+    content_path: &str,
+    trash_path: &str,
+    id: &str,
+    destination_path: Option<&str>,
+) -> Result<TrashItem, Box<dyn std::error::Error>> {
+    let mut item = get_trash_item(trash_path, id)?;
+    let relative_path = normalize_relative_path(destination_path.unwrap_or(&item.original_path));
+    let destination = Path::new(content_path).join(&relative_path);
+
+    if destination.exists() {
+        return Err(AppError::Conflict("Destination already exists".to_string()).into());
+    }
+
+    let entry_dir = Path::new(trash_path).join(id);
+    move_path(&entry_dir.join(TRASH_ITEM_DIR), &destination)?;
+    fs::remove_dir_all(&entry_dir)?;
+
+    item.original_path = relative_path;
+    Ok(item)
+}
+
+/// Permanently delete one trashed item
+pub fn purge_trash_item(trash_path: &str, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    get_trash_item(trash_path, id)?;
+    fs::remove_dir_all(Path::new(trash_path).join(id))?;
+    Ok(())
+}
+
+/// Permanently delete every trashed item, returning how many were removed
+pub fn purge_all_trash(trash_path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let items = list_trash(trash_path)?;
+    for item in &items {
+        fs::remove_dir_all(Path::new(trash_path).join(&item.id))?;
+    }
+    Ok(items.len())
+}
+
+/// Apply the retention policy, permanently deleting items older than `retention_days`
+pub fn purge_expired_trash(trash_path: &str, retention_days: i64) -> Result<usize, Box<dyn std::error::Error>> {
+    let cutoff = Utc::now() - Duration::days(retention_days);
+    let mut purged = 0;
+
+    for item in list_trash(trash_path)? {
+        if item.deleted_at < cutoff {
+            fs::remove_dir_all(Path::new(trash_path).join(&item.id))?;
+            purged += 1;
+        }
+    }
+
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn folders_move_to_the_trash_and_back() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let trash_dir = TempDir::new().unwrap();
+        let trash_path = trash_dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+        fs::write(format!("{}/blog/post.md", content_path), "post").unwrap();
+
+        let item = move_to_trash(content_path, trash_path, "blog", "admin").unwrap();
+        assert!(item.is_directory);
+        assert_eq!(item.original_path, "blog");
+        assert!(!Path::new(&format!("{}/blog", content_path)).exists());
+        assert_eq!(list_trash(trash_path).unwrap().len(), 1);
+
+        restore_from_trash(content_path, trash_path, &item.id, None).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/blog/post.md", content_path)).unwrap(), "post");
+        assert!(list_trash(trash_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recent_items_survive_the_retention_policy() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let trash_dir = TempDir::new().unwrap();
+        let trash_path = trash_dir.path().to_str().unwrap();
+        fs::write(format!("{}/post.md", content_path), "post").unwrap();
+
+        move_to_trash(content_path, trash_path, "post.md", "admin").unwrap();
+        assert_eq!(purge_expired_trash(trash_path, 30).unwrap(), 0);
+        assert_eq!(list_trash(trash_path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn items_are_purged_by_id_only() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let trash_dir = TempDir::new().unwrap();
+        let trash_path = trash_dir.path().to_str().unwrap();
+        fs::write(format!("{}/post.md", content_path), "post").unwrap();
+
+        let item = move_to_trash(content_path, trash_path, "post.md", "admin").unwrap();
+        assert!(restore_from_trash(content_path, trash_path, "../escape", None).is_err());
+        purge_trash_item(trash_path, &item.id).unwrap();
+        assert!(list_trash(trash_path).unwrap().is_empty());
+    }
+}
@@ -4,9 +4,17 @@ use std::path::Path;
 use serde_yaml;
 use reqwest;
 use pulldown_cmark::{Parser, Options, html, Event, Tag, CodeBlockKind};
-use regex::Regex;
 use base64::Engine;
+use tracing::warn;
 use crate::models::*;
+use crate::error::AppError;
+use crate::{AppConfig, JwtKey};
+use crate::shortcodes::*;
+use crate::history::normalize_relative_path;
+use crate::metrics::METRICS;
+use crate::sessions::{TokenRevocations, ACCESS_COOKIE_NAME, CSRF_HEADER_NAME};
+use crate::totp::constant_time_eq;
+use crate::api_tokens::{ApiTokenStore, API_TOKEN_PREFIX};
 
 /// Input validation error types
 #[derive(Debug)]
@@ -32,7 +40,7 @@ impl std::error::Error for ValidationError {}
 
 /// Validates category parameter
 /// Categories must be alphanumeric with hyphens/underscores only
-pub fn validate_category(category: &str) -> Result<(), ValidationError> {
+pub fn validate_category(category: &str, allowed_categories: &[String]) -> Result<(), ValidationError> {
     // Check length
     if category.is_empty() {
         return Err(ValidationError::InvalidCategory("Category cannot be empty".to_string()));
@@ -53,8 +61,7 @@ pub fn validate_category(category: &str) -> Result<(), ValidationError> {
     }
     
     // Check against whitelist of allowed categories
-    let allowed_categories = ["project", "blog", "page"];
-    if !allowed_categories.contains(&category) {
+    if !allowed_categories.iter().any(|allowed| allowed == category) {
         return Err(ValidationError::InvalidCategory(format!("Category '{}' not allowed. Allowed: {:?}", category, allowed_categories)));
     }
     
@@ -97,8 +104,13 @@ pub fn validate_slug(slug: &str) -> Result<(), ValidationError> {
 }
 
 /// Creates a safe file path by validating and joining components
-pub fn create_safe_content_path(content_base: &str, category: &str, filename: Option<&str>) -> Result<String, ValidationError> {
-    validate_category(category)?;
+pub fn create_safe_content_path(
+    content_base: &str,
+    allowed_categories: &[String],
+    category: &str,
+    filename: Option<&str>,
+) -> Result<String, ValidationError> {
+    validate_category(category, allowed_categories)?;
     
     let mut path = format!("{}/{}", content_base, category);
     
@@ -126,16 +138,14 @@ pub fn get_content_files(category: &str, content_path: &str) -> Result<Vec<Strin
     let mut files = Vec::new();
     
     if let Ok(entries) = fs::read_dir(&content_dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(extension) = path.extension() {
-                        if extension == "md" {
-                            if let Some(filename) = path.file_name() {
-                                if let Some(filename_str) = filename.to_str() {
-                                    files.push(filename_str.to_string());
-                                }
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                if let Some(extension) = path.extension() {
+                    if extension == "md" {
+                        if let Some(filename) = path.file_name() {
+                            if let Some(filename_str) = filename.to_str() {
+                                files.push(filename_str.to_string());
                             }
                         }
                     }
@@ -148,13 +158,22 @@ pub fn get_content_files(category: &str, content_path: &str) -> Result<Vec<Strin
 }
 
 pub fn parse_markdown_file(file_path: &str, category: &str) -> Result<ContentItem, Box<dyn std::error::Error>> {
+    parse_markdown_file_with_context(file_path, category, &ShortcodeContext::default())
+}
+
+/// Parse a markdown file, expanding shortcodes with the given context
+pub fn parse_markdown_file_with_context(file_path: &str, category: &str, context: &ShortcodeContext) -> Result<ContentItem, Box<dyn std::error::Error>> {
     let content = fs::read_to_string(file_path)?;
-    
+    parse_markdown_source(file_path, &content, category, context)
+}
+
+/// Parse markdown already read from `file_path`, which only provides the slug
+pub fn parse_markdown_source(file_path: &str, content: &str, category: &str, context: &ShortcodeContext) -> Result<ContentItem, Box<dyn std::error::Error>> {
     // Extract frontmatter and content
-    let (frontmatter, markdown_content) = if content.starts_with("---\n") {
-        let end = content[4..].find("\n---\n").unwrap_or(0) + 4;
-        let frontmatter_str = &content[4..end];
-        let content_str = &content[end + 4..];
+    let (frontmatter, markdown_content) = if let Some(rest) = content.strip_prefix("---\n") {
+        let end = rest.find("\n---\n").unwrap_or(0);
+        let frontmatter_str = &rest[..end];
+        let content_str = &rest[end + 4..];
         (frontmatter_str.trim(), content_str.trim())
     } else {
         ("", content.trim())
@@ -171,17 +190,14 @@ pub fn parse_markdown_file(file_path: &str, category: &str) -> Result<ContentIte
             feature: None,
         }
     } else {
-        serde_yaml::from_str(frontmatter)?
+        serde_yaml::from_str(frontmatter).inspect_err(|_| METRICS.markdown_parse_errors.inc())?
     };
     
-    // Convert markdown to HTML
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    options.insert(Options::ENABLE_TASKLISTS);
+    // Expand shortcodes before markdown rendering so they become raw HTML
+    let markdown_content = expand_shortcodes(markdown_content, context);
     
-    let parser = Parser::new_ext(markdown_content, options);
+    // Convert markdown to HTML
+    let parser = Parser::new_ext(&markdown_content, markdown_options());
     let mut html_content = String::new();
     html::push_html(&mut html_content, parser);
     
@@ -201,6 +217,235 @@ pub fn parse_markdown_file(file_path: &str, category: &str) -> Result<ContentIte
     })
 }
 
+/// Parse every markdown file of a category, newest first
+///
+/// Files that fail to parse are logged and skipped. Shortcodes only use GitHub
+/// data already in `github_cache`.
+pub async fn load_content_list(
+    app_config: &AppConfig,
+    category: &str,
+    github_cache: &std::sync::Mutex<std::collections::HashMap<String, CachedGithubProject>>,
+) -> Result<Vec<ContentItem>, io::Error> {
+    let mut content_items = Vec::new();
+    
+    for file in get_content_files(category, &app_config.content_path)? {
+        // Use safe path creation for additional security
+        let file_stem = file.trim_end_matches(".md");
+        match create_safe_content_path(&app_config.content_path, &app_config.allowed_categories, category, Some(file_stem)) {
+            Ok(file_path) => {
+                let markdown = match fs::read_to_string(&file_path) {
+                    Ok(markdown) => markdown,
+                    Err(e) => {
+                        warn!(path = %file_path, error = %e, "Failed to read content file");
+                        continue;
+                    }
+                };
+                let shortcode_context = build_shortcode_context(&markdown, github_cache, app_config, false).await;
+                match parse_markdown_source(&file_path, &markdown, category, &shortcode_context) {
+                    Ok(content) => content_items.push(content),
+                    Err(e) => warn!(path = %file_path, error = %e, "Failed to parse content file"),
+                }
+            }
+            Err(e) => warn!(%file, error = %e, "Invalid content file path"),
+        }
+    }
+    
+    // Sort by date (newest first)
+    content_items.sort_by(|a, b| b.metadata.date.cmp(&a.metadata.date));
+    Ok(content_items)
+}
+
+/// Categories whose tags are listed by the tags endpoint
+pub const TAGGED_CATEGORIES: &[&str] = &["project", "blog"];
+
+/// All tags used by projects and blog posts, sorted and deduplicated
+pub fn load_content_tags(app_config: &AppConfig) -> Vec<String> {
+    let mut all_tags = std::collections::HashSet::new();
+    
+    for category in TAGGED_CATEGORIES {
+        // Skip categories disabled in the configuration
+        if validate_category(category, &app_config.allowed_categories).is_err() {
+            continue;
+        }
+        
+        if let Ok(files) = get_content_files(category, &app_config.content_path) {
+            for file in files {
+                let file_stem = file.trim_end_matches(".md");
+                if let Ok(file_path) = create_safe_content_path(&app_config.content_path, &app_config.allowed_categories, category, Some(file_stem)) {
+                    if let Ok(content) = parse_markdown_file(&file_path, category) {
+                        all_tags.extend(content.metadata.tags);
+                    }
+                }
+            }
+        }
+    }
+    
+    let mut tags: Vec<String> = all_tags.into_iter().collect();
+    tags.sort();
+    tags
+}
+
+/// Turn a title into a file name slug: lowercase ASCII words joined by hyphens
+pub fn slugify(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Create `<category>/<slug>.md` with frontmatter for `title`, returning its path
+///
+/// Refuses to overwrite an existing file.
+pub fn scaffold_content(content_path: &str, category: &str, title: &str, date: chrono::NaiveDate) -> Result<String, Box<dyn std::error::Error>> {
+    let title = title.trim();
+    let slug = slugify(title);
+    if slug.is_empty() {
+        return Err("Title must contain at least one letter or number".into());
+    }
+    validate_slug(&slug)?;
+    
+    let dir = format!("{}/{}", content_path, category);
+    let file_path = format!("{}/{}.md", dir, slug);
+    if Path::new(&file_path).exists() {
+        return Err(format!("{} already exists", file_path).into());
+    }
+    
+    // JSON strings are valid double-quoted YAML scalars, matching the existing content
+    let markdown = format!(
+        "---\ntitle: {}\ndate: \"{}\"\ntags: []\ndescription: \"\"\nfeature: false\n---\n\n# {}\n\n",
+        serde_json::to_string(title)?,
+        date.format("%Y-%m-%d"),
+        title
+    );
+    
+    fs::create_dir_all(&dir)?;
+    write_file_atomic(&file_path, markdown.as_bytes())?;
+    Ok(file_path)
+}
+
+/// Check every content file parses and has usable metadata
+///
+/// Covers all allowed categories and `github/config.yaml`; an empty result means
+/// the content is ready to publish.
+pub fn lint_content(app_config: &AppConfig) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |path: &str, message: String| issues.push(LintIssue { path: path.to_string(), message });
+    
+    for category in &app_config.allowed_categories {
+        let files = match get_content_files(category, &app_config.content_path) {
+            Ok(files) => files,
+            Err(e) => {
+                issue(category, format!("cannot list category: {}", e));
+                continue;
+            }
+        };
+        
+        for file in files {
+            let relative_path = format!("{}/{}", category, file);
+            let file_stem = file.trim_end_matches(".md");
+            if let Err(e) = validate_slug(file_stem) {
+                issue(&relative_path, format!("invalid file name: {}", e));
+                continue;
+            }
+            
+            let file_path = format!("{}/{}", app_config.content_path, relative_path);
+            let raw = match fs::read_to_string(&file_path) {
+                Ok(raw) => raw,
+                Err(e) => {
+                    issue(&relative_path, format!("cannot read file: {}", e));
+                    continue;
+                }
+            };
+            if !raw.starts_with("---\n") || !raw[4..].contains("\n---\n") {
+                issue(&relative_path, "missing frontmatter block".to_string());
+                continue;
+            }
+            
+            match parse_markdown_file(&file_path, category) {
+                Ok(content) => {
+                    if content.metadata.title.trim().is_empty() {
+                        issue(&relative_path, "title is empty".to_string());
+                    }
+                    if chrono::NaiveDate::parse_from_str(&content.metadata.date, "%Y-%m-%d").is_err() {
+                        issue(&relative_path, format!("date '{}' is not YYYY-MM-DD", content.metadata.date));
+                    }
+                }
+                Err(e) => issue(&relative_path, format!("invalid frontmatter: {}", e)),
+            }
+        }
+    }
+    
+    if let Err(e) = load_github_config(&app_config.content_path) {
+        issue("github/config.yaml", format!("cannot load GitHub config: {}", e));
+    }
+    
+    issues
+}
+
+/// Build the shortcode context for markdown content from the GitHub cache
+///
+/// Repositories referenced by `github` shortcodes that are missing from the cache
+/// (or expired) are fetched when `fetch_missing` is set and stored in the cache.
+/// Request handlers pass `false` so a page view never waits on GitHub.
+pub async fn build_shortcode_context(
+    markdown: &str,
+    github_cache: &std::sync::Mutex<std::collections::HashMap<String, CachedGithubProject>>,
+    app_config: &AppConfig,
+    fetch_missing: bool,
+) -> ShortcodeContext {
+    let mut context = ShortcodeContext::default();
+    
+    for (owner, repo) in find_github_shortcode_repos(markdown) {
+        let cache_key = format!("{}/{}", owner, repo);
+        
+        let cached_project = {
+            let cache = github_cache.lock().unwrap();
+            cache.get(&cache_key).cloned()
+        };
+        
+        if let Some(cached) = cached_project {
+            if Utc::now() - cached.cached_at < Duration::seconds(app_config.github_cache_ttl_seconds) || !fetch_missing {
+                METRICS.record_cache_lookup("github", true);
+                context.github_projects.insert(cache_key, cached.project);
+                continue;
+            }
+        }
+        METRICS.record_cache_lookup("github", false);
+        
+        if !fetch_missing {
+            continue;
+        }
+        
+        let repo_config = GitHubRepo {
+            owner: owner.clone(),
+            repo: repo.clone(),
+            display_name: repo.clone(),
+            feature: None,
+            image: None,
+        };
+        
+        match fetch_github_project(&repo_config, app_config).await {
+            Ok(project) => {
+                {
+                    let mut cache = github_cache.lock().unwrap();
+                    cache.insert(cache_key.clone(), CachedGithubProject {
+                        project: project.clone(),
+                        cached_at: Utc::now(),
+                    });
+                }
+                context.github_projects.insert(cache_key, project);
+            }
+            Err(e) => {
+                warn!(%owner, %repo, error = %e, "Failed to fetch GitHub project for shortcode");
+            }
+        }
+    }
+    
+    context
+}
+
 pub fn load_github_config(content_path: &str) -> Result<GitHubConfig, Box<dyn std::error::Error>> {
     let config_path = format!("{}/github/config.yaml", content_path);
     let config_content = fs::read_to_string(&config_path)?;
@@ -208,30 +453,187 @@ pub fn load_github_config(content_path: &str) -> Result<GitHubConfig, Box<dyn st
     Ok(config)
 }
 
-pub async fn fetch_github_project(repo: &GitHubRepo) -> Result<GitHubProject, Box<dyn std::error::Error>> {
+/// Markdown extensions enabled for content and READMEs
+pub fn markdown_options() -> Options {
+    Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_TASKLISTS
+}
+
+/// File under the data path persisting the GitHub project cache between runs
+pub const GITHUB_CACHE_FILE: &str = "github_cache.json";
+
+/// Load the persisted GitHub cache, starting empty if there is none yet
+pub fn load_github_cache(path: &str) -> Result<std::collections::HashMap<String, CachedGithubProject>, Box<dyn std::error::Error>> {
+    if !Path::new(path).exists() {
+        return Ok(std::collections::HashMap::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Persist the GitHub cache so the server starts warm
+///
+/// Entries already in the file that were fetched more recently, e.g. by
+/// `github refresh` while the server runs, win over the in-memory ones and are
+/// copied into `github_cache`, so a later save never overwrites them with
+/// stale data.
+pub fn save_github_cache(
+    path: &str,
+    github_cache: &std::sync::Mutex<std::collections::HashMap<String, CachedGithubProject>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut cache = github_cache.lock().unwrap();
+    match load_github_cache(path) {
+        Ok(persisted) => {
+            for (key, entry) in persisted {
+                if cache.get(&key).is_none_or(|cached| cached.cached_at < entry.cached_at) {
+                    cache.insert(key, entry);
+                }
+            }
+        }
+        Err(e) => warn!(path, error = %e, "Overwriting unreadable GitHub cache"),
+    }
+    let json = serde_json::to_string(&*cache)?;
+    write_file_atomic(path, json.as_bytes())?;
+    Ok(())
+}
+
+/// The configured GitHub projects, from the cache while fresh unless `force_refresh`
+///
+/// Repositories the GitHub API fails for are returned with placeholder details.
+pub async fn load_github_projects(
+    app_config: &AppConfig,
+    github_cache: &std::sync::Mutex<std::collections::HashMap<String, CachedGithubProject>>,
+    force_refresh: bool,
+) -> Result<Vec<GitHubProject>, Box<dyn std::error::Error>> {
+    let config = load_github_config(&app_config.content_path)?;
+    let mut projects = Vec::new();
+    
+    for repo in config.repositories {
+        let cache_key = format!("{}/{}", repo.owner, repo.repo);
+        
+        // Check cache first
+        let cached_project = {
+            let cache = github_cache.lock().unwrap();
+            cache.get(&cache_key).cloned()
+        };
+        
+        if let Some(cached) = cached_project {
+            // Check if cache is still valid
+            if !force_refresh && Utc::now() - cached.cached_at < Duration::seconds(app_config.github_cache_ttl_seconds) {
+                METRICS.record_cache_lookup("github", true);
+                let mut project = cached.project;
+                // Add feature and image properties from config
+                project.feature = repo.feature;
+                project.image = repo.image.clone();
+                projects.push(project);
+                continue;
+            }
+        }
+        if !force_refresh {
+            METRICS.record_cache_lookup("github", false);
+        }
+        
+        // Fetch from GitHub API
+        match fetch_github_project(&repo, app_config).await {
+            Ok(mut project) => {
+                // Add feature and image properties from config
+                project.feature = repo.feature;
+                project.image = repo.image.clone();
+                
+                // Cache the project
+                let cached_project = CachedGithubProject {
+                    project: project.clone(),
+                    cached_at: Utc::now(),
+                };
+                
+                {
+                    let mut cache = github_cache.lock().unwrap();
+                    cache.insert(cache_key, cached_project);
+                }
+                
+                projects.push(project);
+            }
+            Err(e) => {
+                warn!(owner = %repo.owner, repo = %repo.repo, error = %e, "Failed to fetch GitHub project");
+                
+                // Create minimal project from config if API fails
+                let project = GitHubProject {
+                    owner: repo.owner.clone(),
+                    repo: repo.repo.clone(),
+                    display_name: repo.display_name.clone(),
+                    feature: repo.feature,
+                    image: repo.image.clone(),
+                    readme_html: "README not available".to_string(),
+                    url: format!("https://github.com/{}/{}", repo.owner, repo.repo),
+                    stars: 0,
+                    forks: 0,
+                    description: Some("GitHub repository".to_string()),
+                };
+                projects.push(project);
+            }
+        }
+    }
+    
+    Ok(projects)
+}
+
+/// Fetch every GitHub repository the site shows that is missing from the cache or
+/// expired: configured projects and `github` shortcodes in content
+///
+/// Returns the number of cached repositories.
+pub async fn warm_github_cache(
+    app_config: &AppConfig,
+    github_cache: &std::sync::Mutex<std::collections::HashMap<String, CachedGithubProject>>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    load_github_projects(app_config, github_cache, false).await?;
+    
+    for category in &app_config.allowed_categories {
+        for file in get_content_files(category, &app_config.content_path)? {
+            let file_path = format!("{}/{}/{}", app_config.content_path, category, file);
+            if let Ok(markdown) = fs::read_to_string(&file_path) {
+                build_shortcode_context(&markdown, github_cache, app_config, true).await;
+            }
+        }
+    }
+    
+    let cached = github_cache.lock().unwrap().len();
+    Ok(cached)
+}
+
+/// GET a GitHub API URL, authenticated when a GitHub token is configured
+fn github_request(client: &reqwest::Client, url: &str, app_config: &AppConfig) -> reqwest::RequestBuilder {
+    let request = client.get(url).header("User-Agent", "portfolio-backend");
+    match &app_config.github_token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+pub async fn fetch_github_project(repo: &GitHubRepo, app_config: &AppConfig) -> Result<GitHubProject, Box<dyn std::error::Error>> {
     let client = reqwest::Client::new();
     
     // Fetch repository info
-    let repo_url = format!("https://api.github.com/repos/{}/{}", repo.owner, repo.repo);
-    let repo_response = client
-        .get(&repo_url)
-        .header("User-Agent", "portfolio-backend")
+    let repo_url = format!("{}/repos/{}/{}", app_config.github_api_url, repo.owner, repo.repo);
+    let repo_response = github_request(&client, &repo_url, app_config)
         .send()
-        .await?;
+        .await;
+    METRICS.record_github_call("repo", &repo_response);
+    let repo_response = repo_response?;
     
     if !repo_response.status().is_success() {
-        return Err(format!("GitHub API error: {}", repo_response.status()).into());
+        return Err(AppError::GitHub(format!("status {}", repo_response.status())).into());
     }
     
     let repo_data: serde_json::Value = repo_response.json().await?;
     
     // Fetch README
-    let readme_url = format!("https://api.github.com/repos/{}/{}/readme", repo.owner, repo.repo);
-    let readme_response = client
-        .get(&readme_url)
-        .header("User-Agent", "portfolio-backend")
+    let readme_url = format!("{}/repos/{}/{}/readme", app_config.github_api_url, repo.owner, repo.repo);
+    let readme_response = github_request(&client, &readme_url, app_config)
         .send()
-        .await?;
+        .await;
+    METRICS.record_github_call("readme", &readme_response);
+    let readme_response = readme_response?;
     
     let readme_html = if readme_response.status().is_success() {
         let readme_data: serde_json::Value = readme_response.json().await?;
@@ -244,13 +646,7 @@ pub async fn fetch_github_project(repo: &GitHubRepo) -> Result<GitHubProject, Bo
             let processed_markdown = process_github_images(&markdown_content, &repo.owner, &repo.repo);
             
             // Convert markdown to HTML
-            let mut options = Options::empty();
-            options.insert(Options::ENABLE_STRIKETHROUGH);
-            options.insert(Options::ENABLE_TABLES);
-            options.insert(Options::ENABLE_FOOTNOTES);
-            options.insert(Options::ENABLE_TASKLISTS);
-            
-            let parser = Parser::new_ext(&processed_markdown, options);
+            let parser = Parser::new_ext(&processed_markdown, markdown_options());
             let mut html_content = String::new();
             
             // Process events to add language classes for syntax highlighting
@@ -358,40 +754,62 @@ fn convert_github_image_url(src: &str, owner: &str, repo: &str) -> String {
 }
 
 // Authentication utilities
-use jsonwebtoken::{encode, decode, Header, Algorithm, Validation, EncodingKey, DecodingKey};
+use jsonwebtoken::{encode, decode, decode_header, Header, Algorithm, Validation, EncodingKey, DecodingKey};
 use chrono::{Utc, Duration};
-use actix_web::{HttpRequest, HttpResponse, Result, dev::ServiceRequest, dev::ServiceResponse, Error};
-use actix_web::dev::{forward_ready, Service, Transform};
-use futures_util::future::LocalBoxFuture;
-use std::future::{ready, Ready};
-use std::rc::Rc;
+use actix_web::{HttpRequest, HttpResponse, Result};
 
-/// JWT Secret key - in production this should be from environment variable
-const JWT_SECRET: &[u8] = b"your_super_secret_jwt_key_change_this_in_production";
+/// Default access token lifetime (15 minutes); sessions are extended with refresh tokens
+pub const ACCESS_TOKEN_EXPIRATION_MINUTES: i64 = 15;
 
-/// Token expiration time (24 hours)
-const TOKEN_EXPIRATION_HOURS: i64 = 24;
+/// Create a JWT token for an authenticated user, signed with the active (first) key
+pub fn create_jwt_token(keys: &[JwtKey], username: &str, role: &str) -> Result<(String, chrono::DateTime<Utc>), Box<dyn std::error::Error>> {
+    let (token, expires_at, _) = create_session_token(keys, username, role, ACCESS_TOKEN_EXPIRATION_MINUTES)?;
+    Ok((token, expires_at))
+}
 
-/// Create a JWT token for an authenticated user
-pub fn create_jwt_token(username: &str, role: &str) -> Result<(String, chrono::DateTime<Utc>), Box<dyn std::error::Error>> {
+/// Create a JWT token valid for `expiration_minutes`, along with the CSRF token
+/// embedded in its claims
+pub fn create_session_token(
+    keys: &[JwtKey],
+    username: &str,
+    role: &str,
+    expiration_minutes: i64,
+) -> Result<(String, chrono::DateTime<Utc>, String), Box<dyn std::error::Error>> {
+    let signing_key = keys.first().ok_or("No JWT signing key configured")?;
     let now = Utc::now();
-    let expires_at = now + Duration::hours(TOKEN_EXPIRATION_HOURS);
+    let expires_at = now + Duration::minutes(expiration_minutes);
     
     let claims = Claims {
         sub: username.to_owned(),
         exp: expires_at.timestamp(),
         iat: now.timestamp(),
         role: role.to_owned(),
+        jti: uuid::Uuid::new_v4().to_string(),
+        iat_micros: now.timestamp_micros(),
+        scopes: Vec::new(),
+        csrf: uuid::Uuid::new_v4().simple().to_string(),
     };
     
-    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET))?;
-    Ok((token, expires_at))
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(signing_key.kid.clone());
+    
+    let token = encode(&header, &claims, &EncodingKey::from_secret(signing_key.secret.as_bytes()))?;
+    Ok((token, expires_at, claims.csrf))
 }
 
-/// Verify and decode a JWT token
-pub fn verify_jwt_token(token: &str) -> Result<Claims, Box<dyn std::error::Error>> {
+/// Verify and decode a JWT token with the key named by its `kid` header,
+/// rejecting tokens that have been revoked
+pub fn verify_jwt_token(keys: &[JwtKey], revocations: &TokenRevocations, token: &str) -> Result<Claims, Box<dyn std::error::Error>> {
+    let header = decode_header(token)?;
+    
+    // Tokens without a kid can only have been signed by the active key
+    let key = match &header.kid {
+        Some(kid) => keys.iter().find(|key| &key.kid == kid).ok_or("Unknown signing key")?,
+        None => keys.first().ok_or("No JWT signing key configured")?,
+    };
+    
     let validation = Validation::new(Algorithm::HS256);
-    let token_data = decode::<Claims>(token, &DecodingKey::from_secret(JWT_SECRET), &validation)?;
+    let token_data = decode::<Claims>(token, &DecodingKey::from_secret(key.secret.as_bytes()), &validation)?;
     
     // Check if token is expired
     let now = Utc::now().timestamp();
@@ -399,75 +817,187 @@ pub fn verify_jwt_token(token: &str) -> Result<Claims, Box<dyn std::error::Error
         return Err("Token expired".into());
     }
     
+    if revocations.is_revoked(&token_data.claims) {
+        return Err("Token revoked".into());
+    }
+    
     Ok(token_data.claims)
 }
 
-/// Hash a password using simple hash (for demo - use proper bcrypt in production)
-pub fn hash_password(password: &str) -> String {
-    // Simple hash for demo - in production use proper bcrypt
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    let mut hasher = DefaultHasher::new();
-    password.hash(&mut hasher);
-    format!("hash_{}", hasher.finish())
+/// Hash a password with Argon2id, returning a PHC string with a random salt
+pub fn hash_password(password: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use argon2::{Argon2, PasswordHasher};
+    use password_hash::{rand_core::OsRng, SaltString};
+    
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| format!("Password hashing failed: {}", e))?;
+    Ok(hash.to_string())
 }
 
-/// Verify a password against its hash
+/// Verify a password against an Argon2 PHC string
 pub fn verify_password(password: &str, hash: &str) -> bool {
-    hash_password(password) == hash
+    use argon2::{Argon2, PasswordHash, PasswordVerifier};
+    
+    match PasswordHash::new(hash) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Users file format: a YAML list of users with PHC password hashes
+#[derive(Debug, serde::Deserialize)]
+struct UsersFile {
+    users: Vec<AuthUser>,
 }
 
-/// Get admin user credentials from environment variables (with fallback for development)
-pub fn get_admin_user() -> AuthUser {
-    let username = std::env::var("ADMIN_USERNAME").unwrap_or_else(|_| "admin".to_string());
-    let password = std::env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "admin123".to_string());
+/// Load a users file (YAML) with Argon2 PHC password hashes
+pub fn load_users_file(path: &str) -> Result<Vec<AuthUser>, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+    let users_file: UsersFile = serde_yaml::from_str(&contents)?;
+    
+    for user in &users_file.users {
+        argon2::PasswordHash::new(&user.password_hash)
+            .map_err(|e| format!("Invalid password hash for user '{}': {}", user.username, e))?;
+        if crate::permissions::Role::parse(&user.role).is_none() {
+            return Err(format!("Invalid role '{}' for user '{}', expected admin, editor or viewer", user.role, user.username).into());
+        }
+    }
     
-    AuthUser {
-        username,
-        password_hash: hash_password(&password),
-        role: "admin".to_string(),
+    Ok(users_file.users)
+}
+
+/// Configured users, loaded once at startup
+///
+/// Users come from the YAML file in `USERS_FILE` if set, otherwise from
+/// `ADMIN_USERNAME` and the PHC string in `ADMIN_PASSWORD_HASH`. The plaintext
+/// `ADMIN_PASSWORD` (or the "admin123" default) is only a development fallback;
+/// `AppConfig::validate` refuses to start in production without a hash.
+pub struct UserStore {
+    users: Vec<AuthUser>,
+}
+
+/// Hash verified when the username is unknown, so both failure paths cost one Argon2 run
+///
+/// Hashing a random password cannot fail in practice; if it did, falling back to
+/// an empty hash would make unknown usernames measurably faster, so it panics.
+static DUMMY_PASSWORD_HASH: std::sync::LazyLock<String> = std::sync::LazyLock::new(|| {
+    hash_password(&uuid::Uuid::new_v4().to_string()).expect("Failed to hash the dummy password")
+});
+
+impl UserStore {
+    pub fn new(users: Vec<AuthUser>) -> Self {
+        UserStore { users }
+    }
+
+    /// Load the users named by the configuration
+    pub fn from_config(config: &AppConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(users_file) = &config.users_file {
+            return Ok(Self::new(load_users_file(users_file)?));
+        }
+        
+        let password_hash = match &config.admin_password_hash {
+            Some(hash) => hash.clone(),
+            None => {
+                warn!("ADMIN_PASSWORD_HASH is not set, falling back to plaintext ADMIN_PASSWORD. Generate a hash with `portfolio-backend hash-password`.");
+                hash_password(config.admin_password.as_deref().unwrap_or("admin123"))?
+            }
+        };
+        
+        Ok(Self::new(vec![AuthUser {
+            username: config.admin_username.clone(),
+            password_hash,
+            role: "admin".to_string(),
+        }]))
+    }
+
+    /// Find a user by username
+    pub fn find(&self, username: &str) -> Option<AuthUser> {
+        self.users.iter().find(|user| user.username == username).cloned()
+    }
+
+    /// Check a username and password in constant time with respect to which one is wrong
+    pub fn authenticate(&self, username: &str, password: &str) -> Option<AuthUser> {
+        let user = self.find(username);
+        let hash = match &user {
+            Some(user) => user.password_hash.clone(),
+            None => DUMMY_PASSWORD_HASH.clone(),
+        };
+        
+        let password_valid = verify_password(password, &hash);
+        user.filter(|_| password_valid)
     }
 }
 
+/// Client IP for throttling and logging, from X-Real-IP when behind a trusted proxy
+pub fn client_ip(req: &HttpRequest, trust_proxy: bool) -> String {
+    if trust_proxy {
+        if let Some(real_ip) = req.headers().get("x-real-ip").and_then(|value| value.to_str().ok()) {
+            return real_ip.trim().to_string();
+        }
+    }
+    
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 /// Extract and verify JWT token from request
-pub fn extract_token_from_request(req: &HttpRequest) -> Result<Claims, String> {
+///
+/// Fails with `AppError::Csrf` when a cookie session misses its CSRF header on an
+/// unsafe method, and with `AppError::Unauthorized` for any other missing or
+/// invalid credential.
+pub fn extract_token_from_request(req: &HttpRequest) -> Result<Claims, AppError> {
+    let unauthorized = || AppError::Unauthorized("Authentication required".to_string());
     let auth_header = req.headers().get("authorization");
     
     if let Some(auth_value) = auth_header {
         if let Ok(auth_str) = auth_value.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let token = &auth_str[7..]; // Remove "Bearer " prefix
-                
-                match verify_jwt_token(token) {
-                    Ok(claims) => return Ok(claims),
-                    Err(e) => return Err(format!("Invalid token: {}", e)),
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                if token.starts_with(API_TOKEN_PREFIX) {
+                    let api_tokens = req.app_data::<actix_web::web::Data<ApiTokenStore>>()
+                        .ok_or_else(unauthorized)?;
+                    return api_tokens.verify(token).map_err(|_| unauthorized());
                 }
+                
+                let app_config = req.app_data::<actix_web::web::Data<AppConfig>>()
+                    .ok_or_else(unauthorized)?;
+                let revocations = req.app_data::<actix_web::web::Data<TokenRevocations>>()
+                    .ok_or_else(unauthorized)?;
+                
+                return verify_jwt_token(&app_config.jwt_keys, revocations, token).map_err(|_| unauthorized());
             }
         }
     }
     
-    Err("Missing or invalid authorization header".to_string())
-}
-
-/// Helper function to check admin authentication in handlers
-pub fn check_admin_auth(req: &HttpRequest) -> Result<Claims, HttpResponse> {
-    match extract_token_from_request(req) {
-        Ok(claims) => {
-            if claims.role == "admin" {
-                Ok(claims)
-            } else {
-                Err(HttpResponse::Forbidden().json(
-                    ApiResponse::<()>::error("Insufficient permissions")
-                ))
+    // Fall back to the session cookie, which the browser attaches on its own and
+    // therefore needs a CSRF check on anything that changes state
+    if let Some(cookie) = req.cookie(ACCESS_COOKIE_NAME) {
+        let app_config = req.app_data::<actix_web::web::Data<AppConfig>>()
+            .ok_or_else(unauthorized)?;
+        let revocations = req.app_data::<actix_web::web::Data<TokenRevocations>>()
+            .ok_or_else(unauthorized)?;
+        
+        let claims = verify_jwt_token(&app_config.jwt_keys, revocations, cookie.value())
+            .map_err(|_| unauthorized())?;
+        
+        if !req.method().is_safe() {
+            let presented = req.headers()
+                .get(CSRF_HEADER_NAME)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("");
+            if claims.csrf.is_empty() || !constant_time_eq(presented.as_bytes(), claims.csrf.as_bytes()) {
+                return Err(AppError::Csrf);
             }
         }
-        Err(_) => {
-            Err(HttpResponse::Unauthorized().json(
-                ApiResponse::<()>::error("Authentication required")
-            ))
-        }
+        
+        return Ok(claims);
     }
+    
+    Err(unauthorized())
 }
 
 // File Management utilities
@@ -482,6 +1012,12 @@ pub fn create_safe_file_path(base_path: &str, relative_path: &str) -> Result<Str
     // Remove leading slash if present
     let clean_path = relative_path.trim_start_matches('/');
     
+    // Keep in-progress writes out of reach of the file manager,
+    // however the path is spelled
+    if normalize_relative_path(clean_path).split('/').any(is_reserved_name) {
+        return Err(ValidationError::PathTraversal("Path is reserved".to_string()));
+    }
+    
     let full_path = if clean_path.is_empty() {
         base_path.to_string()
     } else {
@@ -501,6 +1037,22 @@ pub fn create_safe_file_path(base_path: &str, relative_path: &str) -> Result<Str
     Ok(full_path)
 }
 
+/// Compute the ETag of file content (quoted SHA-256 hex digest)
+pub fn content_etag(content: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("\"{:x}\"", Sha256::digest(content))
+}
+
+/// Check an If-Match header value against the current ETag
+///
+/// If-Match uses the strong comparison (RFC 9110, section 13.1.1), so weak
+/// `W/` tags never match.
+pub fn if_match_satisfied(if_match: &str, current_etag: &str) -> bool {
+    if_match.split(',').map(|tag| tag.trim()).any(|tag| {
+        tag == "*" || (!tag.starts_with("W/") && tag == current_etag)
+    })
+}
+
 /// List directory contents
 pub fn list_directory_contents(dir_path: &str) -> Result<DirectoryContents, Box<dyn std::error::Error>> {
     use std::fs;
@@ -508,11 +1060,11 @@ pub fn list_directory_contents(dir_path: &str) -> Result<DirectoryContents, Box<
     
     let path = Path::new(dir_path);
     if !path.exists() {
-        return Err("Directory does not exist".into());
+        return Err(AppError::NotFound("Directory does not exist".to_string()).into());
     }
     
     if !path.is_dir() {
-        return Err("Path is not a directory".into());
+        return Err(AppError::BadRequest("Path is not a directory".to_string()).into());
     }
     
     let mut items = Vec::new();
@@ -521,6 +1073,9 @@ pub fn list_directory_contents(dir_path: &str) -> Result<DirectoryContents, Box<
     for entry in entries {
         let entry = entry?;
         let file_name = entry.file_name().to_string_lossy().to_string();
+        if is_temp_file_name(&file_name) {
+            continue;
+        }
         let file_path = entry.path();
         let metadata = entry.metadata()?;
         
@@ -568,47 +1123,195 @@ pub fn list_directory_contents(dir_path: &str) -> Result<DirectoryContents, Box<
     })
 }
 
-/// Handle file upload from multipart
-pub async fn handle_file_upload(
+/// Prefix of temporary files written before an atomic rename; reserved, so the
+/// file manager can never create a name that startup cleanup would delete
+pub const TEMP_FILE_PREFIX: &str = ".portfolio-write-";
+
+/// Suffix of temporary files written before an atomic rename
+const TEMP_FILE_SUFFIX: &str = ".tmp";
+
+/// Whether a file name belongs to an in-progress (or abandoned) atomic write
+pub fn is_temp_file_name(name: &str) -> bool {
+    name.starts_with(TEMP_FILE_PREFIX) && name.ends_with(TEMP_FILE_SUFFIX)
+}
+
+/// Whether a path segment is reserved for the server's own files
+pub fn is_reserved_name(name: &str) -> bool {
+    name.starts_with(TEMP_FILE_PREFIX)
+}
+
+/// A unique temp sibling of `destination`, for writes renamed into place once complete
+pub(crate) fn temp_path_for(destination: &std::path::Path) -> std::path::PathBuf {
+    let uuid = uuid::Uuid::new_v4().simple().to_string();
+    destination.with_file_name(format!("{}{}{}", TEMP_FILE_PREFIX, uuid, TEMP_FILE_SUFFIX))
+}
+
+/// Flush a directory entry so a rename into it survives a crash
+fn sync_parent_dir(path: &std::path::Path) -> Result<(), io::Error> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() { std::path::Path::new(".") } else { parent };
+        fs::File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Temporary file that is removed on drop unless it has been committed
+struct PendingFile {
+    temp_path: std::path::PathBuf,
+    destination: std::path::PathBuf,
+    committed: bool,
+}
+
+impl PendingFile {
+    fn new(destination: &std::path::Path) -> Self {
+        PendingFile {
+            temp_path: temp_path_for(destination),
+            destination: destination.to_path_buf(),
+            committed: false,
+        }
+    }
+
+    /// Atomically move the fully written temp file into place
+    fn commit(mut self) -> Result<(), io::Error> {
+        fs::rename(&self.temp_path, &self.destination)?;
+        self.committed = true;
+        sync_parent_dir(&self.destination)
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Write a file atomically: write to a temp file, fsync it, then rename over the destination
+pub fn write_file_atomic(path: &str, contents: &[u8]) -> Result<(), io::Error> {
+    use std::io::Write;
+    
+    let pending = PendingFile::new(std::path::Path::new(path));
+    let mut file = fs::File::create(&pending.temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    
+    pending.commit()
+}
+
+/// Remove temp files and folders left behind by writes interrupted by a crash
+pub fn cleanup_stale_temp_files(dir_path: &str) -> Result<usize, io::Error> {
+    let mut removed = 0;
+    let dir = std::path::Path::new(dir_path);
+    if !dir.is_dir() {
+        return Ok(0);
+    }
+    
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() && is_temp_file_name(&name) {
+            fs::remove_dir_all(&path)?;
+            removed += 1;
+        } else if path.is_dir() {
+            removed += cleanup_stale_temp_files(&path.to_string_lossy())?;
+        } else if is_temp_file_name(&name) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    
+    Ok(removed)
+}
+
+/// A malformed multipart body is the client's error, answered without the parser's detail
+fn invalid_upload(error: actix_multipart::MultipartError) -> AppError {
+    tracing::warn!(error = %error, "Invalid multipart upload");
+    AppError::BadRequest("Invalid multipart upload".to_string())
+}
+
+/// Uploaded files streamed into temp files next to their destinations, not yet in place
+///
+/// Dropping it without `commit` removes the temp files.
+pub struct StagedUpload {
+    files: Vec<(String, PendingFile)>,
+}
+
+impl StagedUpload {
+    /// Names of the uploaded files, in upload order
+    pub fn filenames(&self) -> Vec<String> {
+        self.files.iter().map(|(filename, _)| filename.clone()).collect()
+    }
+
+    /// Names of the uploaded files that would replace an existing file
+    pub fn overwritten(&self) -> Vec<String> {
+        self.files.iter()
+            .filter(|(_, pending)| pending.destination.exists())
+            .map(|(filename, _)| filename.clone())
+            .collect()
+    }
+
+    /// Move every file into place, returning their names
+    pub fn commit(self) -> Result<Vec<String>, io::Error> {
+        let mut filenames = Vec::new();
+        for (filename, pending) in self.files {
+            pending.commit()?;
+            filenames.push(filename);
+        }
+        Ok(filenames)
+    }
+}
+
+/// Stream a multipart upload into temp files inside `destination_dir`
+///
+/// Nothing is renamed into place until `StagedUpload::commit`, so an aborted
+/// upload leaves nothing behind.
+pub async fn stage_file_upload(
     payload: &mut actix_multipart::Multipart,
     destination_dir: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
+) -> Result<StagedUpload, Box<dyn std::error::Error>> {
     use futures_util::TryStreamExt;
     use std::io::Write;
     
-    let mut uploaded_files = Vec::new();
+    let mut files = Vec::new();
+    
+    // Create destination directory if it doesn't exist
+    std::fs::create_dir_all(destination_dir)?;
     
-    while let Some(mut field) = payload.try_next().await? {
+    while let Some(mut field) = payload.try_next().await.map_err(invalid_upload)? {
         let content_disposition = field.content_disposition();
         
         if let Some(filename) = content_disposition.get_filename() {
-            let filename_owned = filename.to_string();
-            let filepath = std::path::Path::new(destination_dir).join(&filename_owned);
-            
-            // Create destination directory if it doesn't exist
-            if let Some(parent) = filepath.parent() {
-                std::fs::create_dir_all(parent)?;
+            // Only keep the final path component of client supplied names
+            let filename_owned = match std::path::Path::new(filename).file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => return Err(AppError::BadRequest(format!("Invalid file name '{}'", filename)).into()),
+            };
+            if is_reserved_name(&filename_owned) {
+                return Err(AppError::BadRequest(format!("Invalid file name '{}'", filename_owned)).into());
             }
+            let filepath = std::path::Path::new(destination_dir).join(&filename_owned);
             
-            // Create file and write chunks
-            let mut file = std::fs::File::create(&filepath)?;
-            while let Some(chunk) = field.try_next().await? {
+            // Stream chunks into a temp file next to the destination
+            let pending = PendingFile::new(&filepath);
+            let mut file = std::fs::File::create(&pending.temp_path)?;
+            files.push((filename_owned, pending));
+            while let Some(chunk) = field.try_next().await.map_err(invalid_upload)? {
                 file.write_all(&chunk)?;
             }
-            
-            uploaded_files.push(filename_owned);
+            file.sync_all()?;
         }
     }
     
-    if uploaded_files.is_empty() {
-        return Err("No files found in upload".into());
+    if files.is_empty() {
+        return Err(AppError::BadRequest("No files found in upload".to_string()).into());
     }
     
-    if uploaded_files.len() == 1 {
-        Ok(uploaded_files[0].clone())
-    } else {
-        Ok(format!("{} files", uploaded_files.len()))
-    }
+    Ok(StagedUpload { files })
 }
 
 /// Delete file or folder
@@ -616,7 +1319,7 @@ pub fn delete_file_or_folder(path: &str) -> Result<(), Box<dyn std::error::Error
     let path = std::path::Path::new(path);
     
     if !path.exists() {
-        return Err("File or folder does not exist".into());
+        return Err(AppError::NotFound("File or folder does not exist".to_string()).into());
     }
     
     if path.is_dir() {
@@ -634,11 +1337,11 @@ pub fn rename_file_or_folder(old_path: &str, new_path: &str) -> Result<(), Box<d
     let new = std::path::Path::new(new_path);
     
     if !old.exists() {
-        return Err("Source file or folder does not exist".into());
+        return Err(AppError::NotFound("Source file or folder does not exist".to_string()).into());
     }
     
     if new.exists() {
-        return Err("Destination already exists".into());
+        return Err(AppError::Conflict("Destination already exists".to_string()).into());
     }
     
     std::fs::rename(old, new)?;
@@ -651,7 +1354,7 @@ pub fn move_file_or_folder(source_path: &str, destination_path: &str) -> Result<
     let destination = std::path::Path::new(destination_path);
     
     if !source.exists() {
-        return Err("Source file or folder does not exist".into());
+        return Err(AppError::NotFound("Source file or folder does not exist".to_string()).into());
     }
     
     // Create destination directory if it doesn't exist
@@ -660,7 +1363,7 @@ pub fn move_file_or_folder(source_path: &str, destination_path: &str) -> Result<
     }
     
     if destination.exists() {
-        return Err("Destination already exists".into());
+        return Err(AppError::Conflict("Destination already exists".to_string()).into());
     }
     
     std::fs::rename(source, destination)?;
@@ -681,7 +1384,7 @@ pub fn serve_file_download(file_path: &str) -> Result<HttpResponse, Box<dyn std:
     let path = std::path::Path::new(file_path);
     
     if !path.exists() || !path.is_file() {
-        return Err("File does not exist".into());
+        return Err(AppError::NotFound("File does not exist".to_string()).into());
     }
     
     let mut file = File::open(path)?;
@@ -696,4 +1399,182 @@ pub fn serve_file_download(file_path: &str) -> Result<HttpResponse, Box<dyn std:
         .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
         .append_header(("Content-Type", "application/octet-stream"))
         .body(contents))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn cached_project(repo: &str, stars: u32, cached_at: chrono::DateTime<Utc>) -> CachedGithubProject {
+        CachedGithubProject {
+            project: GitHubProject {
+                owner: "octo".to_string(),
+                repo: repo.to_string(),
+                display_name: repo.to_string(),
+                readme_html: String::new(),
+                url: format!("https://github.com/octo/{}", repo),
+                stars,
+                forks: 0,
+                description: None,
+                feature: None,
+                image: None,
+            },
+            cached_at,
+        }
+    }
+
+    #[test]
+    fn saving_the_github_cache_keeps_newer_entries_from_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let _dir = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path().join(GITHUB_CACHE_FILE).to_string_lossy().to_string();
+        let now = Utc::now();
+        let stale = std::sync::Mutex::new(std::collections::HashMap::from([
+            ("octo/widget".to_string(), cached_project("widget", 1, now - chrono::Duration::hours(1))),
+            ("octo/gadget".to_string(), cached_project("gadget", 1, now)),
+        ]));
+
+        // A CLI refresh writes a newer widget while the server holds the stale one
+        let refreshed = std::sync::Mutex::new(std::collections::HashMap::from([
+            ("octo/widget".to_string(), cached_project("widget", 5, now)),
+            ("octo/gadget".to_string(), cached_project("gadget", 5, now - chrono::Duration::hours(1))),
+        ]));
+        save_github_cache(&path, &refreshed).unwrap();
+        save_github_cache(&path, &stale).unwrap();
+
+        let saved = load_github_cache(&path).unwrap();
+        assert_eq!(saved["octo/widget"].project.stars, 5);
+        assert_eq!(saved["octo/gadget"].project.stars, 1);
+        assert_eq!(stale.lock().unwrap()["octo/widget"].project.stars, 5);
+    }
+
+    #[test]
+    fn atomic_writes_leave_no_temp_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let file_path = format!("{}/post.md", dir);
+        fs::write(&file_path, "old").unwrap();
+
+        write_file_atomic(&file_path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "new");
+        let names: Vec<String> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["post.md".to_string()]);
+    }
+
+    #[test]
+    fn interrupted_writes_are_swept_and_never_listed() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        fs::create_dir_all(format!("{}/blog", dir)).unwrap();
+        assert!(create_safe_file_path(dir, "blog/.portfolio-write-1a2b.tmp").is_err());
+        assert!(create_safe_file_path(dir, "blog/.draft.tmp").is_ok());
+
+        let stale = temp_path_for(Path::new(&format!("{}/blog/post.md", dir)));
+        fs::write(&stale, "partial").unwrap();
+        // Dot files that merely look like temp files are content, not leftovers
+        fs::write(format!("{}/blog/.draft.tmp", dir), "keep").unwrap();
+
+        let listing = list_directory_contents(&format!("{}/blog", dir)).unwrap();
+        assert_eq!(listing.items.len(), 1);
+        assert_eq!(cleanup_stale_temp_files(dir).unwrap(), 1);
+        assert!(!stale.exists());
+        assert!(Path::new(&format!("{}/blog/.draft.tmp", dir)).exists());
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        let etag = content_etag(b"original");
+        assert!(if_match_satisfied(&etag, &etag));
+        assert!(if_match_satisfied(&format!("\"other\", {}", etag), &etag));
+        assert!(if_match_satisfied("*", &etag));
+        assert!(!if_match_satisfied(&format!("W/{}", etag), &etag));
+        assert!(!if_match_satisfied(&content_etag(b"changed"), &etag));
+    }
+
+    #[test]
+    fn passwords_hash_with_salted_argon2id() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("correct horse").unwrap());
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(!verify_password("correct horse", "not-a-phc-string"));
+    }
+
+    #[test]
+    fn users_files_must_store_hashes() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().to_str().unwrap();
+        let users_path = format!("{}/users.yaml", dir);
+        let hash = hash_password("correct horse").unwrap();
+        fs::write(&users_path, format!("users:\n  - username: editor\n    password_hash: \"{}\"\n    role: admin\n", hash)).unwrap();
+        let users = UserStore::new(load_users_file(&users_path).unwrap());
+        assert!(users.authenticate("editor", "correct horse").is_some());
+        assert!(users.authenticate("editor", "wrong").is_none());
+        assert!(users.authenticate("nobody", "correct horse").is_none());
+
+        fs::write(&users_path, "users:\n  - username: editor\n    password_hash: plaintext\n    role: admin\n").unwrap();
+        assert!(load_users_file(&users_path).is_err());
+    }
+
+    #[test]
+    fn rotated_keys_keep_old_tokens_valid_until_retired() {
+        let old_key = JwtKey { kid: "2024".to_string(), secret: "old-secret".to_string() };
+        let new_key = JwtKey { kid: "2025".to_string(), secret: "new-secret".to_string() };
+        let (old_token, _) = create_jwt_token(std::slice::from_ref(&old_key), "admin", "admin").unwrap();
+
+        // After rotation new tokens use the new kid while old tokens still verify
+        let rotated = vec![new_key.clone(), old_key];
+        let (new_token, _) = create_jwt_token(&rotated, "admin", "admin").unwrap();
+        assert_eq!(decode_header(&new_token).unwrap().kid.as_deref(), Some("2025"));
+        assert_eq!(verify_jwt_token(&rotated, &TokenRevocations::in_memory(), &old_token).unwrap().sub, "admin");
+        assert_eq!(verify_jwt_token(&rotated, &TokenRevocations::in_memory(), &new_token).unwrap().sub, "admin");
+
+        // Retiring the old key logs out its tokens
+        assert!(verify_jwt_token(&[new_key], &TokenRevocations::in_memory(), &old_token).is_err());
+    }
+
+    fn lint_config(content_path: &str) -> AppConfig {
+        let mut config = AppConfig::from_sources(&crate::config::ConfigFile::default(), &|_| None).unwrap();
+        config.content_path = content_path.to_string();
+        fs::create_dir_all(format!("{}/github", content_path)).unwrap();
+        fs::write(format!("{}/github/config.yaml", content_path), "repositories: []\n").unwrap();
+        config
+    }
+
+    #[test]
+    fn scaffolded_content_passes_lint_and_is_never_overwritten() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let config = lint_config(content_path);
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+
+        let path = scaffold_content(content_path, "blog", "Hello: Rust & YAML!", date).unwrap();
+        assert!(path.ends_with("/blog/hello-rust-yaml.md"));
+        assert!(scaffold_content(content_path, "blog", "Hello: Rust & YAML!", date).is_err());
+        let item = parse_markdown_file(&path, "blog").unwrap();
+        assert_eq!(item.metadata.title, "Hello: Rust & YAML!");
+        assert_eq!(item.metadata.date, "2025-03-01");
+        assert!(lint_content(&config).is_empty());
+    }
+
+    #[test]
+    fn lint_reports_broken_frontmatter_and_dates_per_file() {
+        let content_dir = TempDir::new().unwrap();
+        let content_path = content_dir.path().to_str().unwrap();
+        let config = lint_config(content_path);
+        fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+        fs::write(format!("{}/blog/broken.md", content_path), "---\ntitle: [unclosed\n---\nbody").unwrap();
+        fs::write(
+            format!("{}/blog/bad-date.md", content_path),
+            "---\ntitle: \"Bad\"\ndate: \"March\"\ntags: []\ndescription: \"\"\n---\nbody",
+        ).unwrap();
+
+        let issues = lint_content(&config);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().any(|issue| issue.path == "blog/broken.md" && issue.message.contains("frontmatter")));
+        assert!(issues.iter().any(|issue| issue.path == "blog/bad-date.md" && issue.message.contains("YYYY-MM-DD")));
+    }
+}
@@ -3,6 +3,8 @@ use portfolio_backend::*;
 use std::collections::HashMap;
 use std::sync::Mutex;
 
+use tempfile::TempDir;
+
 fn create_test_config() -> AppConfig {
     AppConfig {
         host: "127.0.0.1".to_string(),
@@ -10,9 +12,46 @@ fn create_test_config() -> AppConfig {
         content_path: "../content".to_string(),
         frontend_path: "../frontend".to_string(),
         frontend_url: "http://localhost:3000".to_string(),
+        cors_allowed_origins: vec!["http://localhost:3000".to_string()],
+        cors_max_age: 3600,
+        trash_path: "../trash".to_string(),
+        trash_retention_days: 30,
+        data_path: "../data".to_string(),
+        production: false,
+        trust_proxy: false,
+        cookie_sessions: false,
+        hsts_max_age: 0,
+        csp_report_only: false,
+        csp_connect_sources: Vec::new(),
+        metrics_token: None,
+        log_format: LogFormat::Text,
+        jwt_keys: vec![JwtKey { kid: "test".to_string(), secret: "test-secret".to_string() }],
+        two_factor_key: "test-two-factor-key".to_string(),
+        access_token_minutes: 15,
+        refresh_token_days: 7,
+        allowed_categories: vec!["project".to_string(), "blog".to_string(), "page".to_string()],
+        content_cache_ttl_seconds: 3600,
+        github_cache_ttl_seconds: 86400,
+        github_api_url: "https://api.github.com".to_string(),
+        github_token: None,
+        users_file: None,
+        admin_username: "admin".to_string(),
+        admin_password_hash: None,
+        admin_password: None,
     }
 }
 
+/// Hash of the "admin123" test password, computed once since Argon2 is slow in debug builds
+static ADMIN_PASSWORD_HASH: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+fn test_users() -> UserStore {
+    UserStore::new(vec![AuthUser {
+        username: "admin".to_string(),
+        password_hash: ADMIN_PASSWORD_HASH.get_or_init(|| hash_password("admin123").unwrap()).clone(),
+        role: "admin".to_string(),
+    }])
+}
+
 #[actix_web::test]
 async fn test_health_endpoint_works() {
     let github_cache = web::Data::new(Mutex::new(HashMap::<String, portfolio_backend::CachedGithubProject>::new()));
@@ -179,11 +218,14 @@ async fn test_admin_refresh_github_works() {
             .app_data(github_cache)
             .app_data(content_cache)
             .app_data(app_config)
+            .app_data(web::Data::new(TokenRevocations::in_memory()))
             .route("/api/admin/refresh-github", web::post().to(refresh_github_cache))
     ).await;
     
+    let (token, _) = create_jwt_token(&create_test_config().jwt_keys, "admin", "admin").unwrap();
     let req = test::TestRequest::post()
         .uri("/api/admin/refresh-github")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
         .to_request();
         
     let resp = test::call_service(&app, req).await;
@@ -490,4 +532,1549 @@ async fn test_valid_inputs_work() {
     // Should either succeed or return 404 (if file doesn't exist), but not 400
     assert!(resp.status() == StatusCode::OK || resp.status() == StatusCode::NOT_FOUND);
     assert_ne!(resp.status(), StatusCode::BAD_REQUEST);
-}
\ No newline at end of file
+}
+
+/// Stores of a test app, in memory and shared with the test to seed or inspect
+/// them; replace one before `configure` to test against another
+struct TestApp {
+    config: AppConfig,
+    /// Holds the history; removed with the app
+    _data_path: TempDir,
+    github_cache: web::Data<Mutex<HashMap<String, CachedGithubProject>>>,
+    content_cache: web::Data<Mutex<HashMap<String, CachedContent>>>,
+    cache_warmup: web::Data<CacheWarmup>,
+    revocations: web::Data<TokenRevocations>,
+    users: web::Data<UserStore>,
+    refresh_tokens: web::Data<RefreshTokenStore>,
+    login_throttle: web::Data<LoginThrottle>,
+    two_factor: web::Data<TwoFactorStore>,
+    api_tokens: web::Data<ApiTokenStore>,
+    audit_log: web::Data<AuditLog>,
+}
+
+impl TestApp {
+    /// A test app with `config` and a fresh data path
+    fn new(mut config: AppConfig) -> Self {
+        let data_dir = TempDir::new().unwrap();
+        let data_path = data_dir.path().to_str().unwrap();
+        config.data_path = data_path.to_string();
+        TestApp {
+            config,
+            _data_path: data_dir,
+            github_cache: web::Data::new(Mutex::new(HashMap::new())),
+            content_cache: web::Data::new(Mutex::new(HashMap::new())),
+            cache_warmup: web::Data::new(CacheWarmup::default()),
+            revocations: web::Data::new(TokenRevocations::in_memory()),
+            users: web::Data::new(test_users()),
+            refresh_tokens: web::Data::new(RefreshTokenStore::in_memory()),
+            login_throttle: web::Data::new(LoginThrottle::new()),
+            two_factor: web::Data::new(TwoFactorStore::in_memory()),
+            api_tokens: web::Data::new(ApiTokenStore::in_memory()),
+            audit_log: web::Data::new(AuditLog::in_memory()),
+        }
+    }
+
+    /// A test app with `content_path` as its content root
+    fn with_content(content_path: &str) -> Self {
+        let mut config = create_test_config();
+        config.content_path = content_path.to_string();
+        TestApp::new(config)
+    }
+
+    /// Register the stores and the API routes as the server does, without its middleware
+    fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::new(self.config.clone()))
+            .app_data(self.github_cache.clone())
+            .app_data(self.content_cache.clone())
+            .app_data(self.cache_warmup.clone())
+            .app_data(self.revocations.clone())
+            .app_data(self.users.clone())
+            .app_data(self.refresh_tokens.clone())
+            .app_data(self.login_throttle.clone())
+            .app_data(self.two_factor.clone())
+            .app_data(self.api_tokens.clone())
+            .app_data(self.audit_log.clone())
+            .route("/metrics", web::get().to(metrics_endpoint))
+            .route("/api/health/live", web::get().to(health_check))
+            .route("/api/health/ready", web::get().to(readiness_check))
+            .route("/api/content/{category}", web::get().to(get_content_list))
+            .route("/api/content/{category}/{slug}", web::get().to(get_content_item))
+            .service(
+                web::scope("/api/auth")
+                    .route("/login", web::post().to(login))
+                    .route("/refresh", web::post().to(refresh_session))
+                    .route("/2fa/verify", web::post().to(verify_two_factor_login))
+                    .route("/verify", web::get().to(verify_token))
+                    .route("/logout", web::post().to(logout))
+            )
+            .service(
+                web::scope("/api/admin")
+                    .wrap(RequireAuth)
+                    .route("/sessions/revoke-all", web::post().to(revoke_all_sessions))
+                    .route("/audit", web::get().to(list_audit_events))
+                    .route("/tokens", web::get().to(list_api_tokens))
+                    .route("/tokens", web::post().to(create_api_token))
+                    .route("/tokens/revoke", web::post().to(revoke_api_token))
+                    .route("/2fa/enroll", web::post().to(enroll_two_factor))
+                    .route("/2fa/confirm", web::post().to(confirm_two_factor))
+                    .route("/files/list/{path:.*}", web::get().to(list_files))
+                    .route("/files/upload/{path:.*}", web::post().to(upload_file))
+                    .route("/files/read/{path:.*}", web::get().to(read_file_content))
+                    .route("/files/save/{path:.*}", web::post().to(save_file_content))
+                    .route("/files/delete", web::post().to(delete_file))
+                    .route("/files/rename", web::post().to(rename_file))
+                    .route("/files/create-folder", web::post().to(create_folder))
+                    .route("/history/restore", web::post().to(restore_history))
+                    .route("/trash/purge", web::post().to(purge_trash))
+            );
+    }
+
+    /// A bearer `Authorization` header for a user named after `role`
+    fn auth(&self, role: &str) -> (&'static str, String) {
+        let (token, _) = create_jwt_token(&self.config.jwt_keys, role, role).unwrap();
+        ("Authorization", format!("Bearer {}", token))
+    }
+}
+
+#[actix_web::test]
+async fn test_create_folder_at_the_root_records_no_history() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    // Creating the root folder must not snapshot the content root into its own history
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/create-folder")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "path": "" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert!(list_revisions(&state.config.history_path(), None).unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_content_item_never_calls_github() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+    std::fs::write(
+        format!("{}/blog/post.md", content_path),
+        "---\ntitle: \"Post\"\ndate: \"2025-01-01\"\ntags: []\ndescription: \"\"\n---\n{{< github octo/widget >}}",
+    ).unwrap();
+    // A GitHub API that would see any request the handler makes
+    let github = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    github.set_nonblocking(true).unwrap();
+    let mut config = create_test_config();
+    config.content_path = content_path.to_string();
+    config.github_api_url = format!("http://{}", github.local_addr().unwrap());
+    let state = TestApp::new(config);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/content/blog/post").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/api/content/blog").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(github.accept().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+}
+
+/// A multipart body uploading `files` as (name, contents) under the `boundary` boundary
+fn multipart_body(files: &[(&str, &str)]) -> String {
+    let mut body = String::new();
+    for (name, contents) in files {
+        body.push_str(&format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n{}\r\n",
+            name, contents,
+        ));
+    }
+    body.push_str("--boundary--\r\n");
+    body
+}
+
+fn upload_request(state: &TestApp, folder: &str, files: &[(&str, &str)]) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/api/admin/files/upload/{}", folder))
+        .insert_header(state.auth("admin"))
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload(multipart_body(files))
+}
+
+#[actix_web::test]
+async fn test_upload_reports_every_file() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = upload_request(&state, "blog", &[("post.md", "uploaded"), ("new.md", "new")]).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"], "2 files uploaded successfully");
+}
+
+#[actix_web::test]
+async fn test_upload_overwrite_keeps_baseline() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+    std::fs::write(format!("{}/blog/post.md", content_path), "original").unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = upload_request(&state, "blog", &[("post.md", "uploaded"), ("new.md", "new")])
+        .insert_header(("If-Match", content_etag(b"original")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    // The overwritten file keeps its pre-upload version; the new file only its upload
+    let revisions = list_revisions(&state.config.history_path(), Some("blog/post.md")).unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].action, RevisionAction::Upload);
+    assert_eq!(revisions[1].action, RevisionAction::Baseline);
+    restore_revision(content_path, &state.config.history_path(), &revisions[1].id, "admin").unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{}/blog/post.md", content_path)).unwrap(), "original");
+    let revisions = list_revisions(&state.config.history_path(), Some("blog/new.md")).unwrap();
+    assert_eq!(revisions.len(), 1);
+}
+
+#[actix_web::test]
+async fn test_delete_keeps_content_in_the_trash_only() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::write(format!("{}/note.md", content_path), "note").unwrap();
+    let trash_dir = TempDir::new().unwrap();
+    let trash_path = trash_dir.path().to_str().unwrap();
+    let mut state = TestApp::with_content(content_path);
+    state.config.trash_path = trash_path.to_string();
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    // Deleted content is kept by the trash only, not snapshotted again into history
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/delete")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "path": "note.md" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let deleted = list_revisions(&state.config.history_path(), Some("note.md")).unwrap().remove(0);
+    assert_eq!(deleted.action, RevisionAction::Delete);
+    assert!(!deleted.has_snapshot);
+    assert_eq!(list_trash(&state.config.trash_path).unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_upload_requires_if_match_to_overwrite() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+    std::fs::write(format!("{}/blog/post.md", content_path), "original").unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = upload_request(&state, "blog", &[("post.md", "uploaded"), ("new.md", "new")]).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::PRECONDITION_REQUIRED);
+    let req = upload_request(&state, "blog", &[("post.md", "uploaded")])
+        .insert_header(("If-Match", content_etag(b"stale")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+    // Refused uploads leave neither the new file nor temp files behind
+    let names: Vec<String> = std::fs::read_dir(content_dir.path().join("blog")).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(names, vec!["post.md".to_string()]);
+    assert_eq!(std::fs::read_to_string(content_dir.path().join("blog/post.md")).unwrap(), "original");
+
+    let req = upload_request(&state, "blog", &[("post.md", "uploaded")])
+        .insert_header(("If-Match", "*"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(std::fs::read_to_string(content_dir.path().join("blog/post.md")).unwrap(), "uploaded");
+}
+
+#[actix_web::test]
+async fn test_restoring_history_requires_if_match() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::write(format!("{}/note.md", content_path), "original").unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let req = save_note(&state, "edited", Some(&content_etag(b"original"))).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let baseline = list_revisions(&state.config.history_path(), Some("note.md")).unwrap().pop().unwrap();
+
+    let restore = |if_match: Option<&str>| {
+        let mut req = test::TestRequest::post()
+            .uri("/api/admin/history/restore")
+            .insert_header(state.auth("admin"))
+            .set_json(serde_json::json!({ "revision_id": baseline.id }));
+        if let Some(if_match) = if_match {
+            req = req.insert_header(("If-Match", if_match.to_string()));
+        }
+        req.to_request()
+    };
+    assert_eq!(test::call_service(&app, restore(None)).await.status(), StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(test::call_service(&app, restore(Some(&content_etag(b"original")))).await.status(), StatusCode::CONFLICT);
+    assert_eq!(std::fs::read_to_string(content_dir.path().join("note.md")).unwrap(), "edited");
+
+    let resp = test::call_service(&app, restore(Some(&content_etag(b"edited")))).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(std::fs::read_to_string(content_dir.path().join("note.md")).unwrap(), "original");
+}
+
+/// A save of `note.md` as `content`, with an `If-Match` header when `etag` is given
+fn save_note(state: &TestApp, content: &str, etag: Option<&str>) -> test::TestRequest {
+    let mut req = test::TestRequest::post()
+        .uri("/api/admin/files/save/note.md")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "content": content }));
+    if let Some(etag) = etag {
+        req = req.insert_header(("If-Match", etag.to_string()));
+    }
+    req
+}
+
+#[actix_web::test]
+async fn test_read_returns_the_content_etag() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::write(format!("{}/note.md", content_path), "original").unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/files/read/note.md")
+        .insert_header(state.auth("admin"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("ETag").unwrap().to_str().unwrap(), content_etag(b"original"));
+}
+
+#[actix_web::test]
+async fn test_save_requires_if_match_for_existing_files() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::write(format!("{}/note.md", content_path), "original").unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, save_note(&state, "blind write", None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(std::fs::read_to_string(format!("{}/note.md", content_path)).unwrap(), "original");
+}
+
+#[actix_web::test]
+async fn test_save_with_stale_etag_conflicts() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::write(format!("{}/note.md", content_path), "original").unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let etag = content_etag(b"original");
+
+    let resp = test::call_service(&app, save_note(&state, "first tab", Some(&etag)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // A second tab still holding the old ETag gets a conflict with the current version
+    let resp = test::call_service(&app, save_note(&state, "second tab", Some(&etag)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["content"], "first tab");
+    assert_eq!(body["data"]["etag"], content_etag(b"first tab"));
+    assert_eq!(std::fs::read_to_string(format!("{}/note.md", content_path)).unwrap(), "first tab");
+}
+
+#[actix_web::test]
+async fn test_logout_revokes_the_token() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let auth = state.auth("admin");
+
+    let req = test::TestRequest::get().uri("/api/auth/verify").insert_header(auth.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post().uri("/api/auth/logout").insert_header(auth.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/api/auth/verify").insert_header(auth).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn refresh_request(token: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .cookie(actix_web::cookie::Cookie::new(REFRESH_COOKIE_NAME, token.to_string()))
+}
+
+#[actix_web::test]
+async fn test_refresh_rotates_the_refresh_cookie() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (original, _) = state.refresh_tokens.issue("admin").unwrap();
+
+    // Refreshing issues an access token and rotates the HttpOnly refresh cookie
+    let resp = test::call_service(&app, refresh_request(&original).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let cookie = resp.response().cookies().find(|c| c.name() == REFRESH_COOKIE_NAME).unwrap();
+    assert!(cookie.http_only().unwrap_or(false));
+    assert_eq!(cookie.path(), Some("/api/auth"));
+    assert_ne!(cookie.value(), original);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(verify_jwt_token(&state.config.jwt_keys, &state.revocations, body["data"]["token"].as_str().unwrap()).is_ok());
+}
+
+#[actix_web::test]
+async fn test_refresh_token_reuse_revokes_the_family() {
+    let mut state = TestApp::new(create_test_config());
+    state.refresh_tokens = web::Data::new(RefreshTokenStore::in_memory().with_reuse_grace_seconds(0));
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (original, _) = state.refresh_tokens.issue("admin").unwrap();
+
+    let resp = test::call_service(&app, refresh_request(&original).to_request()).await;
+    let rotated = resp.response().cookies().find(|c| c.name() == REFRESH_COOKIE_NAME).unwrap().value().to_string();
+
+    // Replaying the old token is detected and revokes the whole family
+    let resp = test::call_service(&app, refresh_request(&original).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, refresh_request(&rotated).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_refresh_requires_the_refresh_cookie() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post().uri("/api/auth/refresh").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_refresh_storage_failure_is_a_server_error() {
+    let data_dir = TempDir::new().unwrap();
+    let data_path = data_dir.path().to_str().unwrap();
+    let store_path = format!("{}/refresh_tokens.json", data_path);
+    let mut state = TestApp::new(create_test_config());
+    state.refresh_tokens = web::Data::new(RefreshTokenStore::load(&store_path).unwrap());
+    let (token, _) = state.refresh_tokens.issue("admin").unwrap();
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    // Failing to persist the rotation is a server error, not a rejected token
+    std::fs::remove_file(&store_path).unwrap();
+    std::fs::create_dir(&store_path).unwrap();
+    let resp = test::call_service(&app, refresh_request(&token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "storage_error");
+}
+
+#[actix_web::test]
+async fn test_throttled_login_answers_429_with_retry_after() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    for _ in 0..4 {
+        state.login_throttle.record_failure("unknown", "nobody");
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(serde_json::json!({ "username": "nobody", "password": "wrong" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().contains_key("retry-after"));
+}
+
+/// Enroll `admin` in two-factor login, returning the recovery codes
+fn enable_two_factor(two_factor: &TwoFactorStore) -> Vec<String> {
+    let (secret, _) = two_factor.begin_enrollment("admin").unwrap();
+    let code = totp_code(&secret, totp_step(chrono::Utc::now().timestamp())).unwrap();
+    two_factor.confirm_enrollment("admin", &code).unwrap()
+}
+
+fn password_login() -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(serde_json::json!({ "username": "admin", "password": "admin123" }))
+}
+
+fn verify_two_factor(challenge: &str, code: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/auth/2fa/verify")
+        .set_json(serde_json::json!({ "challenge_token": challenge, "code": code }))
+}
+
+#[actix_web::test]
+async fn test_two_factor_login_answers_a_challenge() {
+    let state = TestApp::new(create_test_config());
+    enable_two_factor(&state.two_factor);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    // A correct password yields a challenge instead of a token
+    let resp = test::call_service(&app, password_login().to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["two_factor_required"], true);
+    assert!(body["data"]["token"].is_null());
+
+    let challenge = body["data"]["challenge_token"].as_str().unwrap();
+    let resp = test::call_service(&app, verify_two_factor(challenge, "not-a-code").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_recovery_code_completes_the_login_once() {
+    let state = TestApp::new(create_test_config());
+    let recovery_codes = enable_two_factor(&state.two_factor);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let body: serde_json::Value = test::call_and_read_body_json(&app, password_login().to_request()).await;
+    let challenge = body["data"]["challenge_token"].as_str().unwrap().to_string();
+
+    let resp = test::call_service(&app, verify_two_factor(&challenge, &recovery_codes[0]).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["data"]["token"].is_string());
+    assert_eq!(state.two_factor.remaining_recovery_codes("admin"), Some(9));
+
+    // The challenge and the recovery code are both single-use
+    let resp = test::call_service(&app, verify_two_factor(&challenge, &recovery_codes[0]).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert!(!state.two_factor.verify_code("admin", &recovery_codes[0]).unwrap());
+}
+
+#[actix_web::test]
+async fn test_password_relogin_keeps_two_factor_failures() {
+    let state = TestApp::new(create_test_config());
+    enable_two_factor(&state.two_factor);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let verify = |challenge: &str| verify_two_factor(challenge, "000000").to_request();
+
+    // Three wrong codes are free, then a fresh challenge from the password
+    let body: serde_json::Value = test::call_and_read_body_json(&app, password_login().to_request()).await;
+    let challenge = body["data"]["challenge_token"].as_str().unwrap().to_string();
+    for _ in 0..3 {
+        assert_eq!(test::call_service(&app, verify(&challenge)).await.status(), StatusCode::UNAUTHORIZED);
+    }
+    let body: serde_json::Value = test::call_and_read_body_json(&app, password_login().to_request()).await;
+    let challenge = body["data"]["challenge_token"].as_str().unwrap().to_string();
+
+    // The fourth wrong code still counts as the fourth and starts the backoff
+    let resp = test::call_service(&app, verify(&challenge)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(test::call_service(&app, verify(&challenge)).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(test::call_service(&app, password_login().to_request()).await.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+/// A content root with a note and the protected GitHub config
+fn create_role_test_content() -> TempDir {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/github", content_path)).unwrap();
+    std::fs::write(format!("{}/github/config.yaml", content_path), "repositories: []").unwrap();
+    std::fs::write(format!("{}/note.md", content_path), "note").unwrap();
+    content_dir
+}
+
+fn save_as(state: &TestApp, role: &str, path: &str) -> test::TestRequest {
+    let current = std::fs::read(format!("{}/{}", state.config.content_path, path)).unwrap();
+    test::TestRequest::post()
+        .uri(&format!("/api/admin/files/save/{}", path))
+        .insert_header(state.auth(role))
+        .insert_header(("If-Match", content_etag(&current)))
+        .set_json(serde_json::json!({ "content": "changed" }))
+}
+
+#[actix_web::test]
+async fn test_viewers_can_read_but_not_write() {
+    let content_dir = create_role_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/admin/files/list/").insert_header(state.auth("viewer")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let resp = test::call_service(&app, save_as(&state, "viewer", "note.md").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_editors_edit_content_but_not_the_github_config() {
+    let content_dir = create_role_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, save_as(&state, "editor", "note.md").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, save_as(&state, "editor", "github/config.yaml").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // Renaming the folder holding the config would modify it too
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/rename")
+        .insert_header(state.auth("editor"))
+        .set_json(serde_json::json!({ "old_path": "github", "new_path": "gh" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_editors_cannot_delete() {
+    let content_dir = create_role_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/delete")
+        .insert_header(state.auth("editor"))
+        .set_json(serde_json::json!({ "path": "note.md" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    assert!(std::path::Path::new(&format!("{}/note.md", content_path)).exists());
+}
+
+#[actix_web::test]
+async fn test_admins_can_do_everything_and_unknown_roles_nothing() {
+    let content_dir = create_role_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, save_as(&state, "admin", "github/config.yaml").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/api/admin/files/list/").insert_header(state.auth("intern")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+/// A route that performs no checks of its own, to see what the scope guard lets through
+async fn whoami(req: actix_web::HttpRequest) -> actix_web::HttpResponse {
+    use actix_web::HttpMessage;
+    let user = req.extensions().get::<Claims>().map(|claims| claims.sub.clone());
+    actix_web::HttpResponse::Ok().json(ApiResponse::success(user))
+}
+
+fn configure_whoami(state: &TestApp, cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::Data::new(state.config.clone()))
+        .app_data(state.revocations.clone())
+        .service(
+            web::scope("/api/admin")
+                .wrap(RequireAuth)
+                .route("/whoami", web::get().to(whoami))
+        );
+}
+
+#[actix_web::test]
+async fn test_require_auth_rejects_missing_and_invalid_tokens() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| configure_whoami(&state, cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/admin/whoami").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/whoami")
+        .insert_header(("Authorization", "Bearer not-a-token"))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_require_auth_hands_the_claims_to_handlers() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| configure_whoami(&state, cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/admin/whoami").insert_header(state.auth("editor")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"], "editor");
+}
+
+/// A `content:read` API token's bearer header and id
+fn create_read_only_api_token(state: &TestApp) -> ((&'static str, String), String) {
+    let (secret, api_token) = state.api_tokens.create("ci", &["content:read".to_string()], "admin", None).unwrap();
+    (("Authorization", format!("Bearer {}", secret)), api_token.id)
+}
+
+#[actix_web::test]
+async fn test_api_tokens_reject_unknown_scopes() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/tokens")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "name": "ci", "scopes": ["root"] }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_api_token_secrets_are_shown_only_once() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/tokens")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "name": "ci", "scopes": ["content:read"], "expires_in_days": 30 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let secret = body["data"]["token"].as_str().unwrap().to_string();
+    assert!(secret.starts_with(API_TOKEN_PREFIX));
+
+    // The listing never exposes secrets
+    let req = test::TestRequest::get().uri("/api/admin/tokens").insert_header(state.auth("admin")).to_request();
+    let body: serde_json::Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(body["data"][0]["name"], "ci");
+    assert!(!body.to_string().contains(&secret));
+}
+
+#[actix_web::test]
+async fn test_api_tokens_are_limited_to_their_scopes() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (token_auth, _) = create_read_only_api_token(&state);
+
+    let req = test::TestRequest::get().uri("/api/admin/files/list/").insert_header(token_auth.clone()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/save/new.md")
+        .insert_header(token_auth.clone())
+        .set_json(serde_json::json!({ "content": "from ci" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    let req = test::TestRequest::get().uri("/api/admin/tokens").insert_header(token_auth).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_revoked_api_tokens_stop_working() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (token_auth, id) = create_read_only_api_token(&state);
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/tokens/revoke")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "id": id }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let req = test::TestRequest::get().uri("/api/admin/files/list/").insert_header(token_auth).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_delete_scoped_api_tokens_cannot_remove_protected_paths() {
+    let content_dir = create_role_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let trash_dir = TempDir::new().unwrap();
+    let trash_path = trash_dir.path().to_str().unwrap();
+    let mut state = TestApp::with_content(content_path);
+    state.config.trash_path = trash_path.to_string();
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (secret, _) = state.api_tokens.create("ci", &["content:delete".to_string()], "admin", None).unwrap();
+    let token_auth = ("Authorization", format!("Bearer {}", secret));
+
+    for path in ["github/config.yaml", "github"] {
+        let req = test::TestRequest::post()
+            .uri("/api/admin/files/delete")
+            .insert_header(token_auth.clone())
+            .set_json(serde_json::json!({ "path": path }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+    assert!(content_dir.path().join("github/config.yaml").exists());
+
+    // An admin trashes the config; the token still cannot purge it for good
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/delete")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "path": "github" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    let id = list_trash(&state.config.trash_path).unwrap().remove(0).id;
+    for body in [serde_json::json!({ "id": id }), serde_json::json!({})] {
+        let req = test::TestRequest::post()
+            .uri("/api/admin/trash/purge")
+            .insert_header(token_auth.clone())
+            .set_json(body)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
+    assert_eq!(list_trash(&state.config.trash_path).unwrap().len(), 1);
+}
+
+/// A test app with cookie sessions enabled
+fn cookie_session_app(content_path: &str) -> TestApp {
+    let mut state = TestApp::with_content(content_path);
+    state.config.cookie_sessions = true;
+    state
+}
+
+/// An admin access token cookie and the CSRF token that goes with it
+fn cookie_session(state: &TestApp) -> (actix_web::cookie::Cookie<'static>, String) {
+    let (token, _, csrf) = create_session_token(&state.config.jwt_keys, "admin", "admin", 15).unwrap();
+    (actix_web::cookie::Cookie::new(ACCESS_COOKIE_NAME, token), csrf)
+}
+
+fn with_csrf(req: test::TestRequest, csrf: Option<&str>) -> test::TestRequest {
+    match csrf {
+        Some(csrf) => req.insert_header((CSRF_HEADER_NAME, csrf.to_string())),
+        None => req,
+    }
+}
+
+#[actix_web::test]
+async fn test_cookie_sessions_hand_out_access_and_csrf_cookies() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = cookie_session_app(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (refresh_token, _) = state.refresh_tokens.issue("admin").unwrap();
+
+    let resp = test::call_service(&app, refresh_request(&refresh_token).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let cookie = |name: &str| resp.response().cookies().find(|c| c.name() == name).unwrap().into_owned();
+    assert!(cookie(ACCESS_COOKIE_NAME).http_only().unwrap_or(false));
+    assert!(!cookie(CSRF_COOKIE_NAME).http_only().unwrap_or(false));
+}
+
+#[actix_web::test]
+async fn test_cookie_session_reads_need_only_the_cookie() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = cookie_session_app(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (session, _) = cookie_session(&state);
+
+    let req = test::TestRequest::get().uri("/api/admin/files/list/").cookie(session).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_cookie_session_writes_need_the_csrf_header() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = cookie_session_app(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (session, csrf) = cookie_session(&state);
+    let create = |csrf: Option<&str>| {
+        let req = test::TestRequest::post()
+            .uri("/api/admin/files/create-folder")
+            .cookie(session.clone())
+            .set_json(serde_json::json!({ "path": "a" }));
+        with_csrf(req, csrf).to_request()
+    };
+
+    assert_eq!(test::call_service(&app, create(None)).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(test::call_service(&app, create(Some("forged"))).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(test::call_service(&app, create(Some(&csrf))).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_bearer_tokens_need_no_csrf_header() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = cookie_session_app(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    // Scripts keep working with cookie sessions enabled
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/create-folder")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "path": "b" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_cookie_session_logout_needs_the_csrf_header() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = cookie_session_app(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    let (session, csrf) = cookie_session(&state);
+    let logout = |csrf: Option<&str>| {
+        with_csrf(test::TestRequest::post().uri("/api/auth/logout").cookie(session.clone()), csrf).to_request()
+    };
+    let list = || test::TestRequest::get().uri("/api/admin/files/list/").cookie(session.clone()).to_request();
+
+    // Refused without the header, leaving the session usable
+    assert_eq!(test::call_service(&app, logout(None)).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(test::call_service(&app, list()).await.status(), StatusCode::OK);
+
+    // With it the access token is revoked
+    assert_eq!(test::call_service(&app, logout(Some(&csrf))).await.status(), StatusCode::OK);
+    assert_eq!(test::call_service(&app, list()).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn create_folder_as(state: &TestApp, role: &str, path: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/api/admin/files/create-folder")
+        .insert_header(state.auth(role))
+        .set_json(serde_json::json!({ "path": path }))
+}
+
+#[actix_web::test]
+async fn test_audit_log_records_successful_actions() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, create_folder_as(&state, "admin", "drafts").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let events = state.audit_log.query(&AuditQuery::default()).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, "create_folder");
+    assert_eq!(events[0].user, "admin");
+    assert_eq!(events[0].path.as_deref(), Some("drafts"));
+    assert_eq!(events[0].result, AuditResult::Success);
+}
+
+#[actix_web::test]
+async fn test_audit_log_records_two_factor_enrollment() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let enroll = test::TestRequest::post().uri("/api/admin/2fa/enroll").insert_header(state.auth("admin"));
+    assert_eq!(test::call_service(&app, enroll.to_request()).await.status(), StatusCode::OK);
+    let confirm = test::TestRequest::post()
+        .uri("/api/admin/2fa/confirm")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "code": "not-a-code" }));
+    assert_eq!(test::call_service(&app, confirm.to_request()).await.status(), StatusCode::BAD_REQUEST);
+
+    let events = state.audit_log.query(&AuditQuery::default()).unwrap();
+    let actions: Vec<_> = events.iter().map(|event| (event.action.as_str(), event.user.as_str())).collect();
+    assert_eq!(actions, vec![("confirm_two_factor", "admin"), ("enroll_two_factor", "admin")]);
+    assert_eq!(events[1].result, AuditResult::Success);
+    assert_ne!(events[0].result, AuditResult::Success);
+}
+
+#[actix_web::test]
+async fn test_audit_log_records_denied_actions() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/delete")
+        .insert_header(state.auth("viewer"))
+        .set_json(serde_json::json!({ "path": "drafts" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let events = state.audit_log.query(&AuditQuery::default()).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, "delete");
+    assert_eq!(events[0].user, "viewer");
+    assert_eq!(events[0].result, AuditResult::Denied);
+}
+
+#[actix_web::test]
+async fn test_audit_log_records_failed_logins() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(serde_json::json!({ "username": "admin", "password": "wrong" }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let events = state.audit_log.query(&AuditQuery::default()).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, "login");
+    assert_eq!(events[0].user, "admin");
+    assert_eq!(events[0].result, AuditResult::Denied);
+}
+
+#[actix_web::test]
+async fn test_audit_log_queries_are_filtered_and_admin_only() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+    test::call_service(&app, create_folder_as(&state, "admin", "drafts").to_request()).await;
+    test::call_service(&app, create_folder_as(&state, "viewer", "notes").to_request()).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/audit?user=viewer&result=denied")
+        .insert_header(state.auth("admin"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    assert_eq!(body["data"][0]["path"], "notes");
+
+    let req = test::TestRequest::get().uri("/api/admin/audit").insert_header(state.auth("editor")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_audit_log_records_logging_out_every_session() {
+    let state = TestApp::new(create_test_config());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post().uri("/api/admin/sessions/revoke-all").insert_header(state.auth("admin")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let events = state.audit_log.query(&AuditQuery { action: Some("revoke_all_sessions".to_string()), ..Default::default() }).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].user, "admin");
+    assert_eq!(events[0].result, AuditResult::Success);
+}
+
+/// The API behind the CORS middleware for two origins, and a page outside it
+fn configure_cors(cfg: &mut web::ServiceConfig) {
+    let mut config = create_test_config();
+    config.cors_allowed_origins = vec!["http://localhost:3000".to_string(), "https://admin.example.com".to_string()];
+    cfg.service(
+        web::scope("/api")
+            .wrap(api_cors(&config))
+            .route("/health", web::get().to(health_check))
+    )
+    .route("/index.html", web::get().to(health_check));
+}
+
+#[actix_web::test]
+async fn test_cors_preflights_are_answered_and_cacheable() {
+    let app = test::init_service(App::new().configure(configure_cors)).await;
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/api/health")
+        .insert_header(("Origin", "https://admin.example.com"))
+        .insert_header(("Access-Control-Request-Method", "POST"))
+        .insert_header(("Access-Control-Request-Headers", "content-type, x-csrf-token"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let header = |name: &str| resp.headers().get(name).map(|value| value.to_str().unwrap().to_string());
+    assert_eq!(header("access-control-allow-origin").as_deref(), Some("https://admin.example.com"));
+    assert_eq!(header("access-control-allow-credentials").as_deref(), Some("true"));
+    assert_eq!(header("access-control-max-age").as_deref(), Some("3600"));
+}
+
+#[actix_web::test]
+async fn test_cors_exposes_the_etag_and_request_id() {
+    let app = test::init_service(App::new().configure(configure_cors)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/health")
+        .insert_header(("Origin", "https://admin.example.com"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let exposed = resp.headers().get("access-control-expose-headers").unwrap().to_str().unwrap().to_lowercase();
+    assert!(exposed.contains("etag"));
+    assert!(exposed.contains(REQUEST_ID_HEADER));
+}
+
+#[actix_web::test]
+async fn test_cors_grants_nothing_to_other_origins() {
+    let app = test::init_service(App::new().configure(configure_cors)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/health")
+        .insert_header(("Origin", "https://evil.example.com"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get("access-control-allow-origin").is_none());
+}
+
+#[actix_web::test]
+async fn test_cors_headers_stay_off_routes_outside_the_api() {
+    let app = test::init_service(App::new().configure(configure_cors)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/index.html")
+        .insert_header(("Origin", "http://localhost:3000"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get("access-control-allow-origin").is_none());
+}
+
+/// Hashes of a page with one inline script and one inline style
+fn page_hashes() -> InlineHashes {
+    InlineHashes {
+        scripts: inline_element_hashes("<script>console.log('hi')</script>", "script"),
+        styles: inline_element_hashes("<style>body{color:red}</style>", "style"),
+    }
+}
+
+fn configure_pages(cfg: &mut web::ServiceConfig) {
+    cfg.route("/", web::get().to(health_check))
+        .route("/knockknock/", web::get().to(health_check))
+        .route("/api/admin/audit", web::get().to(health_check));
+}
+
+fn header_of(resp: &actix_web::dev::ServiceResponse, name: &str) -> String {
+    resp.headers().get(name).unwrap().to_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn test_public_pages_allow_only_hashed_inline_scripts() {
+    let mut config = create_test_config();
+    config.hsts_max_age = 31_536_000;
+    let hashes = page_hashes();
+    let app = test::init_service(App::new().wrap(SecurityHeaders::new(&config, &hashes)).configure(configure_pages)).await;
+
+    // Public pages allow the CDN and inline styles, but only hashed inline scripts
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+    let csp = header_of(&resp, "content-security-policy");
+    assert!(csp.contains(&hashes.scripts[0]));
+    assert!(csp.contains("https://cdnjs.cloudflare.com"));
+    assert!(csp.contains("frame-ancestors 'self'"));
+    assert_eq!(header_of(&resp, "x-content-type-options"), "nosniff");
+    assert_eq!(header_of(&resp, "strict-transport-security"), "max-age=31536000; includeSubDomains");
+    assert_eq!(header_of(&resp, "referrer-policy"), "strict-origin-when-cross-origin");
+}
+
+#[actix_web::test]
+async fn test_public_pages_allow_youtube_embeds() {
+    let app = test::init_service(
+        App::new().wrap(SecurityHeaders::new(&create_test_config(), &page_hashes())).configure(configure_pages)
+    ).await;
+
+    // The iframe the youtube shortcode emits is allowed to load on public pages
+    let embed = expand_shortcodes("{{< youtube dQw4w9WgXcQ >}}", &ShortcodeContext::default());
+    let src = embed.split("src=\"").nth(1).unwrap().split('"').next().unwrap();
+    let origin = src.splitn(4, '/').take(3).collect::<Vec<_>>().join("/");
+    assert_eq!(origin, "https://www.youtube-nocookie.com");
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+    let csp = header_of(&resp, "content-security-policy");
+    let frame_src = csp.split(';').map(str::trim).find(|d| d.starts_with("frame-src")).unwrap();
+    assert!(frame_src.split_whitespace().any(|source| source == origin));
+}
+
+#[actix_web::test]
+async fn test_login_page_gets_the_strict_policy() {
+    let hashes = page_hashes();
+    let app = test::init_service(
+        App::new().wrap(SecurityHeaders::new(&create_test_config(), &hashes)).configure(configure_pages)
+    ).await;
+
+    // No third-party sources, no unsafe-inline and no framing
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/knockknock/").to_request()).await;
+    let csp = header_of(&resp, "content-security-policy");
+    assert!(csp.contains(&hashes.styles[0]));
+    assert!(!csp.contains("unsafe-inline"));
+    assert!(!csp.contains("cdnjs"));
+    assert!(csp.contains("frame-ancestors 'none'"));
+    assert_eq!(header_of(&resp, "referrer-policy"), "no-referrer");
+    assert_eq!(header_of(&resp, "cache-control"), "no-store");
+}
+
+#[actix_web::test]
+async fn test_admin_api_allows_nothing_to_load() {
+    let app = test::init_service(
+        App::new().wrap(SecurityHeaders::new(&create_test_config(), &page_hashes())).configure(configure_pages)
+    ).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/api/admin/audit").to_request()).await;
+    assert_eq!(header_of(&resp, "content-security-policy"), "default-src 'none'; frame-ancestors 'none'");
+    assert_eq!(header_of(&resp, "x-frame-options"), "DENY");
+    assert_eq!(header_of(&resp, "cache-control"), "no-store");
+}
+
+#[actix_web::test]
+async fn test_build_writes_the_api_bodies() {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/github", content_path)).unwrap();
+    std::fs::write(format!("{}/github/config.yaml", content_path), "repositories: []\n").unwrap();
+    let mut config = create_test_config();
+    config.content_path = content_path.to_string();
+    let date = chrono::NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+    scaffold_content(content_path, "blog", "Hello: Rust & YAML!", date).unwrap();
+
+    // `build` writes the API bodies the server would return
+    let out = TempDir::new().unwrap();
+    let github_cache = Mutex::new(HashMap::new());
+    export_site(&config, &out.path().join("no-frontend"), out.as_ref(), "https://example.com", &github_cache).await.unwrap();
+    let list: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(out.path().join("api/content/blog.json")).unwrap()).unwrap();
+    assert_eq!(list["success"], true);
+    assert_eq!(list["data"][0]["slug"], "hello-rust-yaml");
+    assert!(out.path().join("api/content/blog/hello-rust-yaml.json").exists());
+    assert!(out.path().join("api/content/tags.json").exists());
+}
+
+/// Directories of a site exported by `export_test_site`, removed when dropped
+struct ExportedSite {
+    _content_path: TempDir,
+    _frontend: TempDir,
+    out: std::path::PathBuf,
+}
+
+/// Export two posts and a built frontend with public, admin and login pages
+async fn export_test_site() -> ExportedSite {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/github", content_path)).unwrap();
+    std::fs::write(format!("{}/github/config.yaml", content_path), "repositories: []\n").unwrap();
+    let mut config = create_test_config();
+    config.content_path = content_path.to_string();
+    for (title, date) in [("Older <Post>", "2025-01-01"), ("Newer Post", "2025-02-01")] {
+        let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        scaffold_content(content_path, "blog", title, date).unwrap();
+    }
+
+    let frontend = TempDir::new().unwrap();
+    std::fs::create_dir_all(frontend.path().join("assets")).unwrap();
+    std::fs::create_dir_all(frontend.path().join("node_modules/pkg")).unwrap();
+    std::fs::write(frontend.path().join("index.html"), "<html></html>").unwrap();
+    std::fs::write(frontend.path().join("about.html"), "<html></html>").unwrap();
+    std::fs::write(frontend.path().join("admin.html"), "<html></html>").unwrap();
+    std::fs::write(frontend.path().join("knockknock.html"), "<html></html>").unwrap();
+    std::fs::write(frontend.path().join("assets/app.js"), "").unwrap();
+    std::fs::write(frontend.path().join("node_modules/pkg/index.js"), "").unwrap();
+
+    // The output may live inside the frontend without being copied into itself
+    let out = frontend.path().join("dist");
+    let github_cache = Mutex::new(HashMap::new());
+    export_site(&config, frontend.as_ref(), &out, "https://example.com/", &github_cache).await.unwrap();
+    ExportedSite { _content_path: content_dir, _frontend: frontend, out }
+}
+
+#[actix_web::test]
+async fn test_export_copies_public_pages_only() {
+    let site = export_test_site().await;
+    let out = &site.out;
+
+    assert!(out.join("index.html").exists());
+    assert!(out.join("assets/app.js").exists());
+    assert!(!out.join("admin.html").exists());
+    assert!(!out.join("knockknock.html").exists());
+    assert!(!out.join("node_modules").exists());
+    assert!(!out.join("dist").exists());
+}
+
+#[actix_web::test]
+async fn test_export_writes_the_github_projects() {
+    let site = export_test_site().await;
+
+    let projects: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(site.out.join("api/github/projects.json")).unwrap()).unwrap();
+    assert_eq!(projects["data"], serde_json::json!([]));
+}
+
+#[actix_web::test]
+async fn test_export_feeds_list_the_newest_post_first() {
+    let site = export_test_site().await;
+
+    let rss = std::fs::read_to_string(site.out.join("rss.xml")).unwrap();
+    assert!(rss.find("Newer Post").unwrap() < rss.find("Older &lt;Post&gt;").unwrap());
+    assert!(rss.contains("<pubDate>Sat, 1 Feb 2025 00:00:00 +0000</pubDate>"));
+    assert!(rss.contains("<guid isPermaLink=\"false\">https://example.com/#blog/newer-post</guid>"));
+    let atom = std::fs::read_to_string(site.out.join("atom.xml")).unwrap();
+    assert!(atom.contains("<updated>2025-02-01T00:00:00+00:00</updated>"));
+}
+
+#[actix_web::test]
+async fn test_export_sitemap_lists_public_pages() {
+    let site = export_test_site().await;
+
+    // The home page is dated by the newest content
+    let sitemap = std::fs::read_to_string(site.out.join("sitemap.xml")).unwrap();
+    assert!(sitemap.contains("<loc>https://example.com/</loc>\n    <lastmod>2025-02-01</lastmod>"));
+    assert!(sitemap.contains("<loc>https://example.com/about.html</loc>"));
+    assert!(!sitemap.contains("admin"));
+}
+
+/// A content root configuring one GitHub repository
+fn create_readiness_test_content() -> TempDir {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/github", content_path)).unwrap();
+    std::fs::write(
+        format!("{}/github/config.yaml", content_path),
+        "repositories:\n  - owner: octo\n    repo: widget\n    display_name: Widget\n",
+    ).unwrap();
+    content_dir
+}
+
+#[actix_web::test]
+async fn test_liveness_is_up_before_the_cache_is_warm() {
+    let content_dir = create_readiness_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/api/health/live").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_readiness_waits_for_the_cache_warmup() {
+    let content_dir = create_readiness_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/api/health/ready").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["ready"], false);
+    assert!(body["data"]["checks"][2]["detail"].as_str().unwrap().contains("0 of 1"));
+
+    state.cache_warmup.finish();
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/api/health/ready").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+/// A blog with a valid post and a post with broken frontmatter
+fn create_metrics_test_content() -> TempDir {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/blog", content_path)).unwrap();
+    std::fs::write(
+        format!("{}/blog/hello.md", content_path),
+        "---\ntitle: \"Hello\"\ndate: \"2025-01-01\"\ntags: []\ndescription: \"\"\n---\nbody",
+    ).unwrap();
+    std::fs::write(format!("{}/blog/broken.md", content_path), "---\ntitle: [unclosed\n---\nbody").unwrap();
+    content_dir
+}
+
+fn scrape_request() -> test::TestRequest {
+    test::TestRequest::get().uri("/metrics").insert_header(("Authorization", "Bearer scrape-secret"))
+}
+
+#[actix_web::test]
+async fn test_metrics_need_the_scrape_token() {
+    let mut state = TestApp::new(create_test_config());
+    state.config.metrics_token = Some("scrape-secret".to_string());
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, scrape_request().to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_metrics_label_routes_by_pattern() {
+    let content_dir = create_metrics_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().wrap(RequestMetrics).configure(|cfg| state.configure(cfg))).await;
+    for uri in ["/api/content/blog/hello", "/no/such/page"] {
+        test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+    }
+
+    let body = String::from_utf8(test::call_and_read_body(&app, scrape_request().to_request()).await.to_vec()).unwrap();
+    assert!(body.contains(r#"portfolio_http_requests_total{method="GET",route="/api/content/{category}/{slug}",status="200"}"#));
+    assert!(body.contains(r#"route="unmatched",status="404""#));
+    assert!(!body.contains("/api/content/blog/hello"));
+    assert!(body.contains("portfolio_http_request_duration_seconds_bucket"));
+}
+
+#[actix_web::test]
+async fn test_metrics_count_cache_lookups_and_parse_errors() {
+    let content_dir = create_metrics_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    // A miss then a hit on the content cache, and a parse error
+    for uri in ["/api/content/blog/hello", "/api/content/blog/hello", "/api/content/blog/broken"] {
+        test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+    }
+
+    let body = String::from_utf8(test::call_and_read_body(&app, scrape_request().to_request()).await.to_vec()).unwrap();
+    assert!(body.contains(r#"portfolio_cache_lookups_total{cache="content",result="hit"}"#));
+    assert!(body.contains(r#"portfolio_cache_lookups_total{cache="content",result="miss"}"#));
+    let parse_errors = body
+        .lines()
+        .find_map(|line| line.strip_prefix("portfolio_markdown_parse_errors_total "))
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap();
+    assert!(parse_errors >= 1);
+}
+
+/// Log sink shared with a test subscriber
+#[derive(Clone, Default)]
+struct CapturedLogs(std::sync::Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A route logging a line and answering with the request id it sees
+fn configure_work(cfg: &mut web::ServiceConfig) {
+    cfg.route("/work", web::get().to(|req: actix_web::HttpRequest| async move {
+        tracing::warn!(step = "inside", "handler log line");
+        actix_web::HttpResponse::Ok().body(request_id(&req).unwrap_or_default())
+    }));
+}
+
+#[actix_web::test]
+async fn test_generated_request_ids_reach_handlers_and_responses() {
+    let app = test::init_service(App::new().wrap(RequestId).configure(configure_work)).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/work").to_request()).await;
+    let generated = resp.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+    assert_eq!(generated.len(), 36);
+    assert_eq!(test::read_body(resp).await, generated.as_bytes());
+}
+
+#[actix_web::test]
+async fn test_safe_request_ids_from_proxies_are_propagated() {
+    let app = test::init_service(App::new().wrap(RequestId).configure(configure_work)).await;
+
+    let req = test::TestRequest::get().uri("/work").insert_header(("X-Request-Id", "edge-42.a_b")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("x-request-id").unwrap(), "edge-42.a_b");
+    let req = test::TestRequest::get().uri("/work").insert_header(("X-Request-Id", "bad id\"")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_ne!(resp.headers().get("x-request-id").unwrap(), "bad id\"");
+}
+
+#[actix_web::test]
+async fn test_json_log_lines_carry_the_request_id() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let _subscriber = tracing::subscriber::set_default(build_subscriber(LogFormat::Json, move || writer.clone()));
+    let app = test::init_service(App::new().wrap(RequestId).configure(configure_work)).await;
+
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/work").to_request()).await;
+    let generated = resp.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+    let req = test::TestRequest::get().uri("/work").insert_header(("X-Request-Id", "edge-42.a_b")).to_request();
+    test::call_service(&app, req).await;
+
+    // Every line is JSON, and handler and completion lines carry the request id
+    let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let for_request = |id: &str| lines
+        .iter()
+        .filter(|line| line["spans"][0]["request_id"] == id)
+        .map(|line| line["message"].as_str().unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(for_request(&generated), vec!["handler log line", "request completed"]);
+    assert_eq!(for_request("edge-42.a_b"), vec!["handler log line", "request completed"]);
+    let handler_line = lines.iter().find(|line| line["message"] == "handler log line").unwrap();
+    assert_eq!(handler_line["level"], "WARN");
+    assert_eq!(handler_line["step"], "inside");
+    assert_eq!(handler_line["spans"][0]["path"], "/work");
+}
+
+/// A blog with broken frontmatter and a folder named like a post
+fn create_error_test_content() -> TempDir {
+    let content_dir = TempDir::new().unwrap();
+    let content_path = content_dir.path().to_str().unwrap();
+    std::fs::create_dir_all(format!("{}/blog/folder.md", content_path)).unwrap();
+    std::fs::write(format!("{}/blog/broken.md", content_path), "---\ntitle: [unclosed\n---\nBody").unwrap();
+    content_dir
+}
+
+/// Status, error code and message of a failed request
+async fn error_of(resp: actix_web::dev::ServiceResponse) -> (StatusCode, String, String) {
+    let status = resp.status();
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["success"], false);
+    (status, body["code"].as_str().unwrap().to_string(), body["message"].as_str().unwrap().to_string())
+}
+
+#[actix_web::test]
+async fn test_validation_errors_keep_their_explanation() {
+    let content_dir = create_error_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/content/secrets/post").to_request();
+    let (status, code, message) = error_of(test::call_service(&app, req).await).await;
+    assert_eq!((status, code.as_str()), (StatusCode::BAD_REQUEST, "invalid_input"));
+    assert!(message.contains("Invalid category parameter"));
+}
+
+#[actix_web::test]
+async fn test_missing_content_does_not_leak_paths() {
+    let content_dir = create_error_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/content/blog/missing").to_request();
+    let (status, code, message) = error_of(test::call_service(&app, req).await).await;
+    assert_eq!((status, code.as_str()), (StatusCode::NOT_FOUND, "not_found"));
+    assert!(!message.contains(content_path));
+}
+
+#[actix_web::test]
+async fn test_malformed_frontmatter_is_invalid_content() {
+    let content_dir = create_error_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/content/blog/broken").to_request();
+    let (status, code, message) = error_of(test::call_service(&app, req).await).await;
+    assert_eq!((status, code.as_str()), (StatusCode::INTERNAL_SERVER_ERROR, "invalid_content"));
+    assert_eq!(message, "Content could not be parsed");
+}
+
+#[actix_web::test]
+async fn test_storage_errors_say_nothing_about_the_file_system() {
+    let content_dir = create_error_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/files/read/blog/folder.md")
+        .insert_header(state.auth("admin"))
+        .to_request();
+    let (status, code, message) = error_of(test::call_service(&app, req).await).await;
+    assert_eq!((status, code.as_str()), (StatusCode::INTERNAL_SERVER_ERROR, "storage_error"));
+    assert!(!message.contains(content_path));
+    assert!(!message.contains("directory"));
+}
+
+#[actix_web::test]
+async fn test_helper_errors_keep_their_status_and_message() {
+    let content_dir = create_error_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/files/rename")
+        .insert_header(state.auth("admin"))
+        .set_json(serde_json::json!({ "old_path": "blog/broken.md", "new_path": "blog/folder.md" }))
+        .to_request();
+    let (status, code, message) = error_of(test::call_service(&app, req).await).await;
+    assert_eq!((status, code.as_str()), (StatusCode::CONFLICT, "conflict"));
+    assert_eq!(message, "Destination already exists");
+}
+
+#[actix_web::test]
+async fn test_missing_authentication_is_unauthorized() {
+    let content_dir = create_error_test_content();
+    let content_path = content_dir.path().to_str().unwrap();
+    let state = TestApp::with_content(content_path);
+    let app = test::init_service(App::new().configure(|cfg| state.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/admin/files/read/blog/broken.md").to_request();
+    let (status, code, _) = error_of(test::call_service(&app, req).await).await;
+    assert_eq!((status, code.as_str()), (StatusCode::UNAUTHORIZED, "unauthorized"));
+}